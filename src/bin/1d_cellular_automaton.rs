@@ -3,6 +3,8 @@ use macroquad::{prelude::*, window};
 use std::time::Duration;
 use std::{cell, thread};
 
+use lbm::rng::CounterRng;
+
 fn update(state: &Vec<(bool, usize)>, rule: i32) -> Vec<(bool, usize)> {
     let n = state.len();
 
@@ -37,12 +39,10 @@ async fn main() {
 
     let mut states = vec![];
 
-    rand::srand(
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs(),
-    );
+    let rng = CounterRng::from_args();
+
+    // every reset draws a new, reproducible initial state
+    let mut generation = 0;
 
     let mut rule = 20;
     let mut probability = 0.5;
@@ -53,16 +53,17 @@ async fn main() {
         if reset {
             states = vec![vec![(false, 0usize); cell_number]];
 
-            probability = rand::gen_range(0.0, 1.0);
+            probability = rng.uniform([generation, 0, 0]);
 
             for i in 0..states[0].len() {
-                if rand::gen_range(0.0, 1.0) <= probability {
+                if rng.uniform([generation, 1, i as u64]) <= probability {
                     states[0][i] = (true, 1);
                 }
             }
 
             rule = 20;
 
+            generation += 1;
             reset = false;
         }
 
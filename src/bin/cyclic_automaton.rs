@@ -1,5 +1,7 @@
 use macroquad::{color::hsl_to_rgb, prelude::*};
 
+use lbm::rng::CounterRng;
+
 const CELL_SIZE: f32 = 8.;
 const STATES: usize = 8;
 
@@ -16,13 +18,10 @@ async fn main() {
 
     let mut change_lattice = vec![false; width * height];
 
-    rand::srand(
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs_f64() as _,
-    );
+    let rng = CounterRng::from_args();
 
+    // every reset draws a new, reproducible initial state
+    let mut generation = 0;
     let mut reset = true;
 
     let mut change_view = false;
@@ -36,12 +35,13 @@ async fn main() {
         if reset {
             for i in 0..width {
                 for j in 0..height {
-                    let state = rand::gen_range(0, STATES);
+                    let state = rng.below([generation, i as u64, j as u64], STATES);
 
                     lattice[width * i + j] = state;
                 }
             }
 
+            generation += 1;
             reset = false;
         }
 
@@ -1,145 +1,281 @@
 use macroquad::prelude::*;
-use macroquad::rand::ChooseRandom;
 
-use rayon::prelude::*;
+use lbm::coarse::{CoarseGraining, Kernel};
+use lbm::fhp::{self, CollisionTable, Lattice, Model, MultiSpin, NodeType};
+use lbm::geometry::{Geometry, Naca};
+use lbm::mask::{Brush, Cell, Mask};
+use lbm::postprocess::{colormap, Derived};
+use lbm::rng::CounterRng;
+use lbm::tracer::{Emitter, Tracers};
+use lbm::wall::Reflection;
 
 const CELL_SIZE: f32 = 2.;
 const CELL_SIZE_Y: f32 = CELL_SIZE * 1.7321 * 0.5;
 
-const OFFSETS: [[[isize; 2]; 6]; 2] = [
-    [[1, 0], [0, -1], [-1, -1], [-1, 0], [-1, 1], [0, 1]],
-    [[1, 0], [1, -1], [0, -1], [-1, 0], [0, 1], [1, 1]],
-];
+// response time of inertial tracers, in time steps
+const TRACER_RESPONSE: f32 = 50.;
 
-#[inline]
-fn get_cell(i: usize, node: u8) -> bool {
-    ((node >> i) & 0b1) != 0b0
+// state drawn at the inflow: mean occupation per channel and velocity
+const INFLOW_OCCUPATION: f32 = 0.3;
+const INFLOW_VELOCITY: f32 = 0.2;
+
+const USAGE: &str = "usage: fhp_lgca [--model I|II|III] [--scalar] [--seed N] [--mask PATH] \
+                     [--naca XXXX] [--alpha DEG] [--walls bounce|specular|diffuse]";
+
+// airfoil chord as a fraction of the channel length
+const CHORD: f32 = 0.3;
+
+const MASK_FILE: &str = "mask.png";
+
+// snapshots averaged over when time averaging is on
+const AVERAGE_FRAMES: usize = 20;
+
+// length of the arrows relative to the sample spacing per unit velocity
+const ARROW_SCALE: f32 = 2.;
+
+/// FHP has no moving walls, so they stand still.
+fn from_cell(cell: Cell, inflow: NodeType, reflection: Reflection) -> NodeType {
+    match cell {
+        Cell::Fluid => NodeType::Fluid,
+        Cell::Wall | Cell::MovingWall => NodeType::Boundary(reflection),
+        Cell::Inflow => inflow,
+        Cell::Sink => NodeType::Sink,
+    }
 }
 
-#[inline]
-fn set_cell(i: usize, node: &mut u8, val: bool) {
-    *node = (!(0b1 << i) & *node) | (val as u8) << i;
+fn to_cell(node_type: &NodeType) -> Cell {
+    match node_type {
+        NodeType::Fluid => Cell::Fluid,
+        NodeType::Boundary(_) => Cell::Wall,
+        NodeType::Inflow(_) => Cell::Inflow,
+        NodeType::Sink => Cell::Sink,
+    }
 }
 
-fn init_lattice<T: Clone>(w: usize, h: usize, t: T) -> Vec<Vec<T>> {
-    vec![vec![t; w]; h]
+fn wall_color(reflection: Reflection) -> Color {
+    match reflection {
+        Reflection::BounceBack => WHITE,
+        Reflection::Specular => SKYBLUE,
+        Reflection::Diffuse => ORANGE,
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
-enum NodeType {
-    Fluid,
-    Boundary,
-    Inflow([f32; 6]),
-    Sink,
+/// Channel with walls along the top and bottom, the inflow on the left and a
+/// sink on the right, around `obstacle` or a thin wall across the middle a
+/// quarter of the way down, every wall reflecting as `reflection`.
+fn init_channel(
+    lattice: &mut Lattice,
+    inflow: NodeType,
+    obstacle: Option<Geometry>,
+    reflection: Reflection,
+) {
+    let (width, height) = (lattice.width, lattice.height);
+    let [length, depth] = fhp::position(width - 1, height - 1);
+
+    let inlet = Geometry::rectangle([-1., -1.], [0.75, depth + 1.]);
+    let outlet = Geometry::rectangle([length - 0.75, -1.], [length + 1., depth + 1.]);
+
+    let wall = obstacle.unwrap_or_else(|| {
+        Geometry::rectangle(
+            [0.25 * length - 0.5, 0.25 * depth],
+            [0.25 * length + 2.5, 0.75 * depth],
+        )
+    });
+
+    let walls = Geometry::rectangle([-1., -1.], [length + 1., 0.5])
+        .union(Geometry::rectangle(
+            [-1., depth - 0.5],
+            [length + 1., depth + 1.],
+        ))
+        .union(wall);
+
+    inlet.fill_at(&mut lattice.node_type, width, fhp::position, inflow);
+    outlet.fill_at(&mut lattice.node_type, width, fhp::position, NodeType::Sink);
+    walls.fill_at(
+        &mut lattice.node_type,
+        width,
+        fhp::position,
+        NodeType::Boundary(reflection),
+    );
+}
+
+/// Value following `flag` on the command line, exiting with the usage if it
+/// is there but does not parse.
+fn arg<T, F: Fn(&str) -> Option<T>>(args: &[String], flag: &str, parse: F) -> Option<T> {
+    let k = args.iter().position(|a| a == flag)?;
+
+    match args.get(k + 1).and_then(|value| parse(value)) {
+        Some(value) => Some(value),
+        None => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Model named by `--model`, FHP-I by default, whether `--scalar` asks for the
+/// reference engine instead of the multi-spin one, the airfoil section and
+/// angle of attack in degrees given by `--naca` and `--alpha`, and the
+/// reflection of the walls, bounce-back unless `--walls` says otherwise.
+fn parse_args() -> (Model, bool, Option<(Naca, f32)>, Reflection) {
+    let args: Vec<_> = std::env::args().collect();
+
+    let model = arg(&args, "--model", Model::parse).unwrap_or(Model::FhpI);
+    let alpha = arg(&args, "--alpha", |a| a.parse().ok()).unwrap_or(0.);
+    let airfoil = arg(&args, "--naca", Naca::parse).map(|naca| (naca, alpha));
+
+    let walls = arg(&args, "--walls", Reflection::parse).unwrap_or(Reflection::BounceBack);
+
+    (model, args.iter().any(|a| a == "--scalar"), airfoil, walls)
+}
+
+/// Averaging kernel for arrows `s` nodes apart. Boxes as wide and high as
+/// the arrows are apart along x and y tile the lattice; the Gaussian has a
+/// spread of half their spacing along x, so neighbouring ones overlap.
+fn kernel(s: usize, gaussian: bool) -> Kernel {
+    let s = s as f32;
+
+    if gaussian {
+        Kernel::Gaussian(0.5 * s)
+    } else {
+        Kernel::Box([0.5 * s, 0.5 * s * fhp::position(0, 1)[1]])
+    }
 }
 
 #[macroquad::main("2D FHP Lattice-Gas Automaton")]
 async fn main() {
-    let height = 400;
-    let width = 400;
+    let (model, scalar, airfoil, walls) = parse_args();
 
-    let mut lattice = init_lattice(width, height, 0u8);
+    let table = CollisionTable::new(model);
+    println!(
+        "model: {} engine: {}",
+        table.model.name(),
+        if scalar { "scalar" } else { "multi-spin" }
+    );
 
-    let mut type_lattice = init_lattice(width, height, NodeType::Fluid);
+    let rng = CounterRng::from_args();
+    let (collisions, injection) = (rng.stream(0), rng.stream(1));
 
-    for j in 0..height {
-        type_lattice[j][0] = NodeType::Inflow([1., 0.5, 0., 0., 0., 0.5]);
-        type_lattice[j][width - 1] = NodeType::Sink;
-    }
+    let mask = Mask::from_args();
 
-    for i in 0..width {
-        type_lattice[0][i] = NodeType::Boundary;
-        type_lattice[height - 1][i] = NodeType::Boundary;
-    }
+    let (width, height) = mask.as_ref().map_or((400, 400), |m| (m.width, m.height));
+
+    let mut lattice = Lattice::new(width, height);
+
+    let inflow = NodeType::Inflow(fhp::equilibrium(
+        model,
+        INFLOW_OCCUPATION * model.channels() as f32,
+        [INFLOW_VELOCITY, 0.],
+    ));
+
+    match &mask {
+        Some(mask) => lattice.node_type = mask.map(|cell| from_cell(cell, inflow, walls)),
+        None => {
+            let [length, depth] = fhp::position(width - 1, height - 1);
+
+            let obstacle = airfoil.map(|(naca, alpha)| {
+                println!("airfoil: {:?} at {} degrees", naca, alpha);
 
-    let wall_width = 200;
+                Geometry::airfoil(
+                    naca,
+                    [0.25 * length, 0.5 * depth],
+                    CHORD * length,
+                    alpha.to_radians(),
+                )
+            });
 
-    for j in height / 2 - wall_width / 2..height / 2 + wall_width / 2 {
-        for i in width / 4..width / 4 + 3 {
-            type_lattice[j][i] = NodeType::Boundary;
+            init_channel(&mut lattice, inflow, obstacle, walls)
         }
     }
 
-    let v: Vec<_> = (0..6i32)
-        .map(|i| {
-            let alpha = i as f32 / 6 as f32 * 2. * std::f32::consts::PI;
-            [alpha.cos(), alpha.sin()]
-        })
-        .collect();
+    // the scalar lattice keeps the geometry and receives the multi-spin state
+    // whenever it is drawn
+    let mut spin = if scalar {
+        None
+    } else {
+        Some(MultiSpin::new(&lattice, &table))
+    };
 
     let mut time = get_time();
 
     let mut s = 10;
 
-    loop {
-        // choose random collision rules once per frame
-
-        let coll_2_0 = *vec![0b010010, 0b001001].choose().unwrap();
-        let coll_2_1 = *vec![0b100100, 0b001001].choose().unwrap();
-        let coll_2_2 = *vec![0b010010, 0b100100].choose().unwrap();
-
-        let coll_4_0 = *vec![0b101101, 0b011011].choose().unwrap();
-        let coll_4_1 = *vec![0b110110, 0b011011].choose().unwrap();
-        let coll_4_2 = *vec![0b101101, 0b110110].choose().unwrap();
-
-        // collision
-
-        lattice.iter_mut().enumerate().for_each(|(j, row)| {
-            for i in 0..width {
-                match type_lattice[j][i] {
-                    NodeType::Fluid => {
-                        row[i] = match row[i] {
-                            0b010101 => 0b101010,
-                            0b101010 => 0b010101,
-                            0b100100 => coll_2_0,
-                            0b010010 => coll_2_1,
-                            0b001001 => coll_2_2,
-                            0b110110 => coll_4_0,
-                            0b101101 => coll_4_1,
-                            0b011011 => coll_4_2,
-                            _ => row[i],
-                        }
-                    }
-                    NodeType::Inflow(p) => {
-                        row[i] = (row[i] >> 3) & 0b111 | (row[i] << 3 & 0b111000);
+    let mut tracers = Tracers::new();
+    let mut steps = 0;
+    let mut t = 0;
+    let mut emitter_start = None;
 
-                        for d in 0..6 {
-                            if rand::gen_range(0., 1.) <= p[d] {
-                                row[i] |= 0b1 << d;
-                            }
-                        }
-                    }
-                    NodeType::Boundary => row[i] = (row[i] >> 3) & 0b111 | (row[i] << 3 & 0b111000),
-                    NodeType::Sink => {
-                        row[i] = 0b0;
-                    }
-                }
-            }
-        });
+    let mut view: Option<Derived> = None;
 
-        let mut new_lattice = init_lattice(width, height, 0);
+    let mut coarse = CoarseGraining::new(model, kernel(s, false), s, 1);
+    let mut gaussian = false;
 
-        // streaming
-        for j in 0..height {
-            for i in 0..width {
-                for d in 0..6 {
-                    let [i_off, j_off] = OFFSETS[j % 2][d];
+    // while painting, the mouse draws the brush's cells and erases with the
+    // right button instead of placing tracers
+    let mut painting = false;
+    let mut brush = Brush::new();
+    let mut reflection = walls;
 
-                    let nj = ((j as isize - j_off + height as isize) % height as isize) as usize;
-
-                    let ni = ((i as isize + i_off + width as isize) % width as isize) as usize;
+    loop {
+        // fresh collision outcomes for every node and step
+        let random = fhp::random_bits(collisions, t);
+        let uniform = |n: usize, d: usize| injection.uniform([t, n as u64, d as u64]);
 
-                    set_cell(d, &mut new_lattice[nj][ni], get_cell(d, lattice[j][i]));
-                }
-            }
+        match spin.as_mut() {
+            Some(spin) => spin.step(random, uniform),
+            None => lattice.step(&table, random, uniform),
         }
 
-        lattice = new_lattice;
+        steps += 1;
+        t += 1;
 
         if get_time() - time > 0.05 {
+            if let Some(spin) = &spin {
+                spin.store(&mut lattice);
+            }
+
             let x_off = screen_width() / 2. - (width - 1) as f32 * CELL_SIZE / 2.;
             let y_off = screen_height() / 2. - (height - 1) as f32 * CELL_SIZE_Y / 2.;
 
+            coarse.stride = s;
+            coarse.kernel = kernel(s, gaussian);
+            coarse.add(&lattice);
+
+            let fields = coarse.fields().unwrap();
+
+            // tracers drift with the particles, the views and arrows show the
+            // velocity that obeys Navier-Stokes
+            let field = &fields.velocity;
+            let corrected = &fields.corrected;
+
+            let derived = view.map(|view| view.compute(corrected));
+
+            if let (Some(view), Some(derived)) = (view, &derived) {
+                let scale = derived.max_abs();
+
+                for j in 0..field.height {
+                    for i in 0..field.width {
+                        let color = colormap(derived.get(i, j), scale, view.signed());
+
+                        draw_rectangle(
+                            x_off + (i as f32 - 0.5) * s as f32 * CELL_SIZE,
+                            y_off + (j as f32 - 0.5) * s as f32 * CELL_SIZE_Y,
+                            s as f32 * CELL_SIZE,
+                            s as f32 * CELL_SIZE_Y,
+                            Color::from(color),
+                        );
+                    }
+                }
+
+                draw_text(
+                    &format!("view: {} (max {:.2e})", view.name(), scale),
+                    20.,
+                    20.,
+                    20.,
+                    WHITE,
+                );
+            }
+
             for j in 0..height {
                 for i in 0..width {
                     let x = x_off
@@ -147,34 +283,24 @@ async fn main() {
                         + if j % 2 == 0 { 0. } else { 0.5 * CELL_SIZE };
                     let y = y_off + j as f32 * CELL_SIZE_Y;
 
-                    match type_lattice[j][i] {
-                        NodeType::Boundary => draw_circle(x, y, CELL_SIZE_Y, WHITE),
-                        _ => {}
+                    match lattice.node_type[lattice.index(i, j)] {
+                        NodeType::Boundary(reflection) => {
+                            draw_circle(x, y, CELL_SIZE_Y, wall_color(reflection))
+                        }
+                        NodeType::Inflow(_) => draw_circle(x, y, CELL_SIZE_Y, RED),
+                        NodeType::Sink => draw_circle(x, y, CELL_SIZE_Y, BLUE),
+                        NodeType::Fluid => {}
                     }
                 }
             }
 
             for j in 1..height / s {
                 for i in 1..width / s {
+                    let [ux, uy] = corrected.get(i, j);
+
                     let j = s * j;
                     let i = s * i;
 
-                    let (mut mx, mut my) = (0., 0.);
-
-                    for ii in i - s..=(i + s).min(width - 1) {
-                        for jj in j - s..=(j + s).min(height - 1) {
-                            let n = lattice[jj][ii];
-                            for d in 0..6 {
-                                let t = ((n >> d) & 0b1) as f32;
-                                mx += t * v[d][0];
-                                my += t * v[d][1];
-                            }
-                        }
-                    }
-
-                    mx = mx / ((2 * s + 1) as f32).powi(2);
-                    my = my / ((2 * s + 1) as f32).powi(2);
-
                     let x = x_off
                         + i as f32 * CELL_SIZE
                         + if j % 2 == 0 { 0. } else { 0.5 * CELL_SIZE };
@@ -183,20 +309,218 @@ async fn main() {
                     draw_line(
                         x,
                         y,
-                        x + 0.5 * mx * CELL_SIZE * (s as f32 + 0.5) as f32,
-                        y + 0.5 * my * CELL_SIZE * s as f32,
+                        x + ARROW_SCALE * ux * CELL_SIZE * s as f32,
+                        y + ARROW_SCALE * uy * CELL_SIZE * s as f32,
                         1.,
                         WHITE,
                     );
                 }
             }
 
+            draw_text(
+                &format!(
+                    "average: {} over {} frame(s)",
+                    coarse.kernel.name(),
+                    coarse.frames
+                ),
+                20.,
+                60.,
+                20.,
+                WHITE,
+            );
+
+            tracers.advance(field, steps as f32);
+            steps = 0;
+
+            for tracer in tracers.tracers.iter() {
+                let trail: Vec<_> = tracers.trail(tracer).collect();
+
+                for w in trail.windows(2) {
+                    draw_line(
+                        x_off + w[0][0] * CELL_SIZE,
+                        y_off + w[0][1] * CELL_SIZE,
+                        x_off + w[1][0] * CELL_SIZE,
+                        y_off + w[1][1] * CELL_SIZE,
+                        1.,
+                        YELLOW,
+                    );
+                }
+
+                draw_circle(
+                    x_off + tracer.pos[0] * CELL_SIZE,
+                    y_off + tracer.pos[1] * CELL_SIZE,
+                    2.,
+                    YELLOW,
+                );
+            }
+
+            let (mouse_x, mouse_y) = mouse_position();
+            let mouse = [(mouse_x - x_off) / CELL_SIZE, (mouse_y - y_off) / CELL_SIZE];
+
+            if painting {
+                draw_circle_lines(mouse_x, mouse_y, brush.radius * CELL_SIZE, 1., YELLOW);
+                draw_text(
+                    &format!(
+                        "paint: {} ({} walls) radius {}",
+                        brush.cell.name(),
+                        reflection.name(),
+                        brush.radius
+                    ),
+                    20.,
+                    40.,
+                    20.,
+                    YELLOW,
+                );
+
+                let cell = if is_mouse_button_down(MouseButton::Left) {
+                    Some(brush.cell)
+                } else if is_mouse_button_down(MouseButton::Right) {
+                    Some(Cell::Fluid)
+                } else {
+                    None
+                };
+
+                if let Some(cell) = cell {
+                    brush.shape(mouse).fill_at(
+                        &mut lattice.node_type,
+                        width,
+                        fhp::position,
+                        from_cell(cell, inflow, reflection),
+                    );
+
+                    // the multi-spin engine packs the geometry, so it is
+                    // rebuilt from the freshly stored state
+                    if let Some(spin) = spin.as_mut() {
+                        *spin = MultiSpin::new(&lattice, &table);
+                    }
+                }
+            }
+
+            if !painting && is_mouse_button_pressed(MouseButton::Left) && field.contains(mouse) {
+                tracers.seed(mouse, field.sample(mouse));
+            }
+
+            if !painting && is_mouse_button_pressed(MouseButton::Right) {
+                emitter_start = Some(mouse);
+            }
+
+            if let Some(start) = emitter_start {
+                draw_line(
+                    x_off + start[0] * CELL_SIZE,
+                    y_off + start[1] * CELL_SIZE,
+                    mouse_x,
+                    mouse_y,
+                    1.,
+                    YELLOW,
+                );
+
+                if is_mouse_button_released(MouseButton::Right) {
+                    tracers.emitters.push(Emitter {
+                        start,
+                        end: mouse,
+                        count: 10,
+                        interval: 20.,
+                    });
+
+                    emitter_start = None;
+                }
+            }
+
+            if is_key_pressed(KeyCode::I) {
+                tracers.response_time = if tracers.response_time > 0. {
+                    0.
+                } else {
+                    TRACER_RESPONSE
+                };
+            }
+
+            if is_key_pressed(KeyCode::C) {
+                tracers.clear();
+            }
+
+            if is_key_pressed(KeyCode::X) {
+                match tracers.write_csv("tracers.csv") {
+                    Ok(()) => println!("wrote tracers.csv"),
+                    Err(e) => println!("could not write tracers.csv: {}", e),
+                }
+            }
+
+            if is_key_pressed(KeyCode::V) {
+                view = match view {
+                    None => Some(Derived::Speed),
+                    Some(Derived::StreamFunction) => None,
+                    Some(view) => Some(view.next()),
+                };
+            }
+
+            if is_key_pressed(KeyCode::E) {
+                if let (Some(view), Some(derived)) = (view, &derived) {
+                    let name = format!("{}.csv", view.name());
+
+                    match derived.write_csv(&name) {
+                        Ok(()) => println!("wrote {}", name),
+                        Err(e) => println!("could not write {}: {}", name, e),
+                    }
+                }
+
+                for (name, result) in [
+                    ("velocity.csv", field.write_csv("velocity.csv")),
+                    ("corrected.csv", corrected.write_csv("corrected.csv")),
+                    ("density.csv", fields.density.write_csv("density.csv")),
+                ] {
+                    match result {
+                        Ok(()) => println!("wrote {}", name),
+                        Err(e) => println!("could not write {}: {}", name, e),
+                    }
+                }
+            }
+
             if is_key_pressed(KeyCode::Period) {
                 s += 1;
+                coarse.clear();
+            }
+
+            if is_key_pressed(KeyCode::G) {
+                gaussian = !gaussian;
+                coarse.clear();
+            }
+
+            if is_key_pressed(KeyCode::A) {
+                coarse.frames = if coarse.frames > 1 { 1 } else { AVERAGE_FRAMES };
+            }
+
+            if is_key_pressed(KeyCode::P) {
+                painting = !painting;
+            }
+
+            if is_key_pressed(KeyCode::T) {
+                brush.cell = brush.cell.next();
+            }
+
+            if is_key_pressed(KeyCode::W) {
+                reflection = reflection.next();
+            }
+
+            if is_key_pressed(KeyCode::RightBracket) {
+                brush.grow();
+            }
+
+            if is_key_pressed(KeyCode::LeftBracket) {
+                brush.shrink();
+            }
+
+            if is_key_pressed(KeyCode::M) {
+                let mask = Mask::from_nodes(width, height, &lattice.node_type, to_cell);
+
+                match mask.save(MASK_FILE) {
+                    Ok(()) => println!("wrote {}", MASK_FILE),
+                    Err(e) => println!("could not write {}: {}", MASK_FILE, e),
+                }
             }
 
             if is_key_pressed(KeyCode::Comma) && s > 1 {
                 s -= 1;
+                coarse.clear();
             }
 
             time = get_time();
@@ -0,0 +1,64 @@
+use lbm::fhp::Model;
+use lbm::rng::CounterRng;
+use lbm::transport::{Experiment, Measurement};
+
+const USAGE: &str = "usage: fhp_transport [--model I|II|III] [--occupation D] [--seed N]";
+
+// mean occupation per channel when none is given
+const OCCUPATION: f32 = 0.3;
+
+/// Value following `flag` on the command line, exiting with the usage if it
+/// is there but does not parse.
+fn arg<T, F: Fn(&str) -> Option<T>>(args: &[String], flag: &str, parse: F) -> Option<T> {
+    let k = args.iter().position(|a| a == flag)?;
+
+    match args.get(k + 1).and_then(|value| parse(value)) {
+        Some(value) => Some(value),
+        None => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn report(name: &str, m: &Measurement) {
+    println!(
+        "  {:<14} measured {:.4} theory {:.4} error {:+.1}%",
+        name,
+        m.measured,
+        m.theory,
+        100. * m.error()
+    );
+}
+
+/// Measures the viscosity and sound speed of the model named by `--model`, or
+/// of all three, at the occupation given by `--occupation`.
+fn main() {
+    let args: Vec<_> = std::env::args().collect();
+
+    let models = match arg(&args, "--model", Model::parse) {
+        Some(model) => vec![model],
+        None => vec![Model::FhpI, Model::FhpII, Model::FhpIII],
+    };
+
+    let occupation = arg(&args, "--occupation", |d| {
+        d.parse().ok().filter(|d| *d > 0. && *d < 1.)
+    })
+    .unwrap_or(OCCUPATION);
+
+    let rng = CounterRng::from_args();
+
+    for model in models {
+        let experiment = Experiment::new(model, occupation, rng);
+
+        println!(
+            "{} at occupation {} (density {}):",
+            model.name(),
+            occupation,
+            occupation * model.channels() as f32
+        );
+
+        report("viscosity", &experiment.shear_wave());
+        report("sound speed", &experiment.sound_pulse());
+    }
+}
@@ -1,11 +1,56 @@
 use macroquad::prelude::*;
 
+use lbm::hpp::{Edge, Edges, Lattice, Node, NodeType, Reversal};
+use lbm::mask::{Brush, Cell, Mask};
+use lbm::rng::CounterRng;
+use lbm::wall::Reflection;
 
-type Node = [bool; 4];
 
 const CELL_SIZE: f32 = 8.;
+
+// edges unless `--edges` says otherwise: all periodic, or all reflecting
 const PERIODIC: bool = false;
 
+// occupation of every channel at inflow nodes, which HPP keeps at rest
+const INFLOW_OCCUPATION: f32 = 0.5;
+
+const MASK_FILE: &str = "mask.png";
+
+const USAGE: &str =
+    "usage: hpp_lgca [--mask PATH] [--seed N] [--edges EDGES] [--reverse STEPS] [--perturb]";
+
+/// Says that `run` turned back, and warns if it cannot retrace its steps.
+fn report_turn(lattice: &Lattice, run: &Reversal, perturb: bool) {
+    if !lattice.reversible() {
+        println!("open edges, inflow, sinks or diffuse walls make this run irreversible");
+    }
+
+    println!(
+        "turned back after {} steps{}",
+        run.steps,
+        if perturb { ", one bit flipped" } else { "" }
+    );
+}
+
+/// HPP has no moving walls, so they stand still.
+fn from_cell(cell: Cell, reflection: Reflection) -> NodeType {
+    match cell {
+        Cell::Fluid => NodeType::Fluid,
+        Cell::Wall | Cell::MovingWall => NodeType::Wall(reflection),
+        Cell::Inflow => NodeType::Inflow(INFLOW_OCCUPATION),
+        Cell::Sink => NodeType::Sink,
+    }
+}
+
+fn to_cell(node_type: &NodeType) -> Cell {
+    match node_type {
+        NodeType::Fluid => Cell::Fluid,
+        NodeType::Wall(_) => Cell::Wall,
+        NodeType::Inflow(_) => Cell::Inflow,
+        NodeType::Sink => Cell::Sink,
+    }
+}
+
 
 
 fn draw_node(node: &Node, x: f32, y: f32) {
@@ -23,93 +68,248 @@ fn draw_node(node: &Node, x: f32, y: f32) {
     }
 }
 
-fn collide(node: &Node) -> Node {
-    match node {
-        [true, false, true, false] => [false, true, false, true],
-        [false, true, false, true] => [true, false, true, false],
-        _ => *node,
-    }
-}
+#[macroquad::main("2D HPP Lattice-Gas Automaton")]
+async fn main() {
+    let mask = Mask::from_args();
 
-fn update_lattice(lattice: &mut Vec<Vec<Node>>, height: usize, width: usize) {
-    let mut new_lattice : Vec<Vec<Node>> = vec![vec![[true;4];height];width];
+    let (width, height) = match &mask {
+        Some(mask) => (mask.width, mask.height),
+        None => {
+            let height = (screen_height() / CELL_SIZE) as usize - 2;
+            (height, height)
+        }
+    };
+
+    let mut node_type = vec![vec![NodeType::Fluid; height]; width];
 
-    for i in 0..width {
-        for j in 0 .. height {
-            new_lattice[i][j] = collide(&lattice[i][j]);
+    if let Some(mask) = &mask {
+        for (i, column) in node_type.iter_mut().enumerate() {
+            for (j, node) in column.iter_mut().enumerate() {
+                *node = from_cell(mask.get(i, j), Reflection::BounceBack);
+            }
         }
     }
 
-    *lattice = new_lattice.clone();
+    let args: Vec<_> = std::env::args().collect();
 
-    for i in 0..width {
-        for j in 0 .. height {
-            if i == 0 {
-                new_lattice[i][j][0] = lattice[i][j][2];
-            } else {
-                new_lattice[i][j][0] = lattice[i-1][j][0];
+    // run forward for this many steps, at least one, and back again
+    let length = args.iter().position(|a| a == "--reverse").map(|k| {
+        match args.get(k + 1).and_then(|n| n.parse().ok()) {
+            Some(n) if n > 0 => n,
+            _ => {
+                eprintln!("{}", USAGE);
+                std::process::exit(1);
             }
+        }
+    });
+    let mut perturb = args.iter().any(|a| a == "--perturb");
 
-            if j == 0 {
-                new_lattice[i][j][3] = lattice[i][j][1];
+    let edges = Edges::from_args(Edges::all(if PERIODIC {
+        Edge::Periodic
+    } else {
+        Edge::Reflecting
+    }));
 
-            } else {
-                new_lattice[i][j][3] = lattice[i][j-1][3];
-            }
+    // the nodes are stored row by row, the node types column by column
+    let mut lattice = Lattice::new(width, height, edges);
+    lattice.set_geometry(|i, j| node_type[i][j]);
 
-            if i == width - 1 {
-                new_lattice[i][j][2] = lattice[i][j][0];
+    let rng = CounterRng::from_args();
+    let (init, inflow) = (rng.stream(0), rng.stream(1));
 
-            } else {
-                new_lattice[i][j][2] = lattice[i+1][j][2];
-            }
+    let mut t = 0;
+
+    // the middle two thirds of the box along each axis, whatever its size
+    let columns = (width / 2).saturating_sub(width / 3)..width / 2 + width / 3;
+    let rows = (height / 2).saturating_sub(height / 3)..height / 2 + height / 3;
 
-            if j == height - 1 {
-                new_lattice[i][j][1] = lattice[i][j][3];
-            } else {
-                new_lattice[i][j][1] = lattice[i][j+1][1];
+    for (i, column) in node_type.iter().enumerate() {
+        for (j, &node_type) in column.iter().enumerate() {
+            if !columns.contains(&i) || !rows.contains(&j) || node_type != NodeType::Fluid {
+                continue;
             }
+
+            let node = [0, 1, 2, 3].map(|d| init.uniform([i as u64, j as u64, d as u64]) < 0.5);
+            lattice.set(i, j, node);
         }
     }
 
-    *lattice = new_lattice;
-} 
+    let mut reversal = length.map(|n| Reversal::new(&lattice, Some(n)));
+    let mut message = String::new();
 
-#[macroquad::main("2D HPP Lattice-Gas Automaton")]
-async fn main() {
-    let height = (screen_height() / CELL_SIZE) as usize - 2;
-    let width = height;
+    let mut time = get_time();
+
+    // the left button paints the brush's cells, the right one erases
+    let mut brush = Brush::new();
+    let mut reflection = Reflection::BounceBack;
+
+    loop {
+        let x_off = screen_width() / 2. - (width - 1) as f32 * CELL_SIZE / 2.;
+        let y_off = screen_height() / 2. - (height - 1) as f32 * CELL_SIZE / 2.;
+
+        for (i, column) in node_type.iter().enumerate() {
+            for (j, &node_type) in column.iter().enumerate() {
+                let node = lattice.node(i, j);
+
+                let x = x_off + i as f32 * CELL_SIZE;
+                let y = y_off + j as f32 * CELL_SIZE;
 
-    let mut lattice : Vec<Vec<Node>> = vec![vec![[false; 4];height];width];
+                let color = match node_type {
+                    NodeType::Fluid => None,
+                    NodeType::Wall(Reflection::BounceBack) => Some(GRAY),
+                    NodeType::Wall(Reflection::Specular) => Some(SKYBLUE),
+                    NodeType::Wall(Reflection::Diffuse) => Some(ORANGE),
+                    NodeType::Inflow(_) => Some(RED),
+                    NodeType::Sink => Some(BLUE),
+                };
 
-    for i in width/2 - height / 3 .. width / 2 + width / 3 {
-        for j in height/2 - height / 3 .. height / 2 + height / 3 {
-            for d in 0..4 {
-                if rand::gen_range(0.0, 1.0) < 0.5 {
-                    lattice[i][j][d] = true;
+                if let Some(color) = color {
+                    let half = CELL_SIZE / 2.;
+                    draw_rectangle(x - half, y - half, CELL_SIZE, CELL_SIZE, color);
                 }
+
+                draw_node(&node, x, y);
             }
         }
-    }
 
-    let mut time = get_time();
+        let (mouse_x, mouse_y) = mouse_position();
+        let mouse = [(mouse_x - x_off) / CELL_SIZE, (mouse_y - y_off) / CELL_SIZE];
 
-    loop {
-        let x_off = screen_width() / 2. - (width - 1) as f32 * CELL_SIZE / 2.;
-        let y_off = screen_height() / 2. - (height - 1) as f32 * CELL_SIZE / 2.;
+        draw_circle_lines(mouse_x, mouse_y, brush.radius * CELL_SIZE, 1., YELLOW);
+        draw_text(
+            &format!(
+                "paint: {} ({} walls) radius {}",
+                brush.cell.name(),
+                reflection.name(),
+                brush.radius
+            ),
+            20.,
+            20.,
+            20.,
+            YELLOW,
+        );
+
+        let paint = if is_mouse_button_down(MouseButton::Left) {
+            Some(brush.cell)
+        } else if is_mouse_button_down(MouseButton::Right) {
+            Some(Cell::Fluid)
+        } else {
+            None
+        };
+
+        if let Some(paint) = paint {
+            let shape = brush.shape(mouse);
+
+            for (i, column) in node_type.iter_mut().enumerate() {
+                for (j, node) in column.iter_mut().enumerate() {
+                    if shape.contains([i as f32, j as f32]) {
+                        *node = from_cell(paint, reflection);
+                    }
+                }
+            }
+
+            lattice.set_geometry(|i, j| node_type[i][j]);
+        }
+
+        if is_key_pressed(KeyCode::T) {
+            brush.cell = brush.cell.next();
+        }
+
+        if is_key_pressed(KeyCode::W) {
+            reflection = reflection.next();
+        }
+
+        if is_key_pressed(KeyCode::RightBracket) {
+            brush.grow();
+        }
+
+        if is_key_pressed(KeyCode::LeftBracket) {
+            brush.shrink();
+        }
 
-        for i in 0..width {
-            for j in 0 .. height {
-                let node = lattice[i][j];
+        if is_key_pressed(KeyCode::M) {
+            // the node types are stored column by column, masks row by row
+            let nodes: Vec<_> = (0..width * height)
+                .map(|n| node_type[n % width][n / width])
+                .collect();
+            let mask = Mask::from_nodes(width, height, &nodes, to_cell);
 
-               draw_node(&node, x_off + i as f32 * CELL_SIZE , y_off + j as f32 * CELL_SIZE);
+            match mask.save(MASK_FILE) {
+                Ok(()) => println!("wrote {}", MASK_FILE),
+                Err(e) => println!("could not write {}: {}", MASK_FILE, e),
+            }
+        }
+
+        let status = match &reversal {
+            Some(Reversal {
+                remaining: None,
+                steps,
+                ..
+            }) => format!("time reversal: {} steps forward", steps),
+            Some(Reversal {
+                remaining: Some(remaining),
+                ..
+            }) => format!("time reversal: {} steps to retrace", remaining),
+            None => message.clone(),
+        };
+
+        draw_text(
+            &format!(
+                "{}{}",
+                status,
+                if perturb { " (perturbing one bit)" } else { "" }
+            ),
+            20.,
+            40.,
+            20.,
+            YELLOW,
+        );
+
+        // starts a time-reversal run, or turns it back
+        if is_key_pressed(KeyCode::R) {
+            match reversal.as_mut() {
+                None => reversal = Some(Reversal::new(&lattice, None)),
+                Some(run) if run.remaining.is_none() => {
+                    run.turn_back(&mut lattice, perturb);
+                    report_turn(&lattice, run, perturb);
+                }
+                Some(_) => {}
             }
         }
 
+        if is_key_pressed(KeyCode::B) {
+            perturb = !perturb;
+        }
+
         if get_time() - time > 0.05 {
             time = get_time();
 
-            update_lattice(&mut lattice, height, width);
+            lattice.step(|n, d| inflow.uniform([t, n as u64, d as u64]));
+            t += 1;
+
+            if let Some(run) = reversal.as_mut() {
+                if run.advance(&mut lattice, perturb) {
+                    report_turn(&lattice, run, perturb);
+                }
+            }
+        }
+
+        if let Some(run) = &reversal {
+            if let Some(bits) = run.finish(&mut lattice) {
+                message = match bits {
+                    0 => format!(
+                        "recovered the initial state bit for bit after {} steps each way",
+                        run.steps
+                    ),
+                    bits => format!(
+                        "{} bits differ from the initial state after {} steps each way",
+                        bits, run.steps
+                    ),
+                };
+
+                println!("{}", message);
+                reversal = None;
+            }
         }
 
         next_frame().await
@@ -0,0 +1,1064 @@
+use macroquad::{color::hsl_to_rgb, prelude::*};
+
+use lbm::convergence::{Convergence, State};
+use lbm::d2q9::{equilibrium, Lattice, NodeType};
+use lbm::field::VectorField;
+use lbm::geometry::Geometry;
+use lbm::ibm::{Filament, ImmersedBoundary, Kernel, RigidBody};
+use lbm::mask::{Brush, Cell, Mask};
+use lbm::outflow::{Convective, Edge, Outflow, Sponge};
+use lbm::particle::{faxen_settling_velocity, Particle, Particles, Shape};
+use lbm::postprocess::{colormap, Derived};
+use lbm::refine::{Block, Refinement};
+use lbm::shallow::ShallowWater;
+use lbm::tracer::{Emitter, Tracers};
+use lbm::watchdog::{Report, Watchdog};
+
+const CELL_SIZE: f32 = 3.;
+
+const TAU: f32 = 0.56;
+const U_IN: f32 = 0.05;
+
+const STEPS_PER_FRAME: usize = 10;
+
+// response time of inertial tracers, in time steps
+const TRACER_RESPONSE: f32 = 50.;
+
+const WATCHDOG_INTERVAL: usize = 100;
+
+// convergence is judged on a sample every 10 steps over the last 5000 steps
+const CONVERGENCE_INTERVAL: usize = 10;
+const CONVERGENCE_WINDOW: usize = 500;
+
+// sponge layer in front of the channel outlet
+const SPONGE_WIDTH: usize = 30;
+const SPONGE_STRENGTH: f32 = 0.1;
+
+const RADIUS: f32 = 8.;
+const FILAMENT_LENGTH: f32 = 40.;
+
+// amplitude and period of the cylinder's transverse oscillation
+const AMPLITUDE: f32 = 10.;
+const PERIOD: f32 = 1000.;
+
+// sedimenting-disc benchmark, in lattice units
+const SEDIMENT_TAU: f32 = 1.;
+const SEDIMENT_RADIUS: f32 = 6.25;
+const SEDIMENT_DENSITY: f32 = 1.5;
+const SEDIMENT_GRAVITY: f32 = 1.43e-4;
+
+// shallow-water dam break: reservoir, wet tail water and a dry step downstream;
+// the tail water is deep enough to keep the flow subcritical, and bed friction
+// tames the thin front running onto the step
+const SW_TAU: f32 = 0.7;
+const SW_GRAVITY: f32 = 0.1;
+const DAM_DEPTH: f32 = 1.;
+const TAIL_DEPTH: f32 = 0.3;
+const STEP_HEIGHT: f32 = 0.3;
+const BED_FRICTION: f32 = 0.003;
+
+// body-force-driven periodic channel with a refined block around the cylinder
+const REFINED_FORCE: f32 = 1e-6;
+
+// one period of a long channel, driven by the density jump across its seam
+const PRESSURE_DROP: f32 = 6e-4;
+
+const MASK_FILE: &str = "mask.png";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scenario {
+    /// Elastic flag behind an immersed-boundary cylinder.
+    Flag,
+    /// Single disc settling in a vertical channel, compared against Faxén.
+    Sedimentation,
+    /// Discs and polygons settling together.
+    Particles,
+    /// Shallow-water dam break running onto a dry step.
+    DamBreak,
+    /// Periodic channel with a 2:1 refined block around a cylinder.
+    Refined,
+    /// One period of a cylinder-lined channel driven by a pressure drop.
+    Periodic,
+    /// Geometry loaded from the `--mask` image.
+    Mask,
+}
+
+/// Treatment of the channel outlet in the flag scenario.
+#[derive(Clone, Copy, PartialEq)]
+enum Outlet {
+    Sink,
+    Convective,
+    Orlanski,
+    Sponge,
+}
+
+impl Outlet {
+    fn next(self) -> Self {
+        match self {
+            Outlet::Sink => Outlet::Convective,
+            Outlet::Convective => Outlet::Orlanski,
+            Outlet::Orlanski => Outlet::Sponge,
+            Outlet::Sponge => Outlet::Sink,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Outlet::Sink => "sink",
+            Outlet::Convective => "convective",
+            Outlet::Orlanski => "orlanski",
+            Outlet::Sponge => "sponge + convective",
+        }
+    }
+}
+
+struct Simulation {
+    scenario: Scenario,
+    lattice: Lattice,
+    ib: ImmersedBoundary,
+    cylinder: RigidBody,
+    filament: Filament,
+    particles: Particles,
+    water: ShallowWater,
+    refinement: Refinement,
+    outlet: Outlet,
+    outflow: Outflow,
+    /// Composite mass at the start, to track conservation across refinement interfaces.
+    mass: f32,
+    oscillate: bool,
+    t: usize,
+}
+
+fn init_channel(width: usize, height: usize) -> Lattice {
+    let mut lattice = Lattice::new(width, height, TAU);
+
+    for j in 0..height {
+        let n = lattice.index(0, j);
+        lattice.node_type[n] = NodeType::Inflow([U_IN, 0.]);
+
+        let n = lattice.index(width - 1, j);
+        lattice.node_type[n] = NodeType::Sink;
+    }
+
+    for i in 0..width {
+        let n = lattice.index(i, 0);
+        lattice.node_type[n] = NodeType::Boundary;
+
+        let n = lattice.index(i, height - 1);
+        lattice.node_type[n] = NodeType::Boundary;
+    }
+
+    lattice.fill(1., [U_IN, 0.]);
+
+    lattice
+}
+
+fn init_box(width: usize, height: usize, tau: f32) -> Lattice {
+    let mut lattice = Lattice::new(width, height, tau);
+
+    for j in 0..height {
+        for i in 0..width {
+            if i == 0 || j == 0 || i == width - 1 || j == height - 1 {
+                let n = lattice.index(i, j);
+                lattice.node_type[n] = NodeType::Boundary;
+            }
+        }
+    }
+
+    lattice
+}
+
+fn init_periodic_channel(width: usize, height: usize) -> Lattice {
+    let mut lattice = Lattice::new(width, height, TAU);
+
+    for i in 0..width {
+        let n = lattice.index(i, 0);
+        lattice.node_type[n] = NodeType::Boundary;
+
+        let n = lattice.index(i, height - 1);
+        lattice.node_type[n] = NodeType::Boundary;
+    }
+
+    lattice
+        .force
+        .iter_mut()
+        .for_each(|f| *f = [REFINED_FORCE, 0.]);
+
+    // start from the developed Poiseuille profile, walls half-way off the edge rows
+    let depth = height as f32 - 2.;
+
+    for j in 1..height - 1 {
+        let y = j as f32 - 0.5;
+        let u = REFINED_FORCE / (2. * lattice.viscosity()) * y * (depth - y);
+
+        for i in 0..width {
+            let n = lattice.index(i, j);
+
+            lattice.f[n] = equilibrium(1., [u, 0.]);
+            lattice.u[n] = [u, 0.];
+        }
+    }
+
+    lattice
+}
+
+fn init_pressure_channel(width: usize, height: usize) -> Lattice {
+    let mut lattice = Lattice::new(width, height, TAU);
+
+    for i in 0..width {
+        let n = lattice.index(i, 0);
+        lattice.node_type[n] = NodeType::Boundary;
+
+        let n = lattice.index(i, height - 1);
+        lattice.node_type[n] = NodeType::Boundary;
+    }
+
+    // the cylinder's surface is bounced back off where the links cross it
+    let center = [width as f32 / 2., height as f32 / 2.];
+    lattice.set_obstacle(&Geometry::circle(center, RADIUS));
+
+    lattice.fill(1., [0., 0.]);
+    lattice.pressure_drop = [PRESSURE_DROP, 0.];
+
+    lattice
+}
+
+/// Inflow and moving walls both run at the channel inflow speed.
+fn from_cell(cell: Cell) -> NodeType {
+    match cell {
+        Cell::Fluid => NodeType::Fluid,
+        Cell::Wall => NodeType::Boundary,
+        Cell::Inflow => NodeType::Inflow([U_IN, 0.]),
+        Cell::Sink => NodeType::Sink,
+        Cell::MovingWall => NodeType::MovingWall([U_IN, 0.]),
+    }
+}
+
+fn to_cell(node_type: &NodeType) -> Cell {
+    match node_type {
+        NodeType::Fluid => Cell::Fluid,
+        NodeType::Boundary => Cell::Wall,
+        NodeType::Inflow(_) => Cell::Inflow,
+        NodeType::Sink => Cell::Sink,
+        NodeType::MovingWall(_) => Cell::MovingWall,
+    }
+}
+
+fn init_mask(mask: &Mask) -> Lattice {
+    let mut lattice = Lattice::new(mask.width, mask.height, TAU);
+
+    lattice.node_type = mask.map(from_cell);
+
+    lattice.fill(1., [0., 0.]);
+
+    lattice
+}
+
+fn init_dam_break(lattice: &mut Lattice, water: &mut ShallowWater) {
+    let (width, height) = (lattice.width, lattice.height);
+
+    for j in 0..height {
+        for i in 0..width {
+            let n = lattice.index(i, j);
+
+            let depth = if i < width / 4 {
+                DAM_DEPTH
+            } else if i < 3 * width / 5 {
+                TAIL_DEPTH
+            } else {
+                water.bed[n] = STEP_HEIGHT;
+                0.
+            };
+
+            water.set(lattice, n, depth, [0., 0.]);
+        }
+    }
+}
+
+impl Simulation {
+    fn new(scenario: Scenario, mask: Option<&Mask>) -> Self {
+        let ib = ImmersedBoundary::new(Kernel::ThreePoint, 3);
+
+        let (mut lattice, particles) = match scenario {
+            Scenario::Flag => (init_channel(300, 100), vec![]),
+            Scenario::Sedimentation => (
+                init_box(101, 400, SEDIMENT_TAU),
+                vec![Particle::new(
+                    Shape::Disc(SEDIMENT_RADIUS),
+                    SEDIMENT_DENSITY,
+                    [50., 60.],
+                )],
+            ),
+            Scenario::Particles => (
+                init_box(151, 300, SEDIMENT_TAU),
+                vec![
+                    Particle::new(Shape::Disc(8.), SEDIMENT_DENSITY, [50., 40.]),
+                    Particle::new(Shape::Disc(6.), SEDIMENT_DENSITY, [60., 80.]),
+                    Particle::new(Shape::regular(3, 10.), SEDIMENT_DENSITY, [100., 40.]),
+                    Particle::new(
+                        Shape::Polygon(vec![[-12., -4.], [12., -4.], [12., 4.], [-12., 4.]]),
+                        SEDIMENT_DENSITY,
+                        [95., 90.],
+                    ),
+                ],
+            ),
+            Scenario::DamBreak => (init_box(400, 80, SW_TAU), vec![]),
+            Scenario::Refined => (init_periodic_channel(300, 100), vec![]),
+            Scenario::Periodic => (init_pressure_channel(150, 100), vec![]),
+            Scenario::Mask => (
+                init_mask(mask.expect("the mask scenario needs --mask")),
+                vec![],
+            ),
+        };
+
+        let center = [lattice.width as f32 / 5., lattice.height as f32 / 2.];
+
+        let cylinder = RigidBody::circle(center, RADIUS);
+        let filament = Filament::new([center[0] + RADIUS, center[1]], 0., FILAMENT_LENGTH, 1.);
+
+        let mut particles = Particles::new(&mut lattice, particles);
+        particles.gravity = [0., SEDIMENT_GRAVITY];
+
+        let mut water = ShallowWater::new(&lattice, SW_GRAVITY);
+        water.friction = BED_FRICTION;
+
+        if scenario == Scenario::DamBreak {
+            init_dam_break(&mut lattice, &mut water);
+        }
+
+        let mut refinement = Refinement::new();
+
+        if scenario == Scenario::Refined {
+            let [cx, cy] = center;
+
+            refinement.refine(&lattice, [40, 25, 160, 75]);
+            refinement.mark_solid(&mut lattice, |x, y| {
+                (x - cx).powi(2) + (y - cy).powi(2) < RADIUS * RADIUS
+            });
+        }
+
+        let mass = refinement.mass(&lattice);
+
+        Simulation {
+            scenario,
+            lattice,
+            ib,
+            cylinder,
+            filament,
+            particles,
+            water,
+            refinement,
+            outlet: Outlet::Sponge,
+            outflow: Outflow::new(),
+            mass,
+            oscillate: false,
+            t: 0,
+        }
+        .with_outlet(Outlet::Sponge)
+    }
+
+    /// Switches the right edge of the flag channel to `outlet`.
+    fn with_outlet(mut self, outlet: Outlet) -> Self {
+        self.outlet = outlet;
+        self.outflow.clear();
+
+        if self.scenario != Scenario::Flag {
+            return self;
+        }
+
+        let (width, height) = (self.lattice.width, self.lattice.height);
+
+        for j in 1..height - 1 {
+            let n = self.lattice.index(width - 1, j);
+
+            self.lattice.node_type[n] = match outlet {
+                Outlet::Sink => NodeType::Sink,
+                _ => NodeType::Fluid,
+            };
+        }
+
+        let sponge = Sponge {
+            width: SPONGE_WIDTH,
+            strength: SPONGE_STRENGTH,
+            rho: 1.,
+            u: [U_IN, 0.],
+        };
+
+        match outlet {
+            Outlet::Sink => {}
+            Outlet::Convective => {
+                self.outflow
+                    .set(Edge::Right, None, Some(Convective::Fixed(U_IN)))
+            }
+            Outlet::Orlanski => self
+                .outflow
+                .set(Edge::Right, None, Some(Convective::Orlanski)),
+            Outlet::Sponge => {
+                self.outflow
+                    .set(Edge::Right, Some(sponge), Some(Convective::Fixed(U_IN)))
+            }
+        }
+
+        self
+    }
+
+    fn step(&mut self) {
+        let lattice = &mut self.lattice;
+
+        match self.scenario {
+            Scenario::Flag => {
+                if self.oscillate {
+                    let omega = 2. * std::f32::consts::PI / PERIOD;
+                    self.cylinder.velocity[1] = AMPLITUDE * omega * (omega * self.t as f32).cos();
+                } else {
+                    self.cylinder.velocity[1] = 0.;
+                }
+
+                lattice.clear_force();
+                lattice.compute_macroscopic();
+
+                self.ib.apply(lattice, &mut self.cylinder.markers);
+                self.ib.apply(lattice, &mut self.filament.markers);
+
+                lattice.collide();
+                lattice.stream();
+
+                self.outflow.apply(lattice);
+
+                self.cylinder.advance();
+
+                self.filament.anchor = Some(self.cylinder.markers[0].pos);
+                self.filament.advance();
+            }
+            Scenario::Sedimentation | Scenario::Particles => {
+                lattice.compute_macroscopic();
+                lattice.collide();
+
+                self.particles.exchange_momentum(lattice);
+
+                lattice.stream();
+
+                self.particles.advance(lattice);
+            }
+            Scenario::DamBreak => self.water.step(lattice),
+            Scenario::Refined => self.refinement.step(lattice),
+            Scenario::Periodic | Scenario::Mask => lattice.step(),
+        }
+
+        self.t += 1;
+    }
+
+    fn status(&self) -> String {
+        match self.scenario {
+            Scenario::Flag => {
+                let [fx, fy] = self.cylinder.force();
+                let [gx, gy] = self.filament.force();
+                let q = 0.5 * U_IN * U_IN * 2. * RADIUS;
+
+                format!(
+                    "t: {} outlet: {} cylinder Cd: {:.2} Cl: {:.2} filament Cd: {:.2} Cl: {:.2}",
+                    self.t,
+                    self.outlet.name(),
+                    fx / q,
+                    fy / q,
+                    gx / q,
+                    gy / q
+                )
+            }
+            Scenario::Sedimentation => {
+                let disc = &self.particles.particles[0];
+
+                // half-way bounce-back puts the walls half a cell inside the box
+                let width = self.lattice.width as f32 - 2.;
+                let weight = (SEDIMENT_DENSITY - 1.) * disc.shape.area() * SEDIMENT_GRAVITY;
+                let expected = faxen_settling_velocity(
+                    SEDIMENT_RADIUS,
+                    width,
+                    self.lattice.viscosity(),
+                    weight,
+                );
+
+                let re = disc.velocity[1] * 2. * SEDIMENT_RADIUS / self.lattice.viscosity();
+
+                format!(
+                    "t: {} settling velocity: {:.5} Faxen: {:.5} error: {:.1}% Re: {:.2}",
+                    self.t,
+                    disc.velocity[1],
+                    expected,
+                    100. * (disc.velocity[1] / expected - 1.),
+                    re
+                )
+            }
+            Scenario::Particles | Scenario::Mask => format!("t: {}", self.t),
+            Scenario::DamBreak => {
+                let j = self.lattice.height / 2;
+                let front = (0..self.lattice.width)
+                    .rev()
+                    .find(|&i| self.lattice.rho[self.lattice.index(i, j)] > self.water.dry_depth)
+                    .unwrap_or(0);
+
+                format!(
+                    "t: {} volume: {:.2} front: {}",
+                    self.t,
+                    self.water.volume(&self.lattice),
+                    front
+                )
+            }
+            Scenario::Refined => format!(
+                "t: {} composite mass drift: {:.2e}",
+                self.t,
+                self.refinement.mass(&self.lattice) / self.mass - 1.
+            ),
+            Scenario::Periodic => {
+                // flow rate through the seam column
+                let flow: f32 = (0..self.lattice.height)
+                    .map(|j| self.lattice.index(0, j))
+                    .filter(|&n| !self.lattice.node_type[n].is_solid())
+                    .map(|n| self.lattice.rho[n] * self.lattice.u[n][0])
+                    .sum();
+
+                format!(
+                    "t: {} pressure drop: {:.1e} flow rate: {:.3} mean velocity: {:.4}",
+                    self.t,
+                    self.lattice.pressure_drop[0],
+                    flow,
+                    flow / (self.lattice.height - 2) as f32
+                )
+            }
+        }
+    }
+
+    /// Force the convergence check watches: drag and lift on the body, or the
+    /// hydrodynamic force on the particles.
+    fn force(&self) -> [f32; 2] {
+        match self.scenario {
+            Scenario::Flag => {
+                let [fx, fy] = self.cylinder.force();
+                let [gx, gy] = self.filament.force();
+
+                [fx + gx, fy + gy]
+            }
+            Scenario::Sedimentation | Scenario::Particles => self
+                .particles
+                .particles
+                .iter()
+                .fold([0., 0.], |[fx, fy], p| [fx + p.force[0], fy + p.force[1]]),
+            Scenario::DamBreak | Scenario::Refined | Scenario::Periodic | Scenario::Mask => {
+                [0., 0.]
+            }
+        }
+    }
+
+    fn convergence(&self) -> Convergence {
+        let mut convergence = Convergence::new(CONVERGENCE_INTERVAL, CONVERGENCE_WINDOW);
+
+        if self.scenario == Scenario::Flag && self.outlet == Outlet::Sponge {
+            convergence.sections = Some([1, self.lattice.width - SPONGE_WIDTH - 1]);
+        }
+
+        convergence
+    }
+
+    /// Sets the nodes of the coarse lattice under `shape` to `cell`. Nodes
+    /// that stop being solid start over at rest.
+    fn paint(&mut self, shape: &Geometry, cell: Cell) {
+        let lattice = &mut self.lattice;
+        let node_type = from_cell(cell);
+
+        for j in 0..lattice.height {
+            for i in 0..lattice.width {
+                if !shape.contains([i as f32, j as f32]) {
+                    continue;
+                }
+
+                let n = lattice.index(i, j);
+
+                if lattice.node_type[n].is_solid() && !node_type.is_solid() {
+                    lattice.f[n] = equilibrium(1., [0., 0.]);
+                    lattice.rho[n] = 1.;
+                    lattice.u[n] = [0., 0.];
+                }
+
+                lattice.node_type[n] = node_type;
+            }
+        }
+    }
+
+    /// Refined blocks of every level, coarsest first.
+    fn blocks(&self) -> Vec<&Block> {
+        let mut blocks: Vec<_> = self.refinement.blocks.iter().collect();
+        let mut k = 0;
+
+        while k < blocks.len() {
+            let children = blocks[k].children.iter();
+            blocks.extend(children);
+            k += 1;
+        }
+
+        blocks
+    }
+
+    fn watchdog(&self) -> Watchdog {
+        let mut watchdog = Watchdog::new(WATCHDOG_INTERVAL);
+        watchdog.snapshot = Some("watchdog_snapshot.csv".into());
+
+        if self.scenario == Scenario::DamBreak {
+            // shallow-water populations dip below zero in fast flow without
+            // harm, and its waves travel at sqrt(g h), not the sound speed
+            watchdog.min_population = f32::MIN;
+            watchdog.max_mach = 1.;
+        }
+
+        watchdog
+    }
+
+    fn markers(&self) -> Vec<[f32; 2]> {
+        match self.scenario {
+            Scenario::Flag => self
+                .cylinder
+                .markers
+                .iter()
+                .chain(self.filament.markers.iter())
+                .map(|m| m.pos)
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+fn node_color(node_type: NodeType, value: f32, view: Derived, scale: f32) -> Color {
+    match node_type {
+        NodeType::Boundary => WHITE,
+        NodeType::MovingWall(_) => LIGHTGRAY,
+        NodeType::Inflow(_) => RED,
+        NodeType::Sink => BLUE,
+        _ if view == Derived::Speed => hsl_to_rgb(0.66 * (1. - (value / scale).min(1.)), 0.8, 0.5),
+        _ => Color::from(colormap(value, scale, view.signed())),
+    }
+}
+
+fn fine_textures(sim: &Simulation) -> Vec<(Image, Texture2D)> {
+    sim.blocks()
+        .into_iter()
+        .map(|block| {
+            let image = Image::gen_image_color(
+                block.lattice.width as u16,
+                block.lattice.height as u16,
+                BLACK,
+            );
+            let texture = Texture2D::from_image(&image);
+            texture.set_filter(FilterMode::Nearest);
+
+            (image, texture)
+        })
+        .collect()
+}
+
+#[macroquad::main("2D Lattice Boltzmann")]
+async fn main() {
+    let mask = Mask::from_args();
+
+    let mut sim = match &mask {
+        Some(mask) => Simulation::new(Scenario::Mask, Some(mask)),
+        None => Simulation::new(Scenario::Flag, None),
+    };
+
+    let mut image =
+        Image::gen_image_color(sim.lattice.width as u16, sim.lattice.height as u16, BLACK);
+    let mut texture = Texture2D::from_image(&image);
+    texture.set_filter(FilterMode::Nearest);
+
+    let mut next = None;
+
+    let mut tracers = Tracers::new();
+    let mut emitter_start = None;
+
+    let mut view = Derived::Speed;
+
+    let mut watchdog = sim.watchdog();
+
+    let mut halted: Option<Report> = None;
+
+    let mut convergence = sim.convergence();
+    let mut converged: Option<(usize, State)> = None;
+    let mut auto_stop = true;
+
+    let mut fine = fine_textures(&sim);
+
+    // while painting, the mouse draws the brush's cells and erases with the
+    // right button instead of placing tracers
+    let mut painting = false;
+    let mut brush = Brush::new();
+
+    loop {
+        if let Some(scenario) = next.take() {
+            sim = Simulation::new(scenario, mask.as_ref());
+            tracers.clear();
+            watchdog = sim.watchdog();
+            halted = None;
+
+            convergence = sim.convergence();
+            converged = None;
+
+            texture.delete();
+
+            image =
+                Image::gen_image_color(sim.lattice.width as u16, sim.lattice.height as u16, BLACK);
+            texture = Texture2D::from_image(&image);
+            texture.set_filter(FilterMode::Nearest);
+
+            for (_, texture) in fine.drain(..) {
+                texture.delete();
+            }
+
+            fine = fine_textures(&sim);
+        }
+
+        for _ in 0..STEPS_PER_FRAME {
+            if halted.is_some() || converged.is_some() {
+                break;
+            }
+
+            sim.step();
+
+            if let Err(report) = watchdog.check(&sim.lattice, sim.t) {
+                eprintln!("{}", report);
+                halted = Some(report);
+            }
+
+            let state = convergence.update(&sim.lattice, sim.t, sim.force());
+
+            if auto_stop && state != State::Running {
+                println!("converged at step {}: {}", sim.t, state);
+                converged = Some((sim.t, state));
+            }
+        }
+
+        let lattice = &sim.lattice;
+
+        let field = VectorField::from_lattice(lattice);
+        tracers.advance(&field, STEPS_PER_FRAME as f32);
+
+        let (width, height) = (lattice.width, lattice.height);
+
+        let derived = view.compute(&field);
+
+        // colour scale: twice the inflow speed, or the settling speed; derived
+        // quantities are scaled by their largest magnitude
+        let scale = match (view, sim.scenario) {
+            (
+                Derived::Speed,
+                Scenario::Flag | Scenario::Refined | Scenario::Periodic | Scenario::Mask,
+            ) => 2. * U_IN,
+            (Derived::Speed, Scenario::DamBreak) => 0.2,
+            (Derived::Speed, _) => 0.01,
+            _ => derived.max_abs(),
+        };
+
+        for j in 0..height {
+            for i in 0..width {
+                let n = lattice.index(i, j);
+
+                let color = if sim.scenario == Scenario::DamBreak
+                    && !lattice.node_type[n].is_solid()
+                    && lattice.rho[n] < sim.water.dry_depth
+                {
+                    Color::new(0.45, 0.35, 0.2, 1.)
+                } else {
+                    node_color(lattice.node_type[n], derived.data[n], view, scale)
+                };
+
+                image.set_pixel(i as u32, j as u32, color);
+            }
+        }
+
+        texture.update(&image);
+
+        for (block, (image, texture)) in sim.blocks().into_iter().zip(fine.iter_mut()) {
+            let mut field = VectorField::from_lattice(&block.lattice);
+            field.spacing = [block.spacing, block.spacing];
+
+            let derived = view.compute(&field);
+
+            for q in 0..block.lattice.height {
+                for p in 0..block.lattice.width {
+                    let n = block.lattice.index(p, q);
+                    let color =
+                        node_color(block.lattice.node_type[n], derived.data[n], view, scale);
+
+                    image.set_pixel(p as u32, q as u32, color);
+                }
+            }
+
+            texture.update(image);
+        }
+
+        let cell_size = CELL_SIZE.min(0.9 * screen_height() / height as f32);
+
+        let x_off = screen_width() / 2. - width as f32 * cell_size / 2.;
+        let y_off = screen_height() / 2. - height as f32 * cell_size / 2.;
+
+        clear_background(BLACK);
+
+        draw_texture_ex(
+            texture,
+            x_off,
+            y_off,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(width as f32 * cell_size, height as f32 * cell_size)),
+                ..Default::default()
+            },
+        );
+
+        for (block, (_, texture)) in sim.blocks().into_iter().zip(fine.iter()) {
+            let s = block.spacing;
+            let x = x_off + (block.position[0] + 0.5 - 0.5 * s) * cell_size;
+            let y = y_off + (block.position[1] + 0.5 - 0.5 * s) * cell_size;
+            let w = block.lattice.width as f32 * s * cell_size;
+            let h = block.lattice.height as f32 * s * cell_size;
+
+            draw_texture_ex(
+                *texture,
+                x,
+                y,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(vec2(w, h)),
+                    ..Default::default()
+                },
+            );
+
+            draw_rectangle_lines(x, y, w, h, 1., GRAY);
+        }
+
+        for [x, y] in sim.markers() {
+            draw_circle(
+                x_off + (x + 0.5) * cell_size,
+                y_off + (y + 0.5) * cell_size,
+                cell_size / 3.,
+                WHITE,
+            );
+        }
+
+        for tracer in tracers.tracers.iter() {
+            let trail: Vec<_> = tracers.trail(tracer).collect();
+
+            for w in trail.windows(2) {
+                draw_line(
+                    x_off + (w[0][0] + 0.5) * cell_size,
+                    y_off + (w[0][1] + 0.5) * cell_size,
+                    x_off + (w[1][0] + 0.5) * cell_size,
+                    y_off + (w[1][1] + 0.5) * cell_size,
+                    1.,
+                    YELLOW,
+                );
+            }
+
+            draw_circle(
+                x_off + (tracer.pos[0] + 0.5) * cell_size,
+                y_off + (tracer.pos[1] + 0.5) * cell_size,
+                2.,
+                YELLOW,
+            );
+        }
+
+        draw_text(&sim.status(), 20., 20., 20., WHITE);
+
+        if let Some(report) = &halted {
+            draw_text(
+                &format!(
+                    "halted at step {}: {} at node ({}, {}), press R to restart",
+                    report.step, report.violation, report.i, report.j
+                ),
+                20.,
+                60.,
+                20.,
+                RED,
+            );
+        }
+
+        if let Some((step, state)) = &converged {
+            draw_text(
+                &format!("converged at step {}: {}, press K to continue", step, state),
+                20.,
+                60.,
+                20.,
+                GREEN,
+            );
+        }
+
+        draw_text(
+            &format!("view: {} (max {:.2e})", view.name(), derived.max_abs()),
+            20.,
+            40.,
+            20.,
+            WHITE,
+        );
+
+        let (mouse_x, mouse_y) = mouse_position();
+        let mouse = [
+            (mouse_x - x_off) / cell_size - 0.5,
+            (mouse_y - y_off) / cell_size - 0.5,
+        ];
+
+        if painting {
+            draw_circle_lines(mouse_x, mouse_y, brush.radius * cell_size, 1., YELLOW);
+            draw_text(
+                &format!("paint: {} radius {}", brush.cell.name(), brush.radius),
+                20.,
+                80.,
+                20.,
+                YELLOW,
+            );
+
+            if is_mouse_button_down(MouseButton::Left) {
+                sim.paint(&brush.shape(mouse), brush.cell);
+            } else if is_mouse_button_down(MouseButton::Right) {
+                sim.paint(&brush.shape(mouse), Cell::Fluid);
+            }
+        }
+
+        if !painting && is_mouse_button_pressed(MouseButton::Left) && field.contains(mouse) {
+            tracers.seed(mouse, field.sample(mouse));
+        }
+
+        if !painting && is_mouse_button_pressed(MouseButton::Right) {
+            emitter_start = Some(mouse);
+        }
+
+        if let Some(start) = emitter_start {
+            draw_line(
+                x_off + (start[0] + 0.5) * cell_size,
+                y_off + (start[1] + 0.5) * cell_size,
+                mouse_x,
+                mouse_y,
+                1.,
+                YELLOW,
+            );
+
+            if is_mouse_button_released(MouseButton::Right) {
+                tracers.emitters.push(Emitter {
+                    start,
+                    end: mouse,
+                    count: 10,
+                    interval: 50.,
+                });
+
+                emitter_start = None;
+            }
+        }
+
+        if is_key_pressed(KeyCode::I) {
+            tracers.response_time = if tracers.response_time > 0. {
+                0.
+            } else {
+                TRACER_RESPONSE
+            };
+        }
+
+        if is_key_pressed(KeyCode::C) {
+            tracers.clear();
+        }
+
+        if is_key_pressed(KeyCode::X) {
+            match tracers.write_csv("tracers.csv") {
+                Ok(()) => println!("wrote tracers.csv"),
+                Err(e) => println!("could not write tracers.csv: {}", e),
+            }
+        }
+
+        if is_key_pressed(KeyCode::V) {
+            view = view.next();
+        }
+
+        if is_key_pressed(KeyCode::E) {
+            let name = format!("{}.csv", view.name());
+
+            match derived.write_csv(&name) {
+                Ok(()) => println!("wrote {}", name),
+                Err(e) => println!("could not write {}: {}", name, e),
+            }
+
+            match field.write_csv("velocity.csv") {
+                Ok(()) => println!("wrote velocity.csv"),
+                Err(e) => println!("could not write velocity.csv: {}", e),
+            }
+        }
+
+        if is_key_pressed(KeyCode::R) {
+            next = Some(sim.scenario);
+        }
+
+        if is_key_pressed(KeyCode::Key1) {
+            next = Some(Scenario::Flag);
+        }
+
+        if is_key_pressed(KeyCode::Key2) {
+            next = Some(Scenario::Sedimentation);
+        }
+
+        if is_key_pressed(KeyCode::Key3) {
+            next = Some(Scenario::Particles);
+        }
+
+        if is_key_pressed(KeyCode::Key4) {
+            next = Some(Scenario::DamBreak);
+        }
+
+        if is_key_pressed(KeyCode::Key5) {
+            next = Some(Scenario::Refined);
+        }
+
+        if is_key_pressed(KeyCode::Key6) {
+            next = Some(Scenario::Periodic);
+        }
+
+        if is_key_pressed(KeyCode::Key7) && mask.is_some() {
+            next = Some(Scenario::Mask);
+        }
+
+        if is_key_pressed(KeyCode::B) {
+            let outlet = sim.outlet.next();
+            sim = sim.with_outlet(outlet);
+            convergence = sim.convergence();
+        }
+
+        if is_key_pressed(KeyCode::K) {
+            auto_stop = !auto_stop;
+            converged = None;
+        }
+
+        if is_key_pressed(KeyCode::P) {
+            painting = !painting;
+        }
+
+        if is_key_pressed(KeyCode::T) {
+            brush.cell = brush.cell.next();
+        }
+
+        if is_key_pressed(KeyCode::RightBracket) {
+            brush.grow();
+        }
+
+        if is_key_pressed(KeyCode::LeftBracket) {
+            brush.shrink();
+        }
+
+        if is_key_pressed(KeyCode::M) {
+            let lattice = &sim.lattice;
+            let mask = Mask::from_nodes(lattice.width, lattice.height, &lattice.node_type, to_cell);
+
+            match mask.save(MASK_FILE) {
+                Ok(()) => println!("wrote {}", MASK_FILE),
+                Err(e) => println!("could not write {}: {}", MASK_FILE, e),
+            }
+        }
+
+        if is_key_pressed(KeyCode::O) {
+            sim.oscillate = !sim.oscillate;
+        }
+
+        next_frame().await
+    }
+}
@@ -0,0 +1,237 @@
+//! Coarse-grained macroscopic fields of an FHP lattice: density, momentum,
+//! velocity and the velocity corrected for the lack of Galilean invariance.
+//!
+//! The momentum flux of a lattice gas carries the advective term with a
+//! factor `g(rho)` in front, `g = b_t / (2 b) (1 - 2 d) / (1 - d)` for `b`
+//! moving out of `b_t` channels at occupation `d`, so at uniform density the
+//! raw velocity `u` obeys the Navier-Stokes equations only after rescaling
+//! to `g u`. That corrected velocity is the one to compare with a continuum
+//! solution; the raw one is what the particles, and tracers, actually drift
+//! with.
+//!
+//! Nodes are weighted by a kernel of their distance to every sample point,
+//! walls excluded, and the sums are averaged over the most recent snapshots
+//! before the velocity is taken, so that it is the ratio of the mean momentum
+//! and mean density rather than the mean of noisy ratios.
+
+use std::collections::VecDeque;
+
+use crate::fhp::{self, Lattice, Model, NodeType, DIRECTIONS, VELOCITIES};
+use crate::field::{ScalarField, VectorField};
+
+/// Spatial averaging kernel, with its size in lattice units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kernel {
+    /// Equal weights within these many units of the sample point along x
+    /// and y, over a rectangle twice as wide and high.
+    Box([f32; 2]),
+    /// Gaussian of this standard deviation, cut off at three of them.
+    Gaussian(f32),
+}
+
+impl Kernel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kernel::Box(_) => "box",
+            Kernel::Gaussian(_) => "gaussian",
+        }
+    }
+
+    /// Distances along x and y beyond which every weight is zero.
+    fn reach(&self) -> [f32; 2] {
+        match *self {
+            Kernel::Box(r) => r,
+            Kernel::Gaussian(sigma) => [3. * sigma; 2],
+        }
+    }
+
+    fn weight(&self, d: [f32; 2]) -> f32 {
+        match *self {
+            Kernel::Box(r) => {
+                if d[0].abs() <= r[0] && d[1].abs() <= r[1] {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            Kernel::Gaussian(sigma) => {
+                let r2 = d[0] * d[0] + d[1] * d[1];
+
+                if r2 <= 9. * sigma * sigma {
+                    (-r2 / (2. * sigma * sigma)).exp()
+                } else {
+                    0.
+                }
+            }
+        }
+    }
+}
+
+/// Factor of the advective term of `model` at `rho` particles per node,
+/// which vanishes at half filling and is negative above it.
+pub fn galilean_factor(model: Model, rho: f32) -> f32 {
+    let channels = model.channels() as f32;
+    let d = rho / channels;
+
+    channels / (2. * DIRECTIONS as f32) * (1. - 2. * d) / (1. - d)
+}
+
+/// Averaged fields on every `stride`-th node of every `stride`-th row,
+/// sampled like the `VectorField`s the tracers and post-processing take.
+#[derive(Clone, Debug)]
+pub struct Fields {
+    /// Particles per node.
+    pub density: ScalarField,
+    /// Momentum per node.
+    pub momentum: VectorField,
+    pub velocity: VectorField,
+    /// `g(rho) u`, the velocity that obeys the Navier-Stokes equations.
+    pub corrected: VectorField,
+}
+
+pub struct CoarseGraining {
+    pub model: Model,
+    pub kernel: Kernel,
+    pub stride: usize,
+    /// Number of most recent snapshots averaged over.
+    pub frames: usize,
+    history: VecDeque<(ScalarField, VectorField)>,
+}
+
+impl CoarseGraining {
+    pub fn new(model: Model, kernel: Kernel, stride: usize, frames: usize) -> Self {
+        CoarseGraining {
+            model,
+            kernel,
+            stride,
+            frames,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Forgets the snapshots taken so far, as needed after the lattice
+    /// changes abruptly.
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Adds a snapshot of `lattice`, dropping the oldest ones beyond `frames`
+    /// and all of them if the sampling has changed since.
+    pub fn add(&mut self, lattice: &Lattice) {
+        let (width, height) = (
+            (lattice.width - 1) / self.stride + 1,
+            (lattice.height - 1) / self.stride + 1,
+        );
+        let spacing = [
+            self.stride as f32,
+            self.stride as f32 * fhp::position(0, 1)[1],
+        ];
+
+        let mut density = ScalarField::new(width, height, spacing);
+        let mut momentum = VectorField::new(width, height, spacing);
+
+        let reach = self.kernel.reach();
+        let rows = (reach[1] / fhp::position(0, 1)[1]).ceil() as usize;
+        let columns = reach[0].ceil() as usize + 1;
+
+        for j in 0..height {
+            for i in 0..width {
+                let (i, j) = (self.stride * i, self.stride * j);
+                let p = fhp::position(i, j);
+
+                let (mut weight, mut mass, mut m) = (0., 0., [0., 0.]);
+
+                for jj in j.saturating_sub(rows)..=(j + rows).min(lattice.height - 1) {
+                    for ii in i.saturating_sub(columns)..=(i + columns).min(lattice.width - 1) {
+                        let n = lattice.index(ii, jj);
+
+                        if let NodeType::Boundary(_) = lattice.node_type[n] {
+                            continue;
+                        }
+
+                        let q = fhp::position(ii, jj);
+                        let w = self.kernel.weight([q[0] - p[0], q[1] - p[1]]);
+
+                        if w == 0. {
+                            continue;
+                        }
+
+                        let s = lattice.nodes[n];
+
+                        for (d, c) in VELOCITIES.iter().enumerate() {
+                            let t = (s >> d & 1) as f32;
+
+                            m[0] += w * t * c[0];
+                            m[1] += w * t * c[1];
+                        }
+
+                        mass += w * fhp::mass(s) as f32;
+                        weight += w;
+                    }
+                }
+
+                if weight > 0. {
+                    density.set(i / self.stride, j / self.stride, mass / weight);
+                    momentum.set(
+                        i / self.stride,
+                        j / self.stride,
+                        [m[0] / weight, m[1] / weight],
+                    );
+                }
+            }
+        }
+
+        if let Some((last, _)) = self.history.back() {
+            if last.width != width || last.height != height {
+                self.history.clear();
+            }
+        }
+
+        self.history.push_back((density, momentum));
+
+        while self.history.len() > self.frames.max(1) {
+            self.history.pop_front();
+        }
+    }
+
+    /// Fields averaged over the snapshots added so far, or `None` before the
+    /// first.
+    pub fn fields(&self) -> Option<Fields> {
+        let (first, _) = self.history.front()?;
+        let (width, height, spacing) = (first.width, first.height, first.spacing);
+
+        let mut density = ScalarField::new(width, height, spacing);
+        let mut momentum = VectorField::new(width, height, spacing);
+        let frames = self.history.len() as f32;
+
+        for (rho, m) in self.history.iter() {
+            for k in 0..density.data.len() {
+                density.data[k] += rho.data[k] / frames;
+                momentum.data[k][0] += m.data[k][0] / frames;
+                momentum.data[k][1] += m.data[k][1] / frames;
+            }
+        }
+
+        let mut velocity = VectorField::new(width, height, spacing);
+        let mut corrected = velocity.clone();
+
+        for k in 0..density.data.len() {
+            let rho = density.data[k];
+
+            if rho > 0. {
+                let [mx, my] = momentum.data[k];
+                let g = galilean_factor(self.model, rho);
+
+                velocity.data[k] = [mx / rho, my / rho];
+                corrected.data[k] = [g * mx / rho, g * my / rho];
+            }
+        }
+
+        Some(Fields {
+            density,
+            momentum,
+            velocity,
+            corrected,
+        })
+    }
+}
@@ -0,0 +1,266 @@
+//! Steady-state and periodic-state detection, so parameter sweeps can stop
+//! once the flow has settled.
+//!
+//! Every `interval` steps a sample is taken: the relative L2 change of the
+//! velocity field since the previous sample, the mass-flux imbalance between
+//! two columns, and a force (typically the drag and
+//! lift on the body). The flow is steady when all three have stayed within
+//! their tolerances over the last `window` samples. Otherwise the force
+//! signal is checked for periodicity through its autocorrelation, which is
+//! how vortex shedding is recognized.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::d2q9::Lattice;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    Running,
+    Steady,
+    /// Periodic force signal with the given period in time steps, and the mean
+    /// and amplitude of the force over the last period.
+    Periodic {
+        period: f32,
+        mean: [f32; 2],
+        amplitude: [f32; 2],
+    },
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            State::Running => write!(f, "running"),
+            State::Steady => write!(f, "steady"),
+            State::Periodic {
+                period,
+                mean,
+                amplitude,
+            } => write!(
+                f,
+                "periodic, period {:.1} force mean [{:.3e}, {:.3e}] amplitude [{:.3e}, {:.3e}]",
+                period, mean[0], mean[1], amplitude[0], amplitude[1]
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    pub step: usize,
+    pub velocity_change: f32,
+    pub flux_residual: f32,
+    pub force: [f32; 2],
+}
+
+pub struct Convergence {
+    /// Steps between samples.
+    pub interval: usize,
+    /// Samples the criteria are evaluated over.
+    pub window: usize,
+    pub velocity_tolerance: f32,
+    pub flux_tolerance: f32,
+    /// Columns whose mass fluxes are compared, the first and last interior
+    /// ones if `None`. Keep them clear of sponge layers, which are not
+    /// conservative.
+    pub sections: Option<[usize; 2]>,
+    /// Relative spread of the force over the window, or drift of its mean
+    /// between the first and last period of a periodic state.
+    pub force_tolerance: f32,
+    /// Relative change of the oscillation amplitude between the first and last
+    /// period in the window, which rules out decaying transients.
+    pub amplitude_tolerance: f32,
+    /// Autocorrelation the force signal must reach at its period.
+    pub periodicity: f32,
+    pub samples: VecDeque<Sample>,
+    pub state: State,
+    previous: Option<Vec<[f32; 2]>>,
+}
+
+/// Relative L2 norm of the difference between two velocity fields.
+fn relative_change(old: &[[f32; 2]], new: &[[f32; 2]]) -> f32 {
+    let mut diff = 0.;
+    let mut norm = 0.;
+
+    for (a, b) in old.iter().zip(new.iter()) {
+        diff += (b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2);
+        norm += b[0] * b[0] + b[1] * b[1];
+    }
+
+    (diff / norm.max(f32::MIN_POSITIVE)).sqrt()
+}
+
+/// Relative imbalance of the mass flux through two columns.
+fn flux_residual(lattice: &Lattice, sections: [usize; 2]) -> f32 {
+    let flux = |i: usize| -> f32 {
+        (0..lattice.height)
+            .map(|j| lattice.index(i, j))
+            .filter(|&n| !lattice.node_type[n].is_solid())
+            .map(|n| lattice.rho[n] * lattice.u[n][0])
+            .sum()
+    };
+
+    let (inflow, outflow) = (flux(sections[0]), flux(sections[1]));
+
+    (inflow - outflow).abs() / inflow.abs().max(outflow.abs()).max(f32::MIN_POSITIVE)
+}
+
+/// Normalized autocorrelation of `x` at every lag up to half its length.
+fn autocorrelation(x: &[f32]) -> Vec<f32> {
+    let mean = x.iter().sum::<f32>() / x.len() as f32;
+    let d: Vec<_> = x.iter().map(|v| v - mean).collect();
+    let var: f32 = d.iter().map(|v| v * v).sum();
+
+    (0..x.len() / 2)
+        .map(|lag| {
+            let c: f32 = d.iter().zip(d[lag..].iter()).map(|(a, b)| a * b).sum();
+            c / var.max(f32::MIN_POSITIVE) * x.len() as f32 / (x.len() - lag) as f32
+        })
+        .collect()
+}
+
+impl Convergence {
+    pub fn new(interval: usize, window: usize) -> Self {
+        Convergence {
+            interval,
+            window,
+            velocity_tolerance: 1e-5,
+            flux_tolerance: 1e-3,
+            sections: None,
+            force_tolerance: 1e-3,
+            amplitude_tolerance: 1e-2,
+            periodicity: 0.9,
+            samples: VecDeque::new(),
+            state: State::Running,
+            previous: None,
+        }
+    }
+
+    /// Samples the lattice if `step` is due and re-evaluates the state.
+    pub fn update(&mut self, lattice: &Lattice, step: usize, force: [f32; 2]) -> State {
+        if self.interval == 0 || !step.is_multiple_of(self.interval) {
+            return self.state;
+        }
+
+        let velocity_change = match &self.previous {
+            Some(old) => relative_change(old, &lattice.u),
+            None => f32::INFINITY,
+        };
+
+        self.previous = Some(lattice.u.clone());
+
+        self.samples.push_back(Sample {
+            step,
+            velocity_change,
+            flux_residual: flux_residual(lattice, self.sections.unwrap_or([1, lattice.width - 2])),
+            force,
+        });
+
+        while self.samples.len() > self.window {
+            self.samples.pop_front();
+        }
+
+        self.state = self.evaluate();
+        self.state
+    }
+
+    fn evaluate(&self) -> State {
+        if self.samples.len() < self.window {
+            return State::Running;
+        }
+
+        let forces: Vec<_> = self.samples.iter().map(|s| s.force).collect();
+        let scale = forces
+            .iter()
+            .map(|f| f[0].abs().max(f[1].abs()))
+            .fold(0., f32::max)
+            .max(f32::MIN_POSITIVE);
+
+        let spread = |d: usize| {
+            let (lo, hi) = forces.iter().fold((f32::MAX, f32::MIN), |(lo, hi), f| {
+                (lo.min(f[d]), hi.max(f[d]))
+            });
+            (hi - lo) / scale
+        };
+
+        let steady_velocity = self
+            .samples
+            .iter()
+            .all(|s| s.velocity_change < self.velocity_tolerance);
+        let steady_flux = self
+            .samples
+            .iter()
+            .all(|s| s.flux_residual < self.flux_tolerance);
+        let steady_force = spread(0) < self.force_tolerance && spread(1) < self.force_tolerance;
+
+        if steady_velocity && steady_flux && steady_force {
+            return State::Steady;
+        }
+
+        self.periodic(&forces, scale).unwrap_or(State::Running)
+    }
+
+    /// Looks for a period in the force component that varies most.
+    fn periodic(&self, forces: &[[f32; 2]], scale: f32) -> Option<State> {
+        let variance = |d: usize| {
+            let mean = forces.iter().map(|f| f[d]).sum::<f32>() / forces.len() as f32;
+            forces.iter().map(|f| (f[d] - mean).powi(2)).sum::<f32>()
+        };
+
+        let d = if variance(1) > variance(0) { 1 } else { 0 };
+        let signal: Vec<_> = forces.iter().map(|f| f[d]).collect();
+        let r = autocorrelation(&signal);
+
+        // the period is the highest peak after the first zero crossing
+        let start = r.iter().position(|&c| c < 0.)?;
+        let lag = (start..r.len() - 1).max_by(|&a, &b| r[a].partial_cmp(&r[b]).unwrap())?;
+
+        if lag == 0 || r[lag] < self.periodicity || 2 * lag > forces.len() {
+            return None;
+        }
+
+        // parabolic refinement of the peak
+        let (a, b, c) = (r[lag - 1], r[lag], r[lag + 1]);
+        let shift = if a - 2. * b + c != 0. {
+            0.5 * (a - c) / (a - 2. * b + c)
+        } else {
+            0.
+        };
+
+        let n = forces.len();
+        let stats = |period: &[[f32; 2]]| {
+            let mut mean = [0., 0.];
+            let mut amplitude = [0., 0.];
+
+            for d in 0..2 {
+                let (lo, hi) = period.iter().fold((f32::MAX, f32::MIN), |(lo, hi), f| {
+                    (lo.min(f[d]), hi.max(f[d]))
+                });
+
+                mean[d] = period.iter().map(|f| f[d]).sum::<f32>() / period.len() as f32;
+                amplitude[d] = 0.5 * (hi - lo);
+            }
+
+            (mean, amplitude)
+        };
+
+        let (mean, amplitude) = stats(&forces[n - lag..]);
+        let (first_mean, first_amplitude) = stats(&forces[..lag]);
+
+        let settled = (0..2).all(|d| {
+            (mean[d] - first_mean[d]).abs() / scale < self.force_tolerance
+                && (amplitude[d] - first_amplitude[d]).abs()
+                    <= self.amplitude_tolerance * amplitude[d]
+        });
+
+        if !settled {
+            return None;
+        }
+
+        Some(State::Periodic {
+            period: (lag as f32 + shift) * self.interval as f32,
+            mean,
+            amplitude,
+        })
+    }
+}
@@ -0,0 +1,384 @@
+//! D2Q9 lattice Boltzmann solver with BGK collision and Guo forcing.
+//!
+//! The lattice is stored row-major (`width * j + i`), with `j` pointing down
+//! the screen like the lattice-gas front-ends.
+
+use rayon::prelude::*;
+
+use crate::geometry::Geometry;
+
+pub const Q: usize = 9;
+
+pub const E: [[isize; 2]; Q] = [
+    [0, 0],
+    [1, 0],
+    [0, 1],
+    [-1, 0],
+    [0, -1],
+    [1, 1],
+    [-1, 1],
+    [-1, -1],
+    [1, -1],
+];
+
+pub const W: [f32; Q] = [
+    4. / 9.,
+    1. / 9.,
+    1. / 9.,
+    1. / 9.,
+    1. / 9.,
+    1. / 36.,
+    1. / 36.,
+    1. / 36.,
+    1. / 36.,
+];
+
+pub const OPP: [usize; Q] = [0, 3, 4, 1, 2, 7, 8, 5, 6];
+
+pub const CS2: f32 = 1. / 3.;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NodeType {
+    Fluid,
+    Boundary,
+    /// Velocity inlet, imposed as the equilibrium at unit density.
+    Inflow([f32; 2]),
+    /// Pressure outlet at unit density, taking the velocity of its fluid neighbours.
+    Sink,
+    /// Solid node moving with the given velocity, bounced back with Ladd's correction.
+    MovingWall([f32; 2]),
+}
+
+impl NodeType {
+    pub fn is_solid(&self) -> bool {
+        matches!(self, NodeType::Boundary | NodeType::MovingWall(_))
+    }
+}
+
+pub fn equilibrium(rho: f32, u: [f32; 2]) -> [f32; Q] {
+    let uu = u[0] * u[0] + u[1] * u[1];
+    let mut feq = [0.; Q];
+
+    for (k, f) in feq.iter_mut().enumerate() {
+        let eu = E[k][0] as f32 * u[0] + E[k][1] as f32 * u[1];
+        *f = W[k] * rho * (1. + eu / CS2 + eu * eu / (2. * CS2 * CS2) - uu / (2. * CS2));
+    }
+
+    feq
+}
+
+/// Momentum added to population `k` when it bounces back off a wall moving with `v`.
+#[inline]
+pub fn moving_wall(k: usize, rho: f32, v: [f32; 2]) -> f32 {
+    2. * W[k] * rho * (E[k][0] as f32 * v[0] + E[k][1] as f32 * v[1]) / CS2
+}
+
+/// Guo forcing term for a node with velocity `u` under body force `force`.
+pub fn forcing(tau: f32, u: [f32; 2], force: [f32; 2]) -> [f32; Q] {
+    let mut s = [0.; Q];
+
+    for (k, s) in s.iter_mut().enumerate() {
+        let e = [E[k][0] as f32, E[k][1] as f32];
+        let eu = e[0] * u[0] + e[1] * u[1];
+
+        let gx = (e[0] - u[0]) / CS2 + eu * e[0] / (CS2 * CS2);
+        let gy = (e[1] - u[1]) / CS2 + eu * e[1] / (CS2 * CS2);
+
+        *s = (1. - 0.5 / tau) * W[k] * (gx * force[0] + gy * force[1]);
+    }
+
+    s
+}
+
+pub struct Lattice {
+    pub width: usize,
+    pub height: usize,
+    pub tau: f32,
+    pub f: Vec<[f32; Q]>,
+    f_new: Vec<[f32; Q]>,
+    pub node_type: Vec<NodeType>,
+    /// Body force per node, consumed by `compute_macroscopic` and `collide`.
+    pub force: Vec<[f32; 2]>,
+    pub rho: Vec<f32>,
+    pub u: Vec<[f32; 2]>,
+    /// Pressure drop imposed across the periodic seams along x and y.
+    /// Populations wrapping from the last column to the first gain their rest
+    /// share of the density `dp / cs^2`, and lose it going back, so the seam
+    /// pushes the fluid without adding mass.
+    pub pressure_drop: [f32; 2],
+    /// Where the link of each population crosses the wall it bounces off, as
+    /// a fraction of the link from its fluid node, for Bouzidi's interpolated
+    /// bounce-back. Empty, or `None` for a link, bounces half-way between the
+    /// nodes, and links into nodes that are no longer walls are ignored.
+    pub wall_fractions: Vec<[Option<f32>; Q]>,
+}
+
+impl Lattice {
+    pub fn new(width: usize, height: usize, tau: f32) -> Self {
+        let n = width * height;
+
+        Lattice {
+            width,
+            height,
+            tau,
+            f: vec![equilibrium(1., [0., 0.]); n],
+            f_new: vec![[0.; Q]; n],
+            node_type: vec![NodeType::Fluid; n],
+            force: vec![[0., 0.]; n],
+            rho: vec![1.; n],
+            u: vec![[0., 0.]; n],
+            pressure_drop: [0., 0.],
+            wall_fractions: vec![],
+        }
+    }
+
+    #[inline]
+    pub fn index(&self, i: usize, j: usize) -> usize {
+        self.width * j + i
+    }
+
+    /// Index of the node reached from `(i, j)` by moving `(di, dj)`, wrapping periodically.
+    #[inline]
+    pub fn neighbor(&self, i: usize, j: usize, di: isize, dj: isize) -> usize {
+        let ni = (i as isize + di).rem_euclid(self.width as isize) as usize;
+        let nj = (j as isize + dj).rem_euclid(self.height as isize) as usize;
+
+        self.index(ni, nj)
+    }
+
+    pub fn viscosity(&self) -> f32 {
+        CS2 * (self.tau - 0.5)
+    }
+
+    /// Sets every node to the equilibrium for `rho` and `u`.
+    pub fn fill(&mut self, rho: f32, u: [f32; 2]) {
+        let feq = equilibrium(rho, u);
+
+        self.f.iter_mut().for_each(|f| *f = feq);
+        self.rho.iter_mut().for_each(|r| *r = rho);
+        self.u.iter_mut().for_each(|v| *v = u);
+    }
+
+    /// Turns the nodes inside `shape` into walls, bounced back off its
+    /// surface where the links actually cross it rather than half-way.
+    pub fn set_obstacle(&mut self, shape: &Geometry) {
+        shape.fill(&mut self.node_type, self.width, NodeType::Boundary);
+
+        // population k arrives along E[k], so its link leaves against it
+        let mut directions = [[0.; 2]; Q];
+
+        for (d, e) in directions.iter_mut().zip(E.iter()) {
+            *d = [-e[0] as f32, -e[1] as f32];
+        }
+
+        let fractions = shape.link_fractions(self.width, self.height, &directions);
+
+        if self.wall_fractions.is_empty() {
+            self.wall_fractions = fractions;
+        } else {
+            for (q, new) in self.wall_fractions.iter_mut().zip(fractions) {
+                for (q, new) in q.iter_mut().zip(new.iter()) {
+                    *q = new.or(*q);
+                }
+            }
+        }
+    }
+
+    pub fn clear_force(&mut self) {
+        self.force.par_iter_mut().for_each(|f| *f = [0., 0.]);
+    }
+
+    /// Computes density and the half-force-corrected velocity from the populations.
+    pub fn compute_macroscopic(&mut self) {
+        let f = &self.f;
+        let force = &self.force;
+        let node_type = &self.node_type;
+
+        self.rho
+            .par_iter_mut()
+            .zip(self.u.par_iter_mut())
+            .enumerate()
+            .for_each(|(n, (rho, u))| {
+                match node_type[n] {
+                    NodeType::Boundary => {
+                        *rho = 1.;
+                        *u = [0., 0.];
+                        return;
+                    }
+                    NodeType::MovingWall(v) => {
+                        *rho = 1.;
+                        *u = v;
+                        return;
+                    }
+                    _ => {}
+                }
+
+                let mut r = 0.;
+                let mut m = [0.5 * force[n][0], 0.5 * force[n][1]];
+
+                for k in 0..Q {
+                    r += f[n][k];
+                    m[0] += f[n][k] * E[k][0] as f32;
+                    m[1] += f[n][k] * E[k][1] as f32;
+                }
+
+                *rho = r;
+                *u = [m[0] / r, m[1] / r];
+            });
+    }
+
+    /// BGK relaxation with Guo forcing, using the fields from `compute_macroscopic`.
+    pub fn collide(&mut self) {
+        let tau = self.tau;
+        let rho = &self.rho;
+        let u = &self.u;
+        let force = &self.force;
+        let node_type = &self.node_type;
+
+        self.f.par_iter_mut().enumerate().for_each(|(n, f)| {
+            if node_type[n] != NodeType::Fluid {
+                return;
+            }
+
+            let feq = equilibrium(rho[n], u[n]);
+            let s = forcing(tau, u[n], force[n]);
+
+            for k in 0..Q {
+                f[k] += (feq[k] - f[k]) / tau + s[k];
+            }
+        });
+    }
+
+    /// Pull-streaming with bounce-back on solid nodes, half-way or interpolated
+    /// along `wall_fractions`, followed by the inflow and sink conditions.
+    /// Domain edges are periodic.
+    pub fn stream(&mut self) {
+        self.stream_with(|_, _| false);
+        self.apply_open_boundaries();
+    }
+
+    /// Pull-streaming and bounce-back only. Populations arriving at `n` along
+    /// `k` for which `blocked(n, k)` holds are bounced back as if they came
+    /// from a wall.
+    pub(crate) fn stream_with<B: Fn(usize, usize) -> bool + Sync>(&mut self, blocked: B) {
+        let width = self.width;
+        let height = self.height;
+        let f = &self.f;
+        let rho = &self.rho;
+        let node_type = &self.node_type;
+        let fractions = &self.wall_fractions;
+        let jump = [self.pressure_drop[0] / CS2, self.pressure_drop[1] / CS2];
+
+        // +1 for a pull that wraps from the last column or row to the first
+        let wrap = |x: isize, len: usize| {
+            if x < 0 {
+                1.
+            } else if x >= len as isize {
+                -1.
+            } else {
+                0.
+            }
+        };
+
+        self.f_new
+            .par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(j, row)| {
+                for (i, f_new) in row.iter_mut().enumerate() {
+                    let n = width * j + i;
+
+                    if node_type[n].is_solid() {
+                        continue;
+                    }
+
+                    for k in 0..Q {
+                        let si = (i as isize - E[k][0]).rem_euclid(width as isize) as usize;
+                        let sj = (j as isize - E[k][1]).rem_euclid(height as isize) as usize;
+                        let s = width * sj + si;
+
+                        if blocked(n, k) {
+                            f_new[k] = f[n][OPP[k]];
+                            continue;
+                        }
+
+                        f_new[k] = match node_type[s] {
+                            NodeType::Boundary => match fractions.get(n).and_then(|q| q[k]) {
+                                Some(q) => {
+                                    // the node behind n, away from the wall
+                                    let bi = (i as isize + E[k][0]).rem_euclid(width as isize);
+                                    let bj = (j as isize + E[k][1]).rem_euclid(height as isize);
+                                    let b = width * bj as usize + bi as usize;
+
+                                    if q >= 0.5 {
+                                        (f[n][OPP[k]] + (2. * q - 1.) * f[n][k]) / (2. * q)
+                                    } else if node_type[b] == NodeType::Fluid {
+                                        2. * q * f[n][OPP[k]] + (1. - 2. * q) * f[b][OPP[k]]
+                                    } else {
+                                        f[n][OPP[k]]
+                                    }
+                                }
+                                None => f[n][OPP[k]],
+                            },
+                            NodeType::MovingWall(v) => f[n][OPP[k]] + moving_wall(k, rho[n], v),
+                            _ => {
+                                let d = wrap(i as isize - E[k][0], width) * jump[0]
+                                    + wrap(j as isize - E[k][1], height) * jump[1];
+
+                                f[s][k] + d * W[k]
+                            }
+                        };
+                    }
+                }
+            });
+
+        std::mem::swap(&mut self.f, &mut self.f_new);
+    }
+
+    fn apply_open_boundaries(&mut self) {
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let n = self.index(i, j);
+
+                match self.node_type[n] {
+                    NodeType::Inflow(u) => self.f[n] = equilibrium(1., u),
+                    NodeType::Sink => {
+                        let mut u = [0., 0.];
+                        let mut count = 0;
+
+                        for e in E.iter().skip(1) {
+                            let m = self.neighbor(i, j, e[0], e[1]);
+
+                            if self.node_type[m] == NodeType::Fluid {
+                                u[0] += self.u[m][0];
+                                u[1] += self.u[m][1];
+                                count += 1;
+                            }
+                        }
+
+                        if count > 0 {
+                            u = [u[0] / count as f32, u[1] / count as f32];
+                        }
+
+                        self.f[n] = equilibrium(1., u);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    pub fn step(&mut self) {
+        self.compute_macroscopic();
+        self.collide();
+        self.stream();
+    }
+
+    pub fn total_mass(&self) -> f32 {
+        self.f
+            .iter()
+            .zip(self.node_type.iter())
+            .filter(|(_, t)| !t.is_solid())
+            .map(|(f, _)| f.iter().sum::<f32>())
+            .sum()
+    }
+}
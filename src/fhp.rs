@@ -0,0 +1,779 @@
+//! Frisch-Hasslacher-Pomeau lattice gases on the hexagonal lattice.
+//!
+//! A node is a bit set of occupied channels: bit `d < 6` is a particle moving
+//! along `VELOCITIES[d]`, and FHP-II and FHP-III add a rest particle in bit 6.
+//! The collision tables are generated from the conservation laws instead of
+//! being written out by hand. The states of a node are grouped into classes of
+//! equal mass and momentum, each model decides which classes collide, and a
+//! colliding state moves to another member of its class chosen by a few random
+//! bits. The choice is uniform over the other members, so every collision is
+//! doubly stochastic and the models satisfy semi-detailed balance.
+
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+
+use crate::rng::CounterRng;
+use crate::wall::{self, Reflection};
+
+pub const DIRECTIONS: usize = 6;
+
+/// Bit of the rest particle in FHP-II and FHP-III.
+pub const REST: usize = 6;
+
+/// Moving channels and the rest channel.
+pub const CHANNELS: usize = DIRECTIONS + 1;
+
+/// Channels holding moving particles.
+pub const MOVING: u8 = 0b111111;
+
+/// Random bits consumed per collision.
+pub const RANDOM_BITS: u32 = 2;
+
+/// Unit velocities of the moving channels, with `y` pointing down the screen.
+pub const VELOCITIES: [[f32; 2]; DIRECTIONS] = [
+    [1., 0.],
+    [0.5, 0.866_025_4],
+    [-0.5, 0.866_025_4],
+    [-1., 0.],
+    [-0.5, -0.866_025_4],
+    [0.5, -0.866_025_4],
+];
+
+/// Velocities in units of `(1/2, sqrt(3)/2)`, so momenta compare exactly.
+const MOMENTA: [[i32; 2]; DIRECTIONS] = [[2, 0], [1, 1], [-1, 1], [-2, 0], [-1, -1], [1, -1]];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Model {
+    /// Six moving channels; head-on pairs, symmetric triples and their
+    /// four-particle duals collide.
+    FhpI,
+    /// FHP-I with a rest particle as spectator, plus a rest particle and a
+    /// moving one trading places with two particles at +-60 degrees.
+    FhpII,
+    /// Collision-saturated: every state with another state of equal mass and
+    /// momentum collides, rest particle included.
+    FhpIII,
+}
+
+impl Model {
+    pub fn channels(self) -> usize {
+        match self {
+            Model::FhpI => DIRECTIONS,
+            Model::FhpII | Model::FhpIII => DIRECTIONS + 1,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Model::FhpI => "FHP-I",
+            Model::FhpII => "FHP-II",
+            Model::FhpIII => "FHP-III",
+        }
+    }
+
+    /// Accepts `1`, `I`, `fhp-i` and so on, ignoring case.
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+
+        match name.trim_start_matches("fhp").trim_start_matches('-') {
+            "1" | "i" => Some(Model::FhpI),
+            "2" | "ii" => Some(Model::FhpII),
+            "3" | "iii" => Some(Model::FhpIII),
+            _ => None,
+        }
+    }
+
+    /// Collision class of `state`, or `None` if the model leaves it alone.
+    fn class(self, state: u8) -> Option<(u32, [i32; 2], u8)> {
+        let m = mass(state);
+        let p = momentum(state);
+
+        match self {
+            Model::FhpI => (p == [0, 0]).then_some((m, p, 0)),
+            Model::FhpII if p == [0, 0] => Some((m, p, state >> REST)),
+            Model::FhpII => (m == 2).then_some((m, p, 2)),
+            Model::FhpIII => Some((m, p, 0)),
+        }
+    }
+}
+
+pub fn mass(state: u8) -> u32 {
+    state.count_ones()
+}
+
+/// Momentum of the moving particles in units of `(1/2, sqrt(3)/2)`.
+pub fn momentum(state: u8) -> [i32; 2] {
+    (0..DIRECTIONS)
+        .filter(|&d| state >> d & 1 != 0)
+        .fold([0, 0], |[x, y], d| [x + MOMENTA[d][0], y + MOMENTA[d][1]])
+}
+
+/// Reverses every moving particle; rest particles stay put.
+#[inline]
+pub fn reverse(state: u8) -> u8 {
+    (state >> 3) & 0b111 | (state << 3) & 0b111000 | state & !MOVING
+}
+
+/// Reflects every moving particle with mirror `m` of `wall::mirror`; rest
+/// particles stay put.
+#[inline]
+pub fn reflect(state: u8, m: usize) -> u8 {
+    (0..DIRECTIONS).fold(state & !MOVING, |out, d| {
+        out | (state >> d & 1) << wall::mirrored(d, m, DIRECTIONS)
+    })
+}
+
+pub struct CollisionTable {
+    pub model: Model,
+    next: Vec<[u8; 1 << RANDOM_BITS]>,
+}
+
+impl CollisionTable {
+    pub fn new(model: Model) -> Self {
+        let states = 1usize << model.channels();
+
+        let mut classes: HashMap<_, Vec<u8>> = HashMap::new();
+
+        for s in 0..states as u8 {
+            if let Some(class) = model.class(s) {
+                classes.entry(class).or_default().push(s);
+            }
+        }
+
+        let mut next = vec![[0; 1 << RANDOM_BITS]; states];
+
+        for s in 0..states as u8 {
+            let members = match model.class(s) {
+                Some(class) => &classes[&class],
+                None => {
+                    next[s as usize] = [s; 1 << RANDOM_BITS];
+                    continue;
+                }
+            };
+
+            let m = members.len();
+            let i = members.iter().position(|&t| t == s).unwrap();
+
+            // classes have 1, 2, 3 or 5 members, so the random bits split
+            // evenly over the others
+            assert!(m == 1 || (1usize << RANDOM_BITS).is_multiple_of(m - 1));
+
+            for (r, out) in next[s as usize].iter_mut().enumerate() {
+                *out = if m == 1 {
+                    s
+                } else {
+                    members[(i + 1 + r % (m - 1)) % m]
+                };
+            }
+        }
+
+        CollisionTable { model, next }
+    }
+
+    /// Post-collision state, using the low `RANDOM_BITS` of `random`.
+    #[inline]
+    pub fn collide(&self, state: u8, random: u32) -> u8 {
+        self.next[state as usize][(random & ((1 << RANDOM_BITS) - 1)) as usize]
+    }
+}
+
+/// Fermi-Dirac equilibrium of `model` with `rho` particles per node moving
+/// at velocity `u`: the occupation probability of every channel, zero for
+/// channels the model lacks. The occupations are `1 / (1 + exp(a + b.c))`,
+/// with the multipliers `a` and `b` of the collision invariants found by
+/// Newton iteration.
+pub fn equilibrium(model: Model, rho: f32, u: [f32; 2]) -> [f32; CHANNELS] {
+    let channels = model.channels();
+    let target = [rho as f64, (rho * u[0]) as f64, (rho * u[1]) as f64];
+
+    // velocities of the channels, the rest channel last
+    let c = |k: usize| {
+        if k < DIRECTIONS {
+            [VELOCITIES[k][0] as f64, VELOCITIES[k][1] as f64]
+        } else {
+            [0., 0.]
+        }
+    };
+
+    let occupations = |x: [f64; 3]| -> Vec<f64> {
+        (0..channels)
+            .map(|k| 1. / (1. + (x[0] + x[1] * c(k)[0] + x[2] * c(k)[1]).exp()))
+            .collect()
+    };
+
+    let d = rho as f64 / channels as f64;
+    let mut x = [((1. - d) / d).ln(), 0., 0.];
+
+    for _ in 0..100 {
+        let n = occupations(x);
+
+        let mut residual = [-target[0], -target[1], -target[2]];
+        let mut jacobian = [[0.; 3]; 3];
+
+        for (k, &n) in n.iter().enumerate() {
+            let v = [1., c(k)[0], c(k)[1]];
+
+            for a in 0..3 {
+                residual[a] += n * v[a];
+
+                for b in 0..3 {
+                    jacobian[a][b] -= n * (1. - n) * v[a] * v[b];
+                }
+            }
+        }
+
+        if residual.iter().all(|r| r.abs() < 1e-12) {
+            break;
+        }
+
+        let step = solve3(jacobian, residual);
+
+        // damped so the multipliers cannot overshoot into saturation
+        let scale = 1f64.min(2. / step.iter().fold(0., |m: f64, s| m.max(s.abs())));
+
+        for a in 0..3 {
+            x[a] -= scale * step[a];
+        }
+    }
+
+    let n = occupations(x);
+    let mut p = [0.; CHANNELS];
+
+    for (k, &n) in n.iter().enumerate() {
+        p[if k < DIRECTIONS { k } else { REST }] = n as f32;
+    }
+
+    let mass: f32 = p.iter().sum();
+
+    assert!(
+        (mass - rho).abs() < 1e-4 * rho.max(1.),
+        "no {} equilibrium with density {} and velocity {:?}",
+        model.name(),
+        rho,
+        u
+    );
+
+    p
+}
+
+/// Solves `a x = b` by Cramer's rule.
+fn solve3(a: [[f64; 3]; 3], b: [f64; 3]) -> [f64; 3] {
+    let det = |m: [[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+
+    let d = det(a);
+    let mut x = [0.; 3];
+
+    for (k, x) in x.iter_mut().enumerate() {
+        let mut m = a;
+
+        for r in 0..3 {
+            m[r][k] = b[r];
+        }
+
+        *x = det(m) / d;
+    }
+
+    x
+}
+
+/// Collision bits for time step `step`, independent for every node, bit and
+/// step, in the per-word form both engines take. Drawing the chirality per
+/// node rather than once for the whole lattice avoids a global handedness.
+pub fn random_bits(
+    rng: CounterRng,
+    step: u64,
+) -> impl Fn(usize, usize) -> [u64; RANDOM_BITS as usize] + Sync + Copy {
+    move |j, w| {
+        let mut words = [0; RANDOM_BITS as usize];
+
+        for (b, word) in words.iter_mut().enumerate() {
+            *word = rng.bits([step, j as u64, (w * RANDOM_BITS as usize + b) as u64]);
+        }
+
+        words
+    }
+}
+
+/// Neighbour offsets `[di, dj]` per direction, for nodes in even and odd rows;
+/// odd rows sit half a node to the right.
+pub const OFFSETS: [[[isize; 2]; DIRECTIONS]; 2] = [
+    [[1, 0], [0, 1], [-1, 1], [-1, 0], [-1, -1], [0, -1]],
+    [[1, 0], [1, 1], [0, 1], [-1, 0], [0, -1], [1, -1]],
+];
+
+/// Position of node `(i, j)` in units of the link length, `y` pointing down.
+pub fn position(i: usize, j: usize) -> [f32; 2] {
+    [i as f32 + 0.5 * (j % 2) as f32, j as f32 * 0.866_025_4]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NodeType {
+    Fluid,
+    /// Wall, reflecting its particles as given.
+    Boundary(Reflection),
+    /// Redrawn every step, each channel occupied with the given probability,
+    /// usually an `equilibrium`.
+    Inflow([f32; CHANNELS]),
+    /// Absorbs every particle.
+    Sink,
+}
+
+/// Mirror of the wall at node `(i, j)`, with the lattice periodic.
+fn wall_mirror(
+    node_type: &[NodeType],
+    width: usize,
+    height: usize,
+    i: usize,
+    j: usize,
+) -> Option<usize> {
+    wall::mirror(&VELOCITIES, |d| {
+        let [di, dj] = OFFSETS[j % 2][d];
+        let ni = (i as isize + di).rem_euclid(width as isize) as usize;
+        let nj = (j as isize + dj).rem_euclid(height as isize) as usize;
+
+        !matches!(node_type[width * nj + ni], NodeType::Boundary(_))
+    })
+}
+
+/// Scalar FHP lattice, one node per byte, stored row-major (`width * j + i`)
+/// with periodic edges.
+///
+/// Random bits are supplied per 64-node word, `random(j, w)` giving one word
+/// per bit for nodes `64 w..64 w + 64` of row `j`, so this engine and
+/// `MultiSpin` can be fed exactly the same bits.
+pub struct Lattice {
+    pub width: usize,
+    pub height: usize,
+    pub nodes: Vec<u8>,
+    pub node_type: Vec<NodeType>,
+    new: Vec<u8>,
+}
+
+impl Lattice {
+    pub fn new(width: usize, height: usize) -> Self {
+        Lattice {
+            width,
+            height,
+            nodes: vec![0; width * height],
+            node_type: vec![NodeType::Fluid; width * height],
+            new: vec![0; width * height],
+        }
+    }
+
+    #[inline]
+    pub fn index(&self, i: usize, j: usize) -> usize {
+        self.width * j + i
+    }
+
+    pub fn collide<R>(&mut self, table: &CollisionTable, random: R)
+    where
+        R: Fn(usize, usize) -> [u64; RANDOM_BITS as usize] + Sync,
+    {
+        let (width, height) = (self.width, self.height);
+        let node_type = &self.node_type;
+
+        self.nodes.par_iter_mut().enumerate().for_each(|(n, s)| {
+            *s = match node_type[n] {
+                NodeType::Fluid => {
+                    let (i, j) = (n % width, n / width);
+                    let words = random(j, i / 64);
+
+                    let r = words
+                        .iter()
+                        .enumerate()
+                        .fold(0, |r, (b, w)| r | ((w >> (i % 64)) as u32 & 1) << b);
+
+                    table.collide(*s, r)
+                }
+                NodeType::Boundary(reflection) => {
+                    let (i, j) = (n % width, n / width);
+
+                    // diffuse walls reflect specularly where the first random
+                    // bit is set, as in `MultiSpin`
+                    let specular = match reflection {
+                        Reflection::BounceBack => false,
+                        Reflection::Specular => true,
+                        Reflection::Diffuse => random(j, i / 64)[0] >> (i % 64) & 1 != 0,
+                    };
+
+                    match wall_mirror(node_type, width, height, i, j) {
+                        Some(m) if specular => reflect(*s, m),
+                        _ => reverse(*s),
+                    }
+                }
+                NodeType::Inflow(_) | NodeType::Sink => 0,
+            }
+        });
+    }
+
+    /// Redraws the inflow nodes in storage order, occupying channel `d` where
+    /// `uniform(n, d)` falls below its probability.
+    pub fn inject<U: FnMut(usize, usize) -> f32>(&mut self, mut uniform: U) {
+        for n in 0..self.nodes.len() {
+            if let NodeType::Inflow(p) = self.node_type[n] {
+                for (d, p) in p.iter().enumerate() {
+                    if uniform(n, d) < *p {
+                        self.nodes[n] |= 1 << d;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn stream(&mut self) {
+        let (width, height) = (self.width, self.height);
+
+        self.new.iter_mut().for_each(|s| *s = 0);
+
+        for j in 0..height {
+            for i in 0..width {
+                let n = self.index(i, j);
+                let s = self.nodes[n];
+
+                // rest particles stay where they are
+                self.new[n] |= s & !MOVING;
+
+                for (d, &[di, dj]) in OFFSETS[j % 2].iter().enumerate() {
+                    if s >> d & 1 == 0 {
+                        continue;
+                    }
+
+                    let ni = (i as isize + di).rem_euclid(width as isize) as usize;
+                    let nj = (j as isize + dj).rem_euclid(height as isize) as usize;
+
+                    self.new[width * nj + ni] |= 1 << d;
+                }
+            }
+        }
+
+        std::mem::swap(&mut self.nodes, &mut self.new);
+    }
+
+    pub fn step<R, U>(&mut self, table: &CollisionTable, random: R, uniform: U)
+    where
+        R: Fn(usize, usize) -> [u64; RANDOM_BITS as usize] + Sync,
+        U: FnMut(usize, usize) -> f32,
+    {
+        self.collide(table, random);
+        self.inject(uniform);
+        self.stream();
+    }
+}
+
+/// A colliding state of the table, compiled for `MultiSpin`. Each output is a
+/// set of random-bit combinations and the channels that nodes in `state` flip
+/// when their random bits are one of them.
+struct Rule {
+    state: u8,
+    outputs: Vec<(u8, u8)>,
+}
+
+/// Multi-spin coded FHP lattice: every channel is a bitplane of `u64` words,
+/// 64 nodes to a word, with rows padded to whole words. Collisions are
+/// evaluated with boolean logic compiled from the `CollisionTable`, and
+/// streaming shifts the words along the rows. Given the same random bits it
+/// reproduces the scalar `Lattice` exactly.
+pub struct MultiSpin {
+    pub width: usize,
+    pub height: usize,
+    /// Words per row.
+    pub words: usize,
+    /// The channel bitplanes, interleaved word by word.
+    pub planes: Vec<[u64; CHANNELS]>,
+    fluid: Vec<u64>,
+    /// Boundary nodes that bounce their particles back.
+    wall: Vec<u64>,
+    /// Specular and diffuse boundary nodes, by mirror.
+    specular: Vec<[u64; DIRECTIONS]>,
+    diffuse: Vec<[u64; DIRECTIONS]>,
+    inflow: Vec<(usize, [f32; CHANNELS])>,
+    channels: usize,
+    rules: Vec<Rule>,
+    new: Vec<[u64; CHANNELS]>,
+}
+
+impl MultiSpin {
+    /// Packs the state and geometry of `lattice`, compiling `table`.
+    pub fn new(lattice: &Lattice, table: &CollisionTable) -> Self {
+        let (width, height) = (lattice.width, lattice.height);
+        let words = width.div_ceil(64);
+
+        let mut rules = vec![];
+
+        for s in 0..1u32 << table.model.channels() {
+            let s = s as u8;
+            let mut outputs: Vec<(u8, u8)> = vec![];
+
+            for r in 0..1 << RANDOM_BITS {
+                let flip = s ^ table.collide(s, r);
+
+                if flip == 0 {
+                    continue;
+                }
+
+                match outputs.iter_mut().find(|(_, f)| *f == flip) {
+                    Some((random, _)) => *random |= 1 << r,
+                    None => outputs.push((1 << r, flip)),
+                }
+            }
+
+            if !outputs.is_empty() {
+                rules.push(Rule { state: s, outputs });
+            }
+        }
+
+        let mut spin = MultiSpin {
+            width,
+            height,
+            words,
+            planes: vec![[0; CHANNELS]; words * height],
+            fluid: vec![0; words * height],
+            wall: vec![0; words * height],
+            specular: vec![[0; DIRECTIONS]; words * height],
+            diffuse: vec![[0; DIRECTIONS]; words * height],
+            inflow: vec![],
+            channels: table.model.channels(),
+            rules,
+            new: vec![[0; CHANNELS]; words * height],
+        };
+
+        for j in 0..height {
+            for i in 0..width {
+                let n = lattice.index(i, j);
+                let (k, bit) = (words * j + i / 64, 1 << (i % 64));
+
+                match lattice.node_type[n] {
+                    NodeType::Fluid => spin.fluid[k] |= bit,
+                    NodeType::Boundary(reflection) => {
+                        match (
+                            reflection,
+                            wall_mirror(&lattice.node_type, width, height, i, j),
+                        ) {
+                            (Reflection::Specular, Some(m)) => spin.specular[k][m] |= bit,
+                            (Reflection::Diffuse, Some(m)) => spin.diffuse[k][m] |= bit,
+                            _ => spin.wall[k] |= bit,
+                        }
+                    }
+                    NodeType::Inflow(p) => spin.inflow.push((n, p)),
+                    NodeType::Sink => {}
+                }
+
+                for (c, plane) in spin.planes[k].iter_mut().enumerate() {
+                    if lattice.nodes[n] >> c & 1 != 0 {
+                        *plane |= bit;
+                    }
+                }
+            }
+        }
+
+        spin
+    }
+
+    pub fn node(&self, i: usize, j: usize) -> u8 {
+        let planes = &self.planes[self.words * j + i / 64];
+
+        (0..CHANNELS).fold(0, |s, c| s | ((planes[c] >> (i % 64)) as u8 & 1) << c)
+    }
+
+    /// Unpacks the node states into `lattice`.
+    pub fn store(&self, lattice: &mut Lattice) {
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let n = lattice.index(i, j);
+                lattice.nodes[n] = self.node(i, j);
+            }
+        }
+    }
+
+    pub fn collide<R>(&mut self, random: R)
+    where
+        R: Fn(usize, usize) -> [u64; RANDOM_BITS as usize] + Sync,
+    {
+        let words = self.words;
+        let channels = self.channels;
+        let rules = &self.rules;
+        let fluid = &self.fluid;
+        let wall = &self.wall;
+        let specular = &self.specular;
+        let diffuse = &self.diffuse;
+
+        self.planes.par_iter_mut().enumerate().for_each(|(k, x)| {
+            let mirrors = specular[k]
+                .iter()
+                .chain(diffuse[k].iter())
+                .fold(0, |a, b| a | b);
+
+            if fluid[k] | wall[k] | mirrors == 0 {
+                *x = [0; CHANNELS];
+                return;
+            }
+
+            // every product of the channel bits and their complements
+            let mut minterms = [0u64; 1 << CHANNELS];
+            minterms[0] = !0;
+
+            for (c, &plane) in x.iter().enumerate().take(channels) {
+                for s in (0..1 << c).rev() {
+                    minterms[s | 1 << c] = minterms[s] & plane;
+                    minterms[s] &= !plane;
+                }
+            }
+
+            let bits = random(k / words, k % words);
+
+            let mut combination = [!0u64; 1 << RANDOM_BITS];
+
+            for (r, m) in combination.iter_mut().enumerate() {
+                for (b, &word) in bits.iter().enumerate() {
+                    *m &= if r >> b & 1 != 0 { word } else { !word };
+                }
+            }
+
+            // nodes whose random bits fall in any given set of combinations
+            let mut selected = [0u64; 1 << (1 << RANDOM_BITS)];
+
+            for set in 1..selected.len() {
+                selected[set] =
+                    selected[set & (set - 1)] | combination[set.trailing_zeros() as usize];
+            }
+
+            let mut flip = [0u64; CHANNELS];
+
+            for rule in rules.iter() {
+                let nodes = minterms[rule.state as usize];
+
+                if nodes == 0 {
+                    continue;
+                }
+
+                for &(random, mut channels) in rule.outputs.iter() {
+                    let hit = nodes & selected[random as usize];
+
+                    while channels != 0 {
+                        flip[channels.trailing_zeros() as usize] |= hit;
+                        channels &= channels - 1;
+                    }
+                }
+            }
+
+            // diffuse walls reflect specularly where the first random bit is
+            // set and bounce back elsewhere
+            let mut bounce = wall[k];
+            let mut mirror = specular[k];
+
+            for (m, &nodes) in diffuse[k].iter().enumerate() {
+                mirror[m] |= nodes & bits[0];
+                bounce |= nodes & !bits[0];
+            }
+
+            let old = *x;
+
+            for c in 0..CHANNELS {
+                if c < DIRECTIONS {
+                    let reflected = (0..DIRECTIONS).fold(0, |out, m| {
+                        out | mirror[m] & old[wall::mirrored(c, m, DIRECTIONS)]
+                    });
+
+                    x[c] = fluid[k] & (old[c] ^ flip[c])
+                        | bounce & old[(c + 3) % DIRECTIONS]
+                        | reflected;
+                } else {
+                    x[c] = fluid[k] & (old[c] ^ flip[c]) | (bounce | mirrors) & old[c];
+                }
+            }
+        });
+    }
+
+    /// Same as `Lattice::inject`, calling `uniform` in the same order.
+    pub fn inject<U: FnMut(usize, usize) -> f32>(&mut self, mut uniform: U) {
+        for &(n, p) in self.inflow.iter() {
+            let (i, j) = (n % self.width, n / self.width);
+            let k = self.words * j + i / 64;
+
+            for (d, p) in p.iter().enumerate() {
+                if uniform(n, d) < *p {
+                    self.planes[k][d] |= 1 << (i % 64);
+                }
+            }
+        }
+    }
+
+    /// Pulls every channel from its upstream row, shifted by the row parity's
+    /// offset with wrap-around at the row ends.
+    pub fn stream(&mut self) {
+        let (width, height, words) = (self.width, self.height, self.words);
+        let planes = &self.planes;
+
+        let last = if width % 64 == 0 {
+            !0
+        } else {
+            (1 << (width % 64)) - 1
+        };
+
+        self.new
+            .par_chunks_mut(words)
+            .enumerate()
+            .for_each(|(j, row)| {
+                for c in 0..CHANNELS {
+                    // the vertical step is the same for both parities
+                    let (di, js) = if c < DIRECTIONS {
+                        let dj = OFFSETS[0][c][1];
+                        let js = (j as isize - dj).rem_euclid(height as isize) as usize;
+
+                        (OFFSETS[js % 2][c][0], js)
+                    } else {
+                        (0, j)
+                    };
+
+                    let src = &planes[words * js..words * (js + 1)];
+
+                    for (w, out) in row.iter_mut().enumerate() {
+                        out[c] = match di {
+                            0 => src[w][c],
+                            // node i takes node i - 1
+                            1 => {
+                                let carry = if w > 0 {
+                                    src[w - 1][c] >> 63
+                                } else {
+                                    src[(width - 1) / 64][c] >> ((width - 1) % 64) & 1
+                                };
+
+                                src[w][c] << 1 | carry
+                            }
+                            // node i takes node i + 1
+                            _ => {
+                                let carry = if w + 1 < words {
+                                    src[w + 1][c] << 63
+                                } else {
+                                    (src[0][c] & 1) << ((width - 1) % 64)
+                                };
+
+                                src[w][c] >> 1 | carry
+                            }
+                        };
+
+                        if w + 1 == words {
+                            out[c] &= last;
+                        }
+                    }
+                }
+            });
+
+        std::mem::swap(&mut self.planes, &mut self.new);
+    }
+
+    pub fn step<R, U>(&mut self, random: R, uniform: U)
+    where
+        R: Fn(usize, usize) -> [u64; RANDOM_BITS as usize] + Sync,
+        U: FnMut(usize, usize) -> f32,
+    {
+        self.collide(random);
+        self.inject(uniform);
+        self.stream();
+    }
+}
@@ -0,0 +1,187 @@
+//! Regularly sampled 2D vector fields, the common currency between the
+//! solvers and the post-processing tools.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::d2q9::Lattice;
+
+#[derive(Clone, Debug)]
+pub struct VectorField {
+    pub width: usize,
+    pub height: usize,
+    /// Distance between neighbouring samples along x and y.
+    pub spacing: [f32; 2],
+    pub data: Vec<[f32; 2]>,
+}
+
+impl VectorField {
+    pub fn new(width: usize, height: usize, spacing: [f32; 2]) -> Self {
+        VectorField {
+            width,
+            height,
+            spacing,
+            data: vec![[0., 0.]; width * height],
+        }
+    }
+
+    /// Macroscopic velocity of the lattice, one sample per node.
+    pub fn from_lattice(lattice: &Lattice) -> Self {
+        VectorField {
+            width: lattice.width,
+            height: lattice.height,
+            spacing: [1., 1.],
+            data: lattice.u.clone(),
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, i: usize, j: usize) -> [f32; 2] {
+        self.data[self.width * j + i]
+    }
+
+    #[inline]
+    pub fn set(&mut self, i: usize, j: usize, v: [f32; 2]) {
+        self.data[self.width * j + i] = v;
+    }
+
+    /// Physical extent covered by the samples.
+    pub fn size(&self) -> [f32; 2] {
+        [
+            self.width.saturating_sub(1) as f32 * self.spacing[0],
+            self.height.saturating_sub(1) as f32 * self.spacing[1],
+        ]
+    }
+
+    pub fn contains(&self, pos: [f32; 2]) -> bool {
+        let [w, h] = self.size();
+
+        pos[0] >= 0. && pos[1] >= 0. && pos[0] <= w && pos[1] <= h
+    }
+
+    /// Writes one `x,y,u,v` line per sample.
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        writeln!(out, "x,y,u,v")?;
+
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let [u, v] = self.get(i, j);
+
+                writeln!(
+                    out,
+                    "{},{},{},{}",
+                    i as f32 * self.spacing[0],
+                    j as f32 * self.spacing[1],
+                    u,
+                    v
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Bilinear interpolation at a physical position, clamped to the sampled area.
+    pub fn sample(&self, pos: [f32; 2]) -> [f32; 2] {
+        let x = (pos[0] / self.spacing[0])
+            .max(0.)
+            .min(self.width.saturating_sub(1) as f32);
+        let y = (pos[1] / self.spacing[1])
+            .max(0.)
+            .min(self.height.saturating_sub(1) as f32);
+
+        let i = (x.floor() as usize).min(self.width.saturating_sub(2));
+        let j = (y.floor() as usize).min(self.height.saturating_sub(2));
+
+        let tx = x - i as f32;
+        let ty = y - j as f32;
+
+        let i1 = (i + 1).min(self.width.saturating_sub(1));
+        let j1 = (j + 1).min(self.height.saturating_sub(1));
+
+        let (a, b, c, d) = (
+            self.get(i, j),
+            self.get(i1, j),
+            self.get(i, j1),
+            self.get(i1, j1),
+        );
+
+        let mut v = [0., 0.];
+
+        for k in 0..2 {
+            v[k] = (1. - ty) * ((1. - tx) * a[k] + tx * b[k]) + ty * ((1. - tx) * c[k] + tx * d[k]);
+        }
+
+        v
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ScalarField {
+    pub width: usize,
+    pub height: usize,
+    pub spacing: [f32; 2],
+    pub data: Vec<f32>,
+}
+
+impl ScalarField {
+    pub fn new(width: usize, height: usize, spacing: [f32; 2]) -> Self {
+        ScalarField {
+            width,
+            height,
+            spacing,
+            data: vec![0.; width * height],
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, i: usize, j: usize) -> f32 {
+        self.data[self.width * j + i]
+    }
+
+    #[inline]
+    pub fn set(&mut self, i: usize, j: usize, value: f32) {
+        self.data[self.width * j + i] = value;
+    }
+
+    /// Smallest and largest finite value, or `None` if there is none.
+    pub fn range(&self) -> Option<(f32, f32)> {
+        self.data
+            .iter()
+            .filter(|x| x.is_finite())
+            .fold(None, |range, &x| match range {
+                Some((lo, hi)) => Some((x.min(lo), x.max(hi))),
+                None => Some((x, x)),
+            })
+    }
+
+    /// Largest finite magnitude, the natural scale for signed quantities, or
+    /// zero if no sample is finite.
+    pub fn max_abs(&self) -> f32 {
+        self.range().map_or(0., |(lo, hi)| lo.abs().max(hi.abs()))
+    }
+
+    /// Writes one `x,y,value` line per sample.
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        writeln!(out, "x,y,value")?;
+
+        for j in 0..self.height {
+            for i in 0..self.width {
+                writeln!(
+                    out,
+                    "{},{},{}",
+                    i as f32 * self.spacing[0],
+                    j as f32 * self.spacing[1],
+                    self.get(i, j)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
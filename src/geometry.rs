@@ -0,0 +1,582 @@
+//! Analytic obstacle shapes rasterized onto node-type grids.
+//!
+//! Shapes live in lattice units with `x` to the right and `y` down, like the
+//! node indices `(i, j)` and SVG user space, and combine into a tree of unions
+//! and differences. A node belongs to the shape if its position does; square
+//! lattices put node `(i, j)` at `(i, j)`, the hexagonal FHP lattice passes its
+//! own positions to `fill_at`.
+//!
+//! For solvers that place walls between nodes, `distance` is the signed
+//! distance to the surface (negative inside) and `link_fractions` the fraction
+//! of each lattice link from a fluid node to where it crosses the surface,
+//! which the D2Q9 solver bounces back from in `Lattice::set_obstacle`.
+
+use std::fmt;
+
+/// Segments per Bézier curve when flattening SVG paths.
+const CURVE_SEGMENTS: usize = 16;
+
+/// Bisection steps when locating the surface along a link.
+const BISECTIONS: usize = 24;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Geometry {
+    Circle {
+        center: [f32; 2],
+        radius: f32,
+    },
+    /// Closed rings filled with the even-odd rule, so rings inside rings are
+    /// holes.
+    Polygons(Vec<Vec<[f32; 2]>>),
+    Union(Vec<Geometry>),
+    /// Points of the first shape that are not in the second.
+    Difference(Box<Geometry>, Box<Geometry>),
+}
+
+/// NACA 4-digit airfoil section, as fractions of the chord.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Naca {
+    /// Maximum camber.
+    pub camber: f32,
+    /// Chordwise position of the maximum camber.
+    pub position: f32,
+    /// Maximum thickness.
+    pub thickness: f32,
+}
+
+impl Naca {
+    /// Section from its designation, e.g. `"2412"` or `"NACA 0012"`.
+    pub fn parse(code: &str) -> Option<Self> {
+        let code = code.trim();
+        let code = code
+            .strip_prefix("NACA")
+            .or_else(|| code.strip_prefix("naca"))
+            .unwrap_or(code)
+            .trim();
+
+        if code.len() != 4 || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let digit = |k: usize| (code.as_bytes()[k] - b'0') as f32;
+
+        Some(Naca {
+            camber: digit(0) / 100.,
+            position: digit(1) / 10.,
+            thickness: (10. * digit(2) + digit(3)) / 100.,
+        })
+    }
+
+    /// Mean camber line and its slope at `x` along a unit chord.
+    fn camber_line(&self, x: f32) -> (f32, f32) {
+        let (m, p) = (self.camber, self.position);
+
+        if m == 0. || p == 0. {
+            (0., 0.)
+        } else if x < p {
+            (
+                m / (p * p) * (2. * p * x - x * x),
+                2. * m / (p * p) * (p - x),
+            )
+        } else {
+            (
+                m / ((1. - p) * (1. - p)) * (1. - 2. * p + 2. * p * x - x * x),
+                2. * m / ((1. - p) * (1. - p)) * (p - x),
+            )
+        }
+    }
+
+    /// Outline on a unit chord from the trailing edge over the upper surface
+    /// to the leading edge at the origin and back along the lower surface,
+    /// with `points` cosine-spaced stations per surface and `y` pointing up.
+    /// The trailing edge is closed (last thickness coefficient -0.1036).
+    pub fn outline(&self, points: usize) -> Vec<[f32; 2]> {
+        let t = self.thickness;
+
+        let surfaces = |x: f32| {
+            let half = 5.
+                * t
+                * (0.2969 * x.sqrt() - 0.126 * x - 0.3516 * x * x + 0.2843 * x.powi(3)
+                    - 0.1036 * x.powi(4));
+            let (yc, slope) = self.camber_line(x);
+            let (sin, cos) = slope.atan().sin_cos();
+
+            (
+                [x - half * sin, yc + half * cos],
+                [x + half * sin, yc - half * cos],
+            )
+        };
+
+        let x = |k: usize| 0.5 * (1. - (k as f32 / points as f32 * std::f32::consts::PI).cos());
+
+        let upper = (1..=points).rev().map(|k| surfaces(x(k)).0);
+        let lower = (0..points).map(|k| surfaces(x(k)).1);
+
+        upper.chain(lower).collect()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PathError {
+    /// Character that starts no command or number, with its byte offset.
+    Unexpected { offset: usize, found: char },
+    /// Arcs are not supported.
+    Arc { offset: usize },
+    /// A command ran out of numbers.
+    MissingNumber { command: char },
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathError::Unexpected { offset, found } => {
+                write!(f, "unexpected {:?} at offset {}", found, offset)
+            }
+            PathError::Arc { offset } => write!(f, "arcs are not supported (offset {})", offset),
+            PathError::MissingNumber { command } => {
+                write!(f, "command {} is missing a number", command)
+            }
+        }
+    }
+}
+
+fn rotate(p: [f32; 2], angle: f32, center: [f32; 2]) -> [f32; 2] {
+    let (sin, cos) = angle.sin_cos();
+    let (x, y) = (p[0] - center[0], p[1] - center[1]);
+
+    [center[0] + cos * x - sin * y, center[1] + sin * x + cos * y]
+}
+
+/// Distance from `p` to the segment from `a` to `b`.
+fn segment_distance(p: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let length = dx * dx + dy * dy;
+
+    let s = if length > 0. {
+        (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / length).clamp(0., 1.)
+    } else {
+        0.
+    };
+
+    ((p[0] - a[0] - s * dx).powi(2) + (p[1] - a[1] - s * dy).powi(2)).sqrt()
+}
+
+impl Geometry {
+    pub fn circle(center: [f32; 2], radius: f32) -> Self {
+        Geometry::Circle { center, radius }
+    }
+
+    pub fn polygon(vertices: Vec<[f32; 2]>) -> Self {
+        Geometry::Polygons(vec![vertices])
+    }
+
+    /// Axis-aligned rectangle between two opposite corners.
+    pub fn rectangle(a: [f32; 2], b: [f32; 2]) -> Self {
+        Geometry::polygon(vec![a, [b[0], a[1]], b, [a[0], b[1]]])
+    }
+
+    /// Airfoil with its leading edge at `leading_edge`, pitched nose-up by
+    /// `angle_of_attack` (radians) about the quarter chord for a flow along
+    /// `+x`.
+    pub fn airfoil(naca: Naca, leading_edge: [f32; 2], chord: f32, angle_of_attack: f32) -> Self {
+        let [x0, y0] = leading_edge;
+
+        let outline = naca
+            .outline(64)
+            .into_iter()
+            .map(|[x, y]| [x0 + chord * x, y0 - chord * y])
+            .collect();
+
+        // y points down, so a clockwise turn on screen lifts the nose
+        Geometry::polygon(outline).rotate(angle_of_attack, [x0 + 0.25 * chord, y0])
+    }
+
+    /// Shape described by the `d` attribute of an SVG path, with curves
+    /// flattened into straight segments. Every subpath is closed. Supports
+    /// the move, line, cubic and quadratic Bézier and close commands in
+    /// absolute and relative form, but not arcs.
+    pub fn svg_path(d: &str) -> Result<Self, PathError> {
+        let mut rings: Vec<Vec<[f32; 2]>> = vec![];
+        let mut ring: Vec<[f32; 2]> = vec![];
+
+        let mut pos = [0., 0.];
+        let mut start = [0., 0.];
+        // last control point of the previous curve and whether it was cubic,
+        // reflected by the smooth variants of the same kind
+        let mut control: Option<(bool, [f32; 2])> = None;
+
+        let mut chars = d.char_indices().peekable();
+        let mut command: Option<char> = None;
+
+        let mut close = |ring: &mut Vec<[f32; 2]>| {
+            if ring.len() > 2 {
+                rings.push(std::mem::take(ring));
+            } else {
+                ring.clear();
+            }
+        };
+
+        loop {
+            while let Some(&(_, c)) = chars.peek() {
+                if c.is_whitespace() || c == ',' {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+
+            let (offset, c) = match chars.peek() {
+                Some(&next) => next,
+                None => break,
+            };
+
+            if c.is_ascii_alphabetic() {
+                chars.next();
+
+                match c {
+                    'A' | 'a' => return Err(PathError::Arc { offset }),
+                    'Z' | 'z' => {
+                        close(&mut ring);
+                        pos = start;
+                        control = None;
+                        command = None;
+                        continue;
+                    }
+                    'M' | 'm' | 'L' | 'l' | 'H' | 'h' | 'V' | 'v' | 'C' | 'c' | 'S' | 's' | 'Q'
+                    | 'q' | 'T' | 't' => command = Some(c),
+                    _ => return Err(PathError::Unexpected { offset, found: c }),
+                }
+            }
+
+            let cmd = match command {
+                Some(cmd) => cmd,
+                None => return Err(PathError::Unexpected { offset, found: c }),
+            };
+
+            let arity = match cmd.to_ascii_uppercase() {
+                'H' | 'V' => 1,
+                'M' | 'L' | 'T' => 2,
+                'S' | 'Q' => 4,
+                _ => 6,
+            };
+
+            let mut v = [0.; 6];
+
+            for value in v.iter_mut().take(arity) {
+                *value = number(&mut chars).ok_or(PathError::MissingNumber { command: cmd })?;
+            }
+
+            // relative coordinates are offsets from the current point
+            let origin = if cmd.is_ascii_lowercase() {
+                pos
+            } else {
+                [0., 0.]
+            };
+            let point = |k: usize| [origin[0] + v[2 * k], origin[1] + v[2 * k + 1]];
+
+            let cubic = matches!(cmd.to_ascii_uppercase(), 'C' | 'S');
+            let reflected = match control {
+                Some((kind, c)) if kind == cubic => [2. * pos[0] - c[0], 2. * pos[1] - c[1]],
+                _ => pos,
+            };
+
+            let (end, curve) = match cmd.to_ascii_uppercase() {
+                'M' => {
+                    close(&mut ring);
+                    start = point(0);
+                    ring.push(start);
+                    pos = start;
+                    control = None;
+
+                    // further coordinate pairs are implicit line-tos
+                    command = Some(if cmd == 'M' { 'L' } else { 'l' });
+                    continue;
+                }
+                'L' => (point(0), None),
+                'H' => ([origin[0] + v[0], pos[1]], None),
+                'V' => ([pos[0], origin[1] + v[0]], None),
+                'C' => (point(2), Some([point(0), point(1)])),
+                'S' => (point(1), Some([reflected, point(0)])),
+                'Q' => {
+                    let q = point(0);
+                    (point(1), Some(quadratic(pos, q, point(1))))
+                }
+                _ => {
+                    let q = reflected;
+                    (point(0), Some(quadratic(pos, q, point(0))))
+                }
+            };
+
+            if ring.is_empty() {
+                ring.push(pos);
+            }
+
+            match curve {
+                Some([c1, c2]) => {
+                    for k in 1..=CURVE_SEGMENTS {
+                        let s = k as f32 / CURVE_SEGMENTS as f32;
+                        let r = 1. - s;
+                        let b = [r * r * r, 3. * r * r * s, 3. * r * s * s, s * s * s];
+
+                        ring.push([
+                            b[0] * pos[0] + b[1] * c1[0] + b[2] * c2[0] + b[3] * end[0],
+                            b[0] * pos[1] + b[1] * c1[1] + b[2] * c2[1] + b[3] * end[1],
+                        ]);
+                    }
+
+                    // quadratic commands remember their own control point
+                    control = Some(match cmd.to_ascii_uppercase() {
+                        'Q' => (false, point(0)),
+                        'T' => (false, reflected),
+                        _ => (true, c2),
+                    });
+                }
+                None => {
+                    ring.push(end);
+                    control = None;
+                }
+            }
+
+            pos = end;
+        }
+
+        close(&mut ring);
+
+        Ok(Geometry::Polygons(rings))
+    }
+
+    pub fn union(self, other: Geometry) -> Self {
+        match self {
+            Geometry::Union(mut shapes) => {
+                shapes.push(other);
+                Geometry::Union(shapes)
+            }
+            shape => Geometry::Union(vec![shape, other]),
+        }
+    }
+
+    pub fn difference(self, other: Geometry) -> Self {
+        Geometry::Difference(Box::new(self), Box::new(other))
+    }
+
+    fn map<F: Fn([f32; 2]) -> [f32; 2]>(self, f: &F, scale: f32) -> Self {
+        match self {
+            Geometry::Circle { center, radius } => Geometry::Circle {
+                center: f(center),
+                radius: radius * scale,
+            },
+            Geometry::Polygons(rings) => Geometry::Polygons(
+                rings
+                    .into_iter()
+                    .map(|ring| ring.into_iter().map(f).collect())
+                    .collect(),
+            ),
+            Geometry::Union(shapes) => {
+                Geometry::Union(shapes.into_iter().map(|s| s.map(f, scale)).collect())
+            }
+            Geometry::Difference(a, b) => {
+                Geometry::Difference(Box::new(a.map(f, scale)), Box::new(b.map(f, scale)))
+            }
+        }
+    }
+
+    pub fn translate(self, offset: [f32; 2]) -> Self {
+        self.map(&|p| [p[0] + offset[0], p[1] + offset[1]], 1.)
+    }
+
+    /// Rotation by `angle` radians about `center`, from `+x` towards `+y`,
+    /// which is clockwise on screen.
+    pub fn rotate(self, angle: f32, center: [f32; 2]) -> Self {
+        self.map(&|p| rotate(p, angle, center), 1.)
+    }
+
+    pub fn scale(self, factor: f32, center: [f32; 2]) -> Self {
+        self.map(
+            &|p| {
+                [
+                    center[0] + factor * (p[0] - center[0]),
+                    center[1] + factor * (p[1] - center[1]),
+                ]
+            },
+            factor,
+        )
+    }
+
+    pub fn contains(&self, p: [f32; 2]) -> bool {
+        match self {
+            Geometry::Circle { center, radius } => {
+                (p[0] - center[0]).powi(2) + (p[1] - center[1]).powi(2) <= radius * radius
+            }
+            Geometry::Polygons(rings) => {
+                let mut inside = false;
+
+                for v in rings {
+                    let n = v.len();
+
+                    for k in 0..n {
+                        let (a, b) = (v[k], v[(k + n - 1) % n]);
+
+                        if (a[1] > p[1]) != (b[1] > p[1])
+                            && p[0] < (b[0] - a[0]) * (p[1] - a[1]) / (b[1] - a[1]) + a[0]
+                        {
+                            inside = !inside;
+                        }
+                    }
+                }
+
+                inside
+            }
+            Geometry::Union(shapes) => shapes.iter().any(|s| s.contains(p)),
+            Geometry::Difference(a, b) => a.contains(p) && !b.contains(p),
+        }
+    }
+
+    /// Signed distance to the surface, negative inside. Exact for circles and
+    /// polygons; unions and differences give a bound that is exact near the
+    /// surface of each part.
+    pub fn distance(&self, p: [f32; 2]) -> f32 {
+        match self {
+            Geometry::Circle { center, radius } => {
+                ((p[0] - center[0]).powi(2) + (p[1] - center[1]).powi(2)).sqrt() - radius
+            }
+            Geometry::Polygons(rings) => {
+                let d = rings
+                    .iter()
+                    .flat_map(|v| {
+                        let n = v.len();
+                        (0..n).map(move |k| segment_distance(p, v[k], v[(k + 1) % n]))
+                    })
+                    .fold(f32::INFINITY, f32::min);
+
+                if self.contains(p) {
+                    -d
+                } else {
+                    d
+                }
+            }
+            Geometry::Union(shapes) => shapes
+                .iter()
+                .map(|s| s.distance(p))
+                .fold(f32::INFINITY, f32::min),
+            Geometry::Difference(a, b) => a.distance(p).max(-b.distance(p)),
+        }
+    }
+
+    /// Sets every node inside the shape to `value`, with node `(i, j)` at
+    /// `(i, j)`. Node types are row-major with rows of `width` nodes.
+    pub fn fill<T: Copy>(&self, node_type: &mut [T], width: usize, value: T) {
+        self.fill_at(node_type, width, |i, j| [i as f32, j as f32], value);
+    }
+
+    /// Like `fill`, with node `(i, j)` at `position(i, j)`.
+    pub fn fill_at<T: Copy, P: Fn(usize, usize) -> [f32; 2]>(
+        &self,
+        node_type: &mut [T],
+        width: usize,
+        position: P,
+        value: T,
+    ) {
+        for (n, node) in node_type.iter_mut().enumerate() {
+            if self.contains(position(n % width, n / width)) {
+                *node = value;
+            }
+        }
+    }
+
+    /// Fraction of the link from `p` to `p + e` at which it enters the shape,
+    /// in `(0, 1]`, if `p` is outside and `p + e` inside.
+    pub fn link_fraction(&self, p: [f32; 2], e: [f32; 2]) -> Option<f32> {
+        let at = |s: f32| [p[0] + s * e[0], p[1] + s * e[1]];
+
+        if self.contains(p) || !self.contains(at(1.)) {
+            return None;
+        }
+
+        let (mut lo, mut hi) = (0., 1.);
+
+        for _ in 0..BISECTIONS {
+            let mid = 0.5 * (lo + hi);
+
+            if self.contains(at(mid)) {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+
+        Some(0.5 * (lo + hi))
+    }
+
+    /// `link_fraction` of every node of a `width` by `height` square lattice
+    /// along each of `directions`, row-major.
+    pub fn link_fractions<const N: usize>(
+        &self,
+        width: usize,
+        height: usize,
+        directions: &[[f32; 2]; N],
+    ) -> Vec<[Option<f32>; N]> {
+        (0..width * height)
+            .map(|n| {
+                let p = [(n % width) as f32, (n / width) as f32];
+                let mut q = [None; N];
+
+                for (q, &e) in q.iter_mut().zip(directions.iter()) {
+                    *q = self.link_fraction(p, e);
+                }
+
+                q
+            })
+            .collect()
+    }
+}
+
+/// Control points of the cubic equal to the quadratic Bézier `a, q, b`.
+fn quadratic(a: [f32; 2], q: [f32; 2], b: [f32; 2]) -> [[f32; 2]; 2] {
+    [
+        [
+            a[0] + 2. / 3. * (q[0] - a[0]),
+            a[1] + 2. / 3. * (q[1] - a[1]),
+        ],
+        [
+            b[0] + 2. / 3. * (q[0] - b[0]),
+            b[1] + 2. / 3. * (q[1] - b[1]),
+        ],
+    ]
+}
+
+/// Parses the SVG number at the front of `chars`, which may run straight into
+/// the next one as in `1.5.5` or `2-3`.
+fn number<I: Iterator<Item = (usize, char)>>(chars: &mut std::iter::Peekable<I>) -> Option<f32> {
+    while let Some(&(_, c)) = chars.peek() {
+        if c.is_whitespace() || c == ',' {
+            chars.next();
+        } else {
+            break;
+        }
+    }
+
+    let mut text = String::new();
+    let mut dot = false;
+    let mut exponent = false;
+
+    while let Some(&(_, c)) = chars.peek() {
+        let accept = match c {
+            '0'..='9' => true,
+            '+' | '-' => text.is_empty() || text.ends_with('e') || text.ends_with('E'),
+            '.' => !dot && !exponent,
+            'e' | 'E' => !exponent && text.chars().any(|c| c.is_ascii_digit()),
+            _ => false,
+        };
+
+        if !accept {
+            break;
+        }
+
+        dot |= c == '.';
+        exponent |= c == 'e' || c == 'E';
+        text.push(c);
+        chars.next();
+    }
+
+    text.parse().ok()
+}
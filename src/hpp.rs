@@ -0,0 +1,664 @@
+//! Hardy-Pomeau-de Pazzis lattice gas on the square lattice.
+//!
+//! A node holds one bit per direction. Head-on pairs turn by a right angle,
+//! every other state passes through unchanged, which conserves mass, momentum
+//! and energy but leaves HPP without the isotropy of FHP.
+//!
+//! Each of the four edges of the lattice is periodic, reflecting or open.
+//! Particles crossing a periodic edge come back in across the opposite one,
+//! which must be periodic too; a reflecting edge turns them back on the node
+//! they would have left, and an open one absorbs them and lets nothing in.
+//!
+//! `Lattice` is multi-spin coded like FHP's `MultiSpin`: every direction is a
+//! bitplane of `u64` words, 64 nodes to a word, collisions are boolean logic
+//! on whole words and streaming shifts them, with rows handled in parallel.
+//! Only the fluid mask is stored densely; walls, sinks and inflow are kept
+//! for the words that hold them, so lattices of `10^8` nodes fit in a few
+//! hundred megabytes.
+//!
+//! Collisions are their own inverse and commute with reversing every
+//! particle, as do walls that bounce back or reflect specularly, and periodic
+//! and reflecting edges stream particles back where they came from when they
+//! are reversed. Without open edges, inflow, sinks or diffuse walls the
+//! dynamics can therefore be run backwards exactly, see `Lattice::turn_back`.
+
+use rayon::prelude::*;
+
+use crate::wall::{self, Reflection};
+
+pub const DIRECTIONS: usize = 4;
+
+pub type Node = [bool; DIRECTIONS];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NodeType {
+    Fluid,
+    /// Wall, reflecting its particles as given.
+    Wall(Reflection),
+    /// Redrawn every step, each channel occupied with the given probability.
+    Inflow(f32),
+    /// Absorbs every particle.
+    Sink,
+}
+
+/// Unit velocities, with `y` pointing down the screen.
+pub const VELOCITIES: [[i32; 2]; DIRECTIONS] = [[1, 0], [0, -1], [-1, 0], [0, 1]];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    Periodic,
+    Reflecting,
+    Open,
+}
+
+impl Edge {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Edge::Periodic => "periodic",
+            Edge::Reflecting => "reflecting",
+            Edge::Open => "open",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "periodic" => Some(Edge::Periodic),
+            "reflecting" | "reflect" => Some(Edge::Reflecting),
+            "open" => Some(Edge::Open),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Edges {
+    pub left: Edge,
+    pub right: Edge,
+    pub top: Edge,
+    pub bottom: Edge,
+}
+
+impl Edges {
+    pub fn all(edge: Edge) -> Self {
+        Edges {
+            left: edge,
+            right: edge,
+            top: edge,
+            bottom: edge,
+        }
+    }
+
+    /// One edge type for all four edges, or four separated by commas in the
+    /// order left, right, top, bottom; periodic edges must come in opposite
+    /// pairs.
+    pub fn parse(s: &str) -> Option<Self> {
+        let edges: Vec<_> = s.split(',').map(Edge::parse).collect::<Option<_>>()?;
+
+        let edges = match edges[..] {
+            [edge] => Edges::all(edge),
+            [left, right, top, bottom] => Edges {
+                left,
+                right,
+                top,
+                bottom,
+            },
+            _ => return None,
+        };
+
+        let paired = |a: Edge, b: Edge| (a == Edge::Periodic) == (b == Edge::Periodic);
+
+        if paired(edges.left, edges.right) && paired(edges.top, edges.bottom) {
+            Some(edges)
+        } else {
+            None
+        }
+    }
+
+    /// Edges given by `--edges`, or `default`.
+    pub fn from_args(default: Edges) -> Self {
+        let args: Vec<_> = std::env::args().collect();
+
+        let edges = match args.iter().position(|a| a == "--edges") {
+            Some(k) => match args.get(k + 1).and_then(|s| Edges::parse(s)) {
+                Some(edges) => edges,
+                None => {
+                    eprintln!(
+                        "--edges takes periodic, reflecting or open, once or as \
+                         left,right,top,bottom with periodic edges in opposite pairs"
+                    );
+                    std::process::exit(1);
+                }
+            },
+            None => default,
+        };
+
+        println!(
+            "edges: left {} right {} top {} bottom {}",
+            edges.left.name(),
+            edges.right.name(),
+            edges.top.name(),
+            edges.bottom.name()
+        );
+
+        edges
+    }
+
+    /// Node one step from `(i, j)` along `d` on a `width` by `height`
+    /// lattice, wrapping around periodic edges, or the edge crossed if it is
+    /// not periodic.
+    pub fn step(
+        &self,
+        i: usize,
+        j: usize,
+        d: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<(usize, usize), Edge> {
+        let ni = i as isize + VELOCITIES[d][0] as isize;
+        let nj = j as isize + VELOCITIES[d][1] as isize;
+
+        let (ni, edge) = match ni {
+            -1 => (width - 1, self.left),
+            ni if ni == width as isize => (0, self.right),
+            ni => (ni as usize, Edge::Periodic),
+        };
+
+        let (nj, edge) = match nj {
+            -1 => (height - 1, self.top),
+            nj if nj == height as isize => (0, self.bottom),
+            nj => (nj as usize, edge),
+        };
+
+        match edge {
+            Edge::Periodic => Ok((ni, nj)),
+            edge => Err(edge),
+        }
+    }
+
+    /// Node and direction the particle arriving at `(i, j)` along `d` comes
+    /// from, or `None` if it would come in through an open edge.
+    pub fn source(
+        &self,
+        i: usize,
+        j: usize,
+        d: usize,
+        width: usize,
+        height: usize,
+    ) -> Option<(usize, usize, usize)> {
+        let back = opposite(d);
+
+        match self.step(i, j, back, width, height) {
+            Ok((si, sj)) => Some((si, sj, d)),
+            Err(Edge::Reflecting) => Some((i, j, back)),
+            Err(_) => None,
+        }
+    }
+}
+
+#[inline]
+pub fn opposite(d: usize) -> usize {
+    (d + 2) % DIRECTIONS
+}
+
+pub fn collide(node: &Node) -> Node {
+    match node {
+        [true, false, true, false] => [false, true, false, true],
+        [false, true, false, true] => [true, false, true, false],
+        _ => *node,
+    }
+}
+
+/// Reflects every particle with mirror `m` of `wall::mirror`.
+pub fn reflect(node: &Node, m: usize) -> Node {
+    let mut out = [false; DIRECTIONS];
+
+    for (d, &b) in node.iter().enumerate() {
+        out[wall::mirrored(d, m, DIRECTIONS)] = b;
+    }
+
+    out
+}
+
+pub fn mass(node: &Node) -> u32 {
+    node.iter().filter(|&&b| b).count() as u32
+}
+
+pub fn momentum(node: &Node) -> [i32; 2] {
+    node.iter()
+        .zip(VELOCITIES.iter())
+        .filter(|(&b, _)| b)
+        .fold([0, 0], |[x, y], (_, c)| [x + c[0], y + c[1]])
+}
+
+/// Mirror of the wall at node `(i, j)` of a `width` by `height` lattice, with
+/// reflecting edges counting as walls.
+pub fn wall_mirror<F>(
+    node_type: F,
+    edges: &Edges,
+    i: usize,
+    j: usize,
+    width: usize,
+    height: usize,
+) -> Option<usize>
+where
+    F: Fn(usize, usize) -> NodeType,
+{
+    let velocities = VELOCITIES.map(|c| [c[0] as f32, c[1] as f32]);
+
+    wall::mirror(&velocities, |d| match edges.step(i, j, d, width, height) {
+        Ok((ni, nj)) => !matches!(node_type(ni, nj), NodeType::Wall(_)),
+        Err(edge) => edge == Edge::Open,
+    })
+}
+
+/// A word holding nodes other than fluid and inflow, with a mask per kind.
+struct Special {
+    word: usize,
+    bounce: u64,
+    specular: [u64; DIRECTIONS],
+    diffuse: [u64; DIRECTIONS],
+    sink: u64,
+}
+
+/// Multi-spin coded HPP lattice, rows padded to whole words.
+///
+/// Random numbers come from `uniform(n, d)` for node `n = width * j + i`:
+/// channel `d` of an inflow node is occupied where it falls below the
+/// inflow's probability, and a diffuse wall node reflects specularly where
+/// `uniform(n, DIRECTIONS)` falls below a half.
+pub struct Lattice {
+    pub width: usize,
+    pub height: usize,
+    /// Words per row.
+    pub words: usize,
+    pub edges: Edges,
+    /// The direction bitplanes, interleaved word by word.
+    pub planes: Vec<[u64; DIRECTIONS]>,
+    fluid: Vec<u64>,
+    special: Vec<Special>,
+    inflow: Vec<(usize, f32)>,
+    new: Vec<[u64; DIRECTIONS]>,
+}
+
+impl Lattice {
+    /// Empty lattice of fluid nodes.
+    pub fn new(width: usize, height: usize, edges: Edges) -> Self {
+        let words = width.div_ceil(64);
+
+        let mut lattice = Lattice {
+            width,
+            height,
+            words,
+            edges,
+            planes: vec![[0; DIRECTIONS]; words * height],
+            fluid: vec![0; words * height],
+            special: vec![],
+            inflow: vec![],
+            new: vec![[0; DIRECTIONS]; words * height],
+        };
+
+        lattice.set_geometry(|_, _| NodeType::Fluid);
+        lattice
+    }
+
+    /// Mask of the nodes of word `w` that lie inside a row.
+    fn row_mask(&self, w: usize) -> u64 {
+        match self.width % 64 {
+            r if r > 0 && w + 1 == self.words => (1 << r) - 1,
+            _ => !0,
+        }
+    }
+
+    /// Packs the type of every node, given by `node_type(i, j)`.
+    pub fn set_geometry<F: Fn(usize, usize) -> NodeType>(&mut self, node_type: F) {
+        let (width, height, words) = (self.width, self.height, self.words);
+
+        self.special.clear();
+        self.inflow.clear();
+
+        for j in 0..height {
+            for w in 0..words {
+                let k = words * j + w;
+                let (mut fluid, mut inflow) = (0, 0);
+                let mut special = Special {
+                    word: k,
+                    bounce: 0,
+                    specular: [0; DIRECTIONS],
+                    diffuse: [0; DIRECTIONS],
+                    sink: 0,
+                };
+
+                for i in 64 * w..width.min(64 * w + 64) {
+                    let bit = 1 << (i % 64);
+
+                    match node_type(i, j) {
+                        NodeType::Fluid => fluid |= bit,
+                        NodeType::Wall(reflection) => {
+                            match (
+                                reflection,
+                                wall_mirror(&node_type, &self.edges, i, j, width, height),
+                            ) {
+                                (Reflection::Specular, Some(m)) => special.specular[m] |= bit,
+                                (Reflection::Diffuse, Some(m)) => special.diffuse[m] |= bit,
+                                _ => special.bounce |= bit,
+                            }
+                        }
+                        NodeType::Inflow(p) => {
+                            inflow |= bit;
+                            self.inflow.push((width * j + i, p));
+                        }
+                        NodeType::Sink => special.sink |= bit,
+                    }
+                }
+
+                self.fluid[k] = fluid;
+
+                if fluid | inflow != self.row_mask(w) {
+                    self.special.push(special);
+                }
+            }
+        }
+    }
+
+    pub fn node(&self, i: usize, j: usize) -> Node {
+        let word = &self.planes[self.words * j + i / 64];
+
+        [0, 1, 2, 3].map(|d| word[d] >> (i % 64) & 1 != 0)
+    }
+
+    pub fn set(&mut self, i: usize, j: usize, node: Node) {
+        let word = &mut self.planes[self.words * j + i / 64];
+        let bit = 1 << (i % 64);
+
+        for (plane, &b) in word.iter_mut().zip(node.iter()) {
+            if b {
+                *plane |= bit;
+            } else {
+                *plane &= !bit;
+            }
+        }
+    }
+
+    /// Whether `turn_back` retraces the dynamics exactly, which open edges,
+    /// inflow, sinks and diffuse walls prevent.
+    pub fn reversible(&self) -> bool {
+        let edges = [
+            self.edges.left,
+            self.edges.right,
+            self.edges.top,
+            self.edges.bottom,
+        ];
+
+        !edges.contains(&Edge::Open)
+            && self.inflow.is_empty()
+            && self
+                .special
+                .iter()
+                .all(|special| special.sink == 0 && special.diffuse.iter().all(|&nodes| nodes == 0))
+    }
+
+    /// Reverses every particle.
+    pub fn reverse(&mut self) {
+        self.planes
+            .par_iter_mut()
+            .for_each(|x| *x = [x[2], x[3], x[0], x[1]]);
+    }
+
+    /// Turns the dynamics around between two steps. As a step collides before
+    /// it streams, this collides once more and then reverses every particle:
+    /// after `n` steps, `turn_back`, `n` more steps and a second `turn_back`,
+    /// a `reversible` lattice is back in its initial state bit for bit.
+    pub fn turn_back(&mut self) {
+        self.collide(|_, _| 0.);
+        self.reverse();
+    }
+
+    /// Number of bits in which the state differs from `planes`, e.g. a copy
+    /// taken earlier.
+    pub fn distance(&self, planes: &[[u64; DIRECTIONS]]) -> u64 {
+        self.planes
+            .par_iter()
+            .zip(planes.par_iter())
+            .map(|(x, y)| {
+                x.iter()
+                    .zip(y.iter())
+                    .map(|(a, b)| (a ^ b).count_ones() as u64)
+                    .sum::<u64>()
+            })
+            .sum()
+    }
+
+    pub fn mass(&self) -> u64 {
+        self.planes
+            .par_iter()
+            .map(|x| x.iter().map(|p| p.count_ones() as u64).sum::<u64>())
+            .sum()
+    }
+
+    pub fn momentum(&self) -> [i64; 2] {
+        self.planes
+            .par_iter()
+            .map(|x| {
+                x.iter()
+                    .zip(VELOCITIES.iter())
+                    .fold([0, 0], |[px, py], (p, c)| {
+                        let n = p.count_ones() as i64;
+                        [px + n * c[0] as i64, py + n * c[1] as i64]
+                    })
+            })
+            .reduce(|| [0, 0], |a, b| [a[0] + b[0], a[1] + b[1]])
+    }
+
+    pub fn collide<U: FnMut(usize, usize) -> f32>(&mut self, mut uniform: U) {
+        let (width, words) = (self.width, self.words);
+
+        self.planes
+            .par_iter_mut()
+            .zip(self.fluid.par_iter())
+            .for_each(|(x, &fluid)| {
+                // head-on pairs along either axis turn by a right angle
+                let pairs = (x[0] & x[2] & !x[1] & !x[3] | x[1] & x[3] & !x[0] & !x[2]) & fluid;
+
+                for plane in x.iter_mut() {
+                    *plane ^= pairs;
+                }
+            });
+
+        for special in self.special.iter() {
+            let (j, w) = (special.word / words, special.word % words);
+
+            // diffuse walls reflect specularly where their draw falls below a
+            // half and bounce back elsewhere
+            let mut draws = 0;
+            let mut diffuse = special.diffuse.iter().fold(0, |a, b| a | b);
+
+            while diffuse != 0 {
+                let b = diffuse.trailing_zeros() as usize;
+
+                if uniform(width * j + 64 * w + b, DIRECTIONS) < 0.5 {
+                    draws |= 1 << b;
+                }
+
+                diffuse &= diffuse - 1;
+            }
+
+            let mut bounce = special.bounce;
+            let mut mirror = special.specular;
+
+            for (m, &nodes) in mirror.iter_mut().zip(special.diffuse.iter()) {
+                *m |= nodes & draws;
+                bounce |= nodes & !draws;
+            }
+
+            let solid = mirror.iter().fold(bounce | special.sink, |a, b| a | b);
+            let x = &mut self.planes[special.word];
+            let old = *x;
+
+            for (c, plane) in x.iter_mut().enumerate() {
+                let reflected = mirror.iter().enumerate().fold(0, |out, (m, &nodes)| {
+                    out | nodes & old[wall::mirrored(c, m, DIRECTIONS)]
+                });
+
+                *plane = old[c] & !solid | bounce & old[opposite(c)] | reflected;
+            }
+        }
+    }
+
+    /// Redraws the inflow nodes in storage order.
+    pub fn inject<U: FnMut(usize, usize) -> f32>(&mut self, mut uniform: U) {
+        for &(n, p) in self.inflow.iter() {
+            let (i, j) = (n % self.width, n / self.width);
+            let bit = 1 << (i % 64);
+
+            for (d, plane) in self.planes[self.words * j + i / 64].iter_mut().enumerate() {
+                if uniform(n, d) < p {
+                    *plane |= bit;
+                } else {
+                    *plane &= !bit;
+                }
+            }
+        }
+    }
+
+    /// Shifts the horizontal channels along the rows and copies the vertical
+    /// ones from the rows above and below, with what crosses the edges
+    /// treated as in `Edges::source`.
+    pub fn stream(&mut self) {
+        let (width, height, words) = (self.width, self.height, self.words);
+        let (edges, last) = (self.edges, self.row_mask(words - 1));
+        let planes = &self.planes;
+
+        let bit = |row: &[[u64; DIRECTIONS]], i: usize, d: usize| row[i / 64][d] >> (i % 64) & 1;
+
+        self.new
+            .par_chunks_mut(words)
+            .enumerate()
+            .for_each(|(j, out)| {
+                let row = &planes[words * j..words * (j + 1)];
+
+                // what enters the row across its ends
+                let left = match edges.left {
+                    Edge::Periodic => bit(row, width - 1, 0),
+                    Edge::Reflecting => bit(row, 0, 2),
+                    Edge::Open => 0,
+                };
+                let right = match edges.right {
+                    Edge::Periodic => bit(row, 0, 2),
+                    Edge::Reflecting => bit(row, width - 1, 0),
+                    Edge::Open => 0,
+                };
+
+                // row and channel the vertical channels come from
+                let above = match (j, edges.top) {
+                    (0, Edge::Periodic) => Some((height - 1, 3)),
+                    (0, Edge::Reflecting) => Some((0, 1)),
+                    (0, Edge::Open) => None,
+                    _ => Some((j - 1, 3)),
+                };
+                let below = match (height - 1 - j, edges.bottom) {
+                    (0, Edge::Periodic) => Some((0, 1)),
+                    (0, Edge::Reflecting) => Some((height - 1, 3)),
+                    (0, Edge::Open) => None,
+                    _ => Some((j + 1, 1)),
+                };
+
+                for (w, x) in out.iter_mut().enumerate() {
+                    // node i takes node i - 1
+                    let carry = if w > 0 { row[w - 1][0] >> 63 } else { left };
+                    x[0] = row[w][0] << 1 | carry;
+
+                    // node i takes node i + 1
+                    x[2] = if w + 1 < words {
+                        row[w][2] >> 1 | row[w + 1][2] << 63
+                    } else {
+                        row[w][2] >> 1 | right << ((width - 1) % 64)
+                    };
+
+                    x[3] = above.map_or(0, |(js, d)| planes[words * js + w][d]);
+                    x[1] = below.map_or(0, |(js, d)| planes[words * js + w][d]);
+
+                    if w + 1 == words {
+                        x[0] &= last;
+                    }
+                }
+            });
+
+        std::mem::swap(&mut self.planes, &mut self.new);
+    }
+
+    pub fn step<U: FnMut(usize, usize) -> f32>(&mut self, mut uniform: U) {
+        self.collide(&mut uniform);
+        self.inject(&mut uniform);
+        self.stream();
+    }
+}
+
+/// A time-reversal run: the state it started from and the steps taken
+/// forward, then, once turned back, the steps left to retrace them.
+pub struct Reversal {
+    pub start: Vec<[u64; DIRECTIONS]>,
+    pub steps: usize,
+    /// Steps to take before turning back, at least one, unless that is left
+    /// to the caller.
+    pub length: Option<usize>,
+    pub remaining: Option<usize>,
+}
+
+impl Reversal {
+    pub fn new(lattice: &Lattice, length: Option<usize>) -> Self {
+        Reversal {
+            start: lattice.planes.clone(),
+            steps: 0,
+            length,
+            remaining: None,
+        }
+    }
+
+    /// Turns the run back, flipping one bit in the middle of the lattice if
+    /// `perturb`.
+    pub fn turn_back(&mut self, lattice: &mut Lattice, perturb: bool) {
+        lattice.turn_back();
+
+        if perturb {
+            let (i, j) = (lattice.width / 2, lattice.height / 2);
+            let mut node = lattice.node(i, j);
+
+            node[0] = !node[0];
+            lattice.set(i, j, node);
+        }
+
+        self.remaining = Some(self.steps);
+    }
+
+    /// Counts a step taken, turning back once `length` are. Returns whether
+    /// it turned back.
+    pub fn advance(&mut self, lattice: &mut Lattice, perturb: bool) -> bool {
+        match self.remaining {
+            Some(remaining) => {
+                self.remaining = Some(remaining.saturating_sub(1));
+                false
+            }
+            None => {
+                self.steps += 1;
+
+                if Some(self.steps) == self.length {
+                    self.turn_back(lattice, perturb);
+                    return true;
+                }
+
+                false
+            }
+        }
+    }
+
+    /// Once every step is retraced, turns back to the initial orientation
+    /// and returns the number of bits that differ from the initial state.
+    pub fn finish(&self, lattice: &mut Lattice) -> Option<u64> {
+        if self.remaining != Some(0) {
+            return None;
+        }
+
+        lattice.turn_back();
+        Some(lattice.distance(&self.start))
+    }
+}
@@ -0,0 +1,19 @@
+pub mod coarse;
+pub mod convergence;
+pub mod d2q9;
+pub mod fhp;
+pub mod field;
+pub mod geometry;
+pub mod hpp;
+pub mod ibm;
+pub mod mask;
+pub mod outflow;
+pub mod particle;
+pub mod postprocess;
+pub mod refine;
+pub mod rng;
+pub mod shallow;
+pub mod tracer;
+pub mod transport;
+pub mod wall;
+pub mod watchdog;
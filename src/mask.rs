@@ -0,0 +1,233 @@
+//! Geometry masks: images whose pixel colours give the type of every node.
+//!
+//! Each colour channel is read as on or off at half intensity: white is
+//! fluid, black a wall, red an inflow, blue a sink and green a moving wall,
+//! and transparent pixels are fluid. Pixel `(x, y)` is node `(i, j)`, rows
+//! running down the image like they run down the screen in every front-end.
+//! The mask only says what kind of node sits where; inflow and wall velocities
+//! are up to the model that maps the cells onto its own node types.
+//!
+//! Front-ends paint cells into a running simulation with a `Brush` and save
+//! the result as a mask again, mapping their node types back onto cells.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::geometry::Geometry;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cell {
+    Fluid,
+    Wall,
+    Inflow,
+    Sink,
+    MovingWall,
+}
+
+impl Cell {
+    pub fn color(&self) -> [u8; 3] {
+        match self {
+            Cell::Fluid => [255, 255, 255],
+            Cell::Wall => [0, 0, 0],
+            Cell::Inflow => [255, 0, 0],
+            Cell::Sink => [0, 0, 255],
+            Cell::MovingWall => [0, 255, 0],
+        }
+    }
+
+    /// The next solid or boundary cell, for cycling through brushes.
+    pub fn next(self) -> Self {
+        match self {
+            Cell::Wall => Cell::Inflow,
+            Cell::Inflow => Cell::Sink,
+            Cell::Sink => Cell::MovingWall,
+            Cell::MovingWall | Cell::Fluid => Cell::Wall,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Cell::Fluid => "fluid",
+            Cell::Wall => "wall",
+            Cell::Inflow => "inflow",
+            Cell::Sink => "sink",
+            Cell::MovingWall => "moving wall",
+        }
+    }
+
+    fn from_rgba(rgba: [u8; 4]) -> Option<Self> {
+        if rgba[3] < 128 {
+            return Some(Cell::Fluid);
+        }
+
+        match [rgba[0] >= 128, rgba[1] >= 128, rgba[2] >= 128] {
+            [true, true, true] => Some(Cell::Fluid),
+            [false, false, false] => Some(Cell::Wall),
+            [true, false, false] => Some(Cell::Inflow),
+            [false, false, true] => Some(Cell::Sink),
+            [false, true, false] => Some(Cell::MovingWall),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MaskError {
+    Image(image::ImageError),
+    /// A pixel whose colour is none of the cell colours.
+    Color {
+        x: u32,
+        y: u32,
+        rgba: [u8; 4],
+    },
+}
+
+impl fmt::Display for MaskError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MaskError::Image(e) => write!(f, "{}", e),
+            MaskError::Color { x, y, rgba } => {
+                write!(f, "pixel ({}, {}) has no cell colour: {:?}", x, y, rgba)
+            }
+        }
+    }
+}
+
+impl From<image::ImageError> for MaskError {
+    fn from(e: image::ImageError) -> Self {
+        MaskError::Image(e)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Mask {
+    pub width: usize,
+    pub height: usize,
+    /// Row-major (`width * j + i`).
+    pub cells: Vec<Cell>,
+}
+
+impl Mask {
+    /// Mask of a lattice whose node types map back onto cells with `cell`.
+    pub fn from_nodes<T, F: Fn(&T) -> Cell>(
+        width: usize,
+        height: usize,
+        node_type: &[T],
+        cell: F,
+    ) -> Self {
+        Mask {
+            width,
+            height,
+            cells: node_type.iter().map(cell).collect(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MaskError> {
+        let image = image::open(path)?.to_rgba8();
+        let (width, height) = image.dimensions();
+
+        let mut cells = Vec::with_capacity((width * height) as usize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let rgba = image.get_pixel(x, y).0;
+
+                match Cell::from_rgba(rgba) {
+                    Some(cell) => cells.push(cell),
+                    None => return Err(MaskError::Color { x, y, rgba }),
+                }
+            }
+        }
+
+        Ok(Mask {
+            width: width as usize,
+            height: height as usize,
+            cells,
+        })
+    }
+
+    /// Writes the mask as an image in the format given by the extension,
+    /// which should be lossless.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), MaskError> {
+        let mut image = image::RgbImage::new(self.width as u32, self.height as u32);
+
+        for (n, cell) in self.cells.iter().enumerate() {
+            let (x, y) = ((n % self.width) as u32, (n / self.width) as u32);
+            image.put_pixel(x, y, image::Rgb(cell.color()));
+        }
+
+        Ok(image.save(path)?)
+    }
+
+    /// The mask named by `--mask <path>` on the command line, if any. Exits
+    /// with a message if it cannot be loaded.
+    pub fn from_args() -> Option<Self> {
+        let args: Vec<_> = std::env::args().collect();
+        let k = args.iter().position(|a| a == "--mask")?;
+
+        let path = match args.get(k + 1) {
+            Some(path) => path,
+            None => {
+                eprintln!("--mask takes the path of an image");
+                std::process::exit(1);
+            }
+        };
+
+        match Mask::load(path) {
+            Ok(mask) => {
+                println!("mask: {} ({}x{})", path, mask.width, mask.height);
+                Some(mask)
+            }
+            Err(e) => {
+                eprintln!("could not load mask {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> Cell {
+        self.cells[self.width * j + i]
+    }
+
+    /// Node types of the mask in row-major order, with `node` mapping every
+    /// cell onto the model's own node type.
+    pub fn map<T, F: Fn(Cell) -> T>(&self, node: F) -> Vec<T> {
+        self.cells.iter().map(|&cell| node(cell)).collect()
+    }
+}
+
+/// Round brush for painting cells into a running simulation.
+#[derive(Clone, Copy, Debug)]
+pub struct Brush {
+    pub cell: Cell,
+    /// In nodes.
+    pub radius: f32,
+}
+
+impl Brush {
+    pub fn new() -> Self {
+        Brush {
+            cell: Cell::Wall,
+            radius: 2.,
+        }
+    }
+
+    pub fn grow(&mut self) {
+        self.radius += 1.;
+    }
+
+    pub fn shrink(&mut self) {
+        self.radius = (self.radius - 1.).max(0.5);
+    }
+
+    /// Area covered with the brush centred on `center`, in lattice units.
+    pub fn shape(&self, center: [f32; 2]) -> Geometry {
+        Geometry::circle(center, self.radius)
+    }
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,228 @@
+//! Non-reflecting treatments for the open edges of a `Lattice`.
+//!
+//! A sponge layer relaxes the populations towards a target equilibrium with a
+//! strength that grows quadratically towards the edge, so vortices and sound
+//! waves are damped before they reach it. A convective outflow replaces the
+//! populations on the edge itself by solving `df/dt + c df/dn = 0` with an
+//! implicit upwind step, either with a fixed speed `c` or with Orlanski's
+//! local phase speed estimated from the normal velocity one node inside.
+//!
+//! The convective condition lets vortices leave without distortion but
+//! reflects sound travelling at `u + cs`, so it is best combined with a
+//! sponge. Both act after `Lattice::stream` on nodes that are not solid; edge
+//! nodes with a convective condition should be plain fluid nodes.
+
+use crate::d2q9::{equilibrium, Lattice, E, Q};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Edge {
+    /// Unit step pointing into the domain.
+    pub fn inward(&self) -> [isize; 2] {
+        match self {
+            Edge::Left => [1, 0],
+            Edge::Right => [-1, 0],
+            Edge::Top => [0, 1],
+            Edge::Bottom => [0, -1],
+        }
+    }
+
+    /// Nodes `depth` nodes in from the edge.
+    fn line(&self, lattice: &Lattice, depth: usize) -> Vec<usize> {
+        let (w, h) = (lattice.width, lattice.height);
+
+        match self {
+            Edge::Left => (0..h).map(|j| lattice.index(depth, j)).collect(),
+            Edge::Right => (0..h).map(|j| lattice.index(w - 1 - depth, j)).collect(),
+            Edge::Top => (0..w).map(|i| lattice.index(i, depth)).collect(),
+            Edge::Bottom => (0..w).map(|i| lattice.index(i, h - 1 - depth)).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sponge {
+    /// Thickness of the layer in nodes.
+    pub width: usize,
+    /// Relaxation rate on the edge itself, between 0 and 1.
+    pub strength: f32,
+    pub rho: f32,
+    pub u: [f32; 2],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Convective {
+    /// Fixed advection speed, typically the mean outflow velocity.
+    Fixed(f32),
+    /// Phase speed estimated per node and clipped to `[0, 1]`.
+    Orlanski,
+}
+
+pub struct EdgeOutflow {
+    pub edge: Edge,
+    pub sponge: Option<Sponge>,
+    pub convective: Option<Convective>,
+    previous: Option<History>,
+}
+
+/// Populations on the edge and one node inside after the last `apply`.
+struct History {
+    boundary: Vec<[f32; Q]>,
+    inner: Vec<[f32; Q]>,
+}
+
+pub struct Outflow {
+    pub edges: Vec<EdgeOutflow>,
+}
+
+fn outward_velocity(f: &[f32; Q], edge: Edge) -> f32 {
+    let [ni, nj] = edge.inward();
+    let mut rho = 0.;
+    let mut m = 0.;
+
+    for k in 0..Q {
+        rho += f[k];
+        m -= f[k] * (E[k][0] * ni + E[k][1] * nj) as f32;
+    }
+
+    m / rho
+}
+
+impl Outflow {
+    pub fn new() -> Self {
+        Outflow { edges: vec![] }
+    }
+
+    /// Configures `edge`, replacing whatever it had before.
+    pub fn set(&mut self, edge: Edge, sponge: Option<Sponge>, convective: Option<Convective>) {
+        self.edges.retain(|e| e.edge != edge);
+
+        self.edges.push(EdgeOutflow {
+            edge,
+            sponge,
+            convective,
+            previous: None,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.edges.clear();
+    }
+
+    /// Applies every edge condition to the freshly streamed populations.
+    pub fn apply(&mut self, lattice: &mut Lattice) {
+        for outflow in self.edges.iter_mut() {
+            if let Some(sponge) = outflow.sponge {
+                apply_sponge(lattice, outflow.edge, &sponge);
+            }
+
+            if let Some(convective) = outflow.convective {
+                apply_convective(lattice, outflow.edge, convective, &mut outflow.previous);
+            }
+        }
+    }
+}
+
+impl Default for Outflow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn apply_sponge(lattice: &mut Lattice, edge: Edge, sponge: &Sponge) {
+    let feq = equilibrium(sponge.rho, sponge.u);
+
+    for depth in 0..sponge.width {
+        let x = 1. - depth as f32 / sponge.width as f32;
+        let sigma = sponge.strength * x * x;
+
+        for n in edge.line(lattice, depth) {
+            if lattice.node_type[n].is_solid() {
+                continue;
+            }
+
+            for (f, feq) in lattice.f[n].iter_mut().zip(feq.iter()) {
+                *f += sigma * (feq - *f);
+            }
+        }
+    }
+}
+
+fn apply_convective(
+    lattice: &mut Lattice,
+    edge: Edge,
+    convective: Convective,
+    previous: &mut Option<History>,
+) {
+    let boundary = edge.line(lattice, 0);
+    let inner = edge.line(lattice, 1);
+    let second = edge.line(lattice, 2);
+    let [ni, nj] = edge.inward();
+
+    let old = match previous.as_ref() {
+        Some(old) => old,
+        None => {
+            // nothing to convect yet: start from a zero normal gradient
+            for (&b, &m) in boundary.iter().zip(inner.iter()) {
+                if !lattice.node_type[b].is_solid() && !lattice.node_type[m].is_solid() {
+                    lattice.f[b] = lattice.f[m];
+                }
+            }
+
+            *previous = Some(History {
+                boundary: boundary.iter().map(|&n| lattice.f[n]).collect(),
+                inner: inner.iter().map(|&n| lattice.f[n]).collect(),
+            });
+
+            return;
+        }
+    };
+
+    let mut next = History {
+        boundary: old.boundary.clone(),
+        inner: old.inner.clone(),
+    };
+
+    for l in 0..boundary.len() {
+        let (b, m, s) = (boundary[l], inner[l], second[l]);
+
+        if lattice.node_type[b].is_solid() || lattice.node_type[m].is_solid() {
+            continue;
+        }
+
+        let c = match convective {
+            Convective::Fixed(c) => c,
+            Convective::Orlanski => {
+                let now = outward_velocity(&lattice.f[m], edge);
+                let before = outward_velocity(&old.inner[l], edge);
+                let behind = outward_velocity(&lattice.f[s], edge);
+
+                let dn = now - behind;
+
+                if dn.abs() > 1e-9 {
+                    (-(now - before) / dn).clamp(0., 1.)
+                } else {
+                    0.
+                }
+            }
+        };
+
+        // only the populations entering from outside are unknown
+        for (k, e) in E.iter().enumerate() {
+            if e[0] * ni + e[1] * nj > 0 {
+                lattice.f[b][k] = (old.boundary[l][k] + c * lattice.f[m][k]) / (1. + c);
+            }
+        }
+
+        next.boundary[l] = lattice.f[b];
+        next.inner[l] = lattice.f[m];
+    }
+
+    *previous = Some(next);
+}
@@ -0,0 +1,461 @@
+//! Freely moving rigid particles resolved on the lattice.
+//!
+//! Nodes covered by a particle become `NodeType::MovingWall` with the local
+//! surface velocity, so the fluid sees them through the moving bounce-back in
+//! `Lattice::stream`. The hydrodynamic force and torque come from the
+//! Galilean-invariant momentum exchange over the boundary links, and nodes the
+//! particle uncovers are refilled with the equilibrium at the surface velocity.
+
+use crate::d2q9::{equilibrium, moving_wall, Lattice, NodeType, E, OPP, Q};
+
+#[derive(Clone, Debug)]
+pub enum Shape {
+    Disc(f32),
+    /// Vertices in counter-clockwise or clockwise order, relative to the centroid.
+    Polygon(Vec<[f32; 2]>),
+}
+
+impl Shape {
+    /// Regular polygon with `n` corners on a circle of radius `r`.
+    pub fn regular(n: usize, r: f32) -> Self {
+        Shape::Polygon(
+            (0..n)
+                .map(|k| {
+                    let alpha = k as f32 / n as f32 * 2. * std::f32::consts::PI;
+                    [r * alpha.cos(), r * alpha.sin()]
+                })
+                .collect(),
+        )
+    }
+
+    pub fn area(&self) -> f32 {
+        match self {
+            Shape::Disc(r) => std::f32::consts::PI * r * r,
+            Shape::Polygon(v) => {
+                let n = v.len();
+
+                (0..n)
+                    .map(|k| {
+                        let (a, b) = (v[k], v[(k + 1) % n]);
+                        a[0] * b[1] - b[0] * a[1]
+                    })
+                    .sum::<f32>()
+                    .abs()
+                    / 2.
+            }
+        }
+    }
+
+    /// Polar second moment of area about the centroid.
+    pub fn polar_moment(&self) -> f32 {
+        match self {
+            Shape::Disc(r) => std::f32::consts::PI * r.powi(4) / 2.,
+            Shape::Polygon(v) => {
+                let n = v.len();
+
+                (0..n)
+                    .map(|k| {
+                        let (a, b) = (v[k], v[(k + 1) % n]);
+                        let cross = a[0] * b[1] - b[0] * a[1];
+
+                        cross
+                            * (a[0] * a[0]
+                                + a[0] * b[0]
+                                + b[0] * b[0]
+                                + a[1] * a[1]
+                                + a[1] * b[1]
+                                + b[1] * b[1])
+                    })
+                    .sum::<f32>()
+                    .abs()
+                    / 12.
+            }
+        }
+    }
+
+    pub fn bounding_radius(&self) -> f32 {
+        match self {
+            Shape::Disc(r) => *r,
+            Shape::Polygon(v) => v
+                .iter()
+                .map(|p| (p[0] * p[0] + p[1] * p[1]).sqrt())
+                .fold(0., f32::max),
+        }
+    }
+
+    /// Point-in-shape test in the body frame.
+    pub fn contains(&self, p: [f32; 2]) -> bool {
+        match self {
+            Shape::Disc(r) => p[0] * p[0] + p[1] * p[1] <= r * r,
+            Shape::Polygon(v) => {
+                let n = v.len();
+                let mut inside = false;
+
+                for k in 0..n {
+                    let (a, b) = (v[k], v[(k + n - 1) % n]);
+
+                    if (a[1] > p[1]) != (b[1] > p[1])
+                        && p[0] < (b[0] - a[0]) * (p[1] - a[1]) / (b[1] - a[1]) + a[0]
+                    {
+                        inside = !inside;
+                    }
+                }
+
+                inside
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Particle {
+    pub shape: Shape,
+    pub density: f32,
+    pub center: [f32; 2],
+    pub angle: f32,
+    pub velocity: [f32; 2],
+    pub angular_velocity: f32,
+    /// Hydrodynamic force and torque of the last step.
+    pub force: [f32; 2],
+    pub torque: f32,
+}
+
+impl Particle {
+    pub fn new(shape: Shape, density: f32, center: [f32; 2]) -> Self {
+        Particle {
+            shape,
+            density,
+            center,
+            angle: 0.,
+            velocity: [0., 0.],
+            angular_velocity: 0.,
+            force: [0., 0.],
+            torque: 0.,
+        }
+    }
+
+    pub fn mass(&self) -> f32 {
+        self.density * self.shape.area()
+    }
+
+    pub fn inertia(&self) -> f32 {
+        self.density * self.shape.polar_moment()
+    }
+
+    pub fn contains(&self, p: [f32; 2]) -> bool {
+        let (sin, cos) = self.angle.sin_cos();
+        let d = [p[0] - self.center[0], p[1] - self.center[1]];
+
+        self.shape
+            .contains([cos * d[0] + sin * d[1], -sin * d[0] + cos * d[1]])
+    }
+
+    pub fn surface_velocity(&self, p: [f32; 2]) -> [f32; 2] {
+        let r = [p[0] - self.center[0], p[1] - self.center[1]];
+
+        [
+            self.velocity[0] - self.angular_velocity * r[1],
+            self.velocity[1] + self.angular_velocity * r[0],
+        ]
+    }
+}
+
+pub struct Particles {
+    pub particles: Vec<Particle>,
+    pub gravity: [f32; 2],
+    /// Range and strength of the short-range repulsion that keeps particles
+    /// from overlapping each other and the walls (Glowinski et al. 1999).
+    pub contact_range: f32,
+    pub contact_stiffness: f32,
+    /// Particle covering each node, if any.
+    owner: Vec<Option<usize>>,
+    /// Momentum handed over by covered and uncovered nodes since the last step.
+    exchange: Vec<([f32; 2], f32)>,
+}
+
+impl Particles {
+    pub fn new(lattice: &mut Lattice, particles: Vec<Particle>) -> Self {
+        let n = particles.len();
+
+        let mut system = Particles {
+            particles,
+            gravity: [0., 0.],
+            contact_range: 2.,
+            contact_stiffness: 1e-3,
+            owner: vec![None; lattice.width * lattice.height],
+            exchange: vec![([0., 0.], 0.); n],
+        };
+
+        system.map(lattice);
+
+        system
+    }
+
+    /// Marks covered nodes as moving walls. Fluid swallowed by a particle gives
+    /// its momentum to the particle; freshly uncovered nodes are refilled with
+    /// the equilibrium at the local surface velocity and the mean density of
+    /// their fluid neighbours, and the particle pays for that momentum.
+    pub fn map(&mut self, lattice: &mut Lattice) {
+        for (p, particle) in self.particles.iter().enumerate() {
+            let [i0, i1, j0, j1] = bounds(
+                particle.center,
+                particle.shape.bounding_radius() + 2.,
+                lattice,
+            );
+
+            for j in j0..=j1 {
+                for i in i0..=i1 {
+                    let n = lattice.index(i, j);
+                    let pos = [i as f32, j as f32];
+                    let rel = [pos[0] - particle.center[0], pos[1] - particle.center[1]];
+
+                    let (momentum, torque) = &mut self.exchange[p];
+
+                    match (lattice.node_type[n], self.owner[n]) {
+                        (NodeType::Fluid, None) if particle.contains(pos) => {
+                            let (rho, u) = (lattice.rho[n], lattice.u[n]);
+
+                            momentum[0] += rho * u[0];
+                            momentum[1] += rho * u[1];
+                            *torque += rho * (rel[0] * u[1] - rel[1] * u[0]);
+
+                            self.owner[n] = Some(p);
+                        }
+                        (NodeType::MovingWall(_), Some(q)) if q == p && !particle.contains(pos) => {
+                            let mut rho = 0.;
+                            let mut count = 0;
+
+                            for e in E.iter().skip(1) {
+                                let m = lattice.neighbor(i, j, e[0], e[1]);
+
+                                if lattice.node_type[m] == NodeType::Fluid {
+                                    rho += lattice.rho[m];
+                                    count += 1;
+                                }
+                            }
+
+                            let rho = if count > 0 { rho / count as f32 } else { 1. };
+                            let v = particle.surface_velocity(pos);
+
+                            lattice.node_type[n] = NodeType::Fluid;
+                            lattice.f[n] = equilibrium(rho, v);
+                            lattice.rho[n] = rho;
+                            lattice.u[n] = v;
+
+                            momentum[0] -= rho * v[0];
+                            momentum[1] -= rho * v[1];
+                            *torque -= rho * (rel[0] * v[1] - rel[1] * v[0]);
+
+                            self.owner[n] = None;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        for (p, particle) in self.particles.iter().enumerate() {
+            let [i0, i1, j0, j1] = bounds(
+                particle.center,
+                particle.shape.bounding_radius() + 2.,
+                lattice,
+            );
+
+            for j in j0..=j1 {
+                for i in i0..=i1 {
+                    let n = lattice.index(i, j);
+
+                    if self.owner[n] == Some(p) {
+                        let v = particle.surface_velocity([i as f32, j as f32]);
+                        lattice.node_type[n] = NodeType::MovingWall(v);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Momentum exchange over all fluid-particle links. Must run between
+    /// `Lattice::collide` and `Lattice::stream`.
+    pub fn exchange_momentum(&mut self, lattice: &Lattice) {
+        for particle in self.particles.iter_mut() {
+            particle.force = [0., 0.];
+            particle.torque = 0.;
+        }
+
+        for (p, particle) in self.particles.iter_mut().enumerate() {
+            let [i0, i1, j0, j1] = bounds(
+                particle.center,
+                particle.shape.bounding_radius() + 2.,
+                lattice,
+            );
+
+            for j in j0..=j1 {
+                for i in i0..=i1 {
+                    let n = lattice.index(i, j);
+
+                    if lattice.node_type[n].is_solid() {
+                        continue;
+                    }
+
+                    for k in 1..Q {
+                        let s = lattice.neighbor(i, j, E[k][0], E[k][1]);
+
+                        let v = match (lattice.node_type[s], self.owner[s]) {
+                            (NodeType::MovingWall(v), Some(q)) if q == p => v,
+                            _ => continue,
+                        };
+
+                        // f_out leaves along e_k, hits the wall and returns along -e_k
+                        let f_out = lattice.f[n][k];
+                        let f_in = f_out + moving_wall(OPP[k], lattice.rho[n], v);
+
+                        let e = [E[k][0] as f32, E[k][1] as f32];
+                        let df = [
+                            (e[0] - v[0]) * f_out + (e[0] + v[0]) * f_in,
+                            (e[1] - v[1]) * f_out + (e[1] + v[1]) * f_in,
+                        ];
+
+                        let x = [i as f32 + 0.5 * e[0], j as f32 + 0.5 * e[1]];
+                        let r = [x[0] - particle.center[0], x[1] - particle.center[1]];
+
+                        particle.force[0] += df[0];
+                        particle.force[1] += df[1];
+                        particle.torque += r[0] * df[1] - r[1] * df[0];
+                    }
+                }
+            }
+        }
+    }
+
+    fn contact_forces(&self, lattice: &Lattice) -> Vec<[f32; 2]> {
+        let n = self.particles.len();
+        let range = self.contact_range;
+        let stiffness = self.contact_stiffness;
+
+        let repulsion = |d: [f32; 2], gap: f32| {
+            let dist = (d[0] * d[0] + d[1] * d[1]).sqrt().max(1e-6);
+            let s = if gap < range {
+                stiffness * ((range - gap) / range).powi(2) / dist
+            } else {
+                0.
+            };
+
+            [s * d[0], s * d[1]]
+        };
+
+        let mut contact = vec![[0., 0.]; n];
+
+        for p in 0..n {
+            for q in p + 1..n {
+                let (a, b) = (&self.particles[p], &self.particles[q]);
+                let d = [a.center[0] - b.center[0], a.center[1] - b.center[1]];
+                let gap = (d[0] * d[0] + d[1] * d[1]).sqrt()
+                    - a.shape.bounding_radius()
+                    - b.shape.bounding_radius();
+
+                let f = repulsion(d, gap);
+
+                contact[p][0] += f[0];
+                contact[p][1] += f[1];
+                contact[q][0] -= f[0];
+                contact[q][1] -= f[1];
+            }
+        }
+
+        // against the nearest static wall node within reach
+        for (p, a) in self.particles.iter().enumerate() {
+            let ra = a.shape.bounding_radius();
+            let [i0, i1, j0, j1] = bounds(a.center, ra + range + 1., lattice);
+
+            let mut nearest: Option<([f32; 2], f32)> = None;
+
+            for j in j0..=j1 {
+                for i in i0..=i1 {
+                    if lattice.node_type[lattice.index(i, j)] != NodeType::Boundary {
+                        continue;
+                    }
+
+                    let d = [a.center[0] - i as f32, a.center[1] - j as f32];
+                    let gap = (d[0] * d[0] + d[1] * d[1]).sqrt() - ra - 0.5;
+
+                    let closer = match nearest {
+                        Some((_, g)) => gap < g,
+                        None => true,
+                    };
+
+                    if closer {
+                        nearest = Some((d, gap));
+                    }
+                }
+            }
+
+            if let Some((d, gap)) = nearest {
+                let f = repulsion(d, gap);
+
+                contact[p][0] += f[0];
+                contact[p][1] += f[1];
+            }
+        }
+
+        contact
+    }
+
+    /// Integrates the particles with the forces from `exchange_momentum` and
+    /// remaps them onto the lattice. Must run after `Lattice::stream`.
+    pub fn advance(&mut self, lattice: &mut Lattice) {
+        let contact = self.contact_forces(lattice);
+
+        for (p, particle) in self.particles.iter_mut().enumerate() {
+            let mass = particle.mass();
+            let inertia = particle.inertia();
+
+            // buoyancy-corrected weight, the fluid itself carries no gravity
+            let weight = (particle.density - 1.) * particle.shape.area();
+
+            let (momentum, torque) = self.exchange[p];
+
+            for d in 0..2 {
+                particle.velocity[d] +=
+                    (particle.force[d] + momentum[d] + weight * self.gravity[d] + contact[p][d])
+                        / mass;
+            }
+
+            particle.angular_velocity += (particle.torque + torque) / inertia;
+
+            particle.center[0] += particle.velocity[0];
+            particle.center[1] += particle.velocity[1];
+            particle.angle += particle.angular_velocity;
+
+            self.exchange[p] = ([0., 0.], 0.);
+        }
+
+        self.map(lattice);
+    }
+
+    pub fn owner(&self, n: usize) -> Option<usize> {
+        self.owner[n]
+    }
+}
+
+/// Inclusive node range `[i0, i1, j0, j1]` within `reach` of `center`, clamped to the lattice.
+fn bounds(center: [f32; 2], reach: f32, lattice: &Lattice) -> [usize; 4] {
+    let clamp = |x: f32, max: usize| (x.max(0.) as usize).min(max - 1);
+
+    [
+        clamp((center[0] - reach).floor(), lattice.width),
+        clamp((center[0] + reach).ceil(), lattice.width),
+        clamp((center[1] - reach).floor(), lattice.height),
+        clamp((center[1] + reach).ceil(), lattice.height),
+    ]
+}
+
+/// Low-Reynolds-number settling velocity of a disc of radius `radius` falling
+/// midway between two plane walls `width` apart, from Faxén's wall-corrected
+/// drag `F = 4 pi mu U / (ln(1/k) - 0.9157 + 1.7244 k^2 - 1.7302 k^4)` with
+/// `k = 2 radius / width`. `weight` is the buoyancy-corrected weight per unit length.
+pub fn faxen_settling_velocity(radius: f32, width: f32, viscosity: f32, weight: f32) -> f32 {
+    let k = 2. * radius / width;
+    let correction = (1. / k).ln() - 0.9157 + 1.7244 * k.powi(2) - 1.7302 * k.powi(4);
+
+    weight * correction / (4. * std::f32::consts::PI * viscosity)
+}
@@ -0,0 +1,182 @@
+//! Derived quantities of a 2D velocity field: vorticity, divergence,
+//! Q-criterion, strain rate and stream function.
+//!
+//! Derivatives are second-order central differences in the interior and
+//! first-order one-sided differences on the edges of the sampled area.
+
+use crate::field::{ScalarField, VectorField};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Derived {
+    Speed,
+    Vorticity,
+    Divergence,
+    QCriterion,
+    StrainRate,
+    StreamFunction,
+}
+
+pub const DERIVED: [Derived; 6] = [
+    Derived::Speed,
+    Derived::Vorticity,
+    Derived::Divergence,
+    Derived::QCriterion,
+    Derived::StrainRate,
+    Derived::StreamFunction,
+];
+
+impl Derived {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Derived::Speed => "speed",
+            Derived::Vorticity => "vorticity",
+            Derived::Divergence => "divergence",
+            Derived::QCriterion => "q_criterion",
+            Derived::StrainRate => "strain_rate",
+            Derived::StreamFunction => "stream_function",
+        }
+    }
+
+    /// Whether the quantity takes both signs, i.e. wants a diverging colour map.
+    pub fn signed(&self) -> bool {
+        !matches!(self, Derived::Speed | Derived::StrainRate)
+    }
+
+    /// The next quantity in `DERIVED`, wrapping around.
+    pub fn next(&self) -> Derived {
+        let k = DERIVED.iter().position(|d| d == self).unwrap();
+
+        DERIVED[(k + 1) % DERIVED.len()]
+    }
+
+    pub fn compute(&self, field: &VectorField) -> ScalarField {
+        match self {
+            Derived::Speed => speed(field),
+            Derived::Vorticity => vorticity(field),
+            Derived::Divergence => divergence(field),
+            Derived::QCriterion => q_criterion(field),
+            Derived::StrainRate => strain_rate(field),
+            Derived::StreamFunction => stream_function(field),
+        }
+    }
+}
+
+/// Velocity gradient `[du/dx, du/dy, dv/dx, dv/dy]` at every sample.
+pub fn gradient(field: &VectorField) -> Vec<[f32; 4]> {
+    let (w, h) = (field.width, field.height);
+    let mut grad = vec![[0.; 4]; w * h];
+
+    let diff = |a: [f32; 2], b: [f32; 2], d: f32| [(b[0] - a[0]) / d, (b[1] - a[1]) / d];
+
+    for j in 0..h {
+        for i in 0..w {
+            let (il, ir) = (i.saturating_sub(1), (i + 1).min(w - 1));
+            let (jl, jr) = (j.saturating_sub(1), (j + 1).min(h - 1));
+
+            let dx = (ir - il) as f32 * field.spacing[0];
+            let dy = (jr - jl) as f32 * field.spacing[1];
+
+            let [ux, vx] = if dx > 0. {
+                diff(field.get(il, j), field.get(ir, j), dx)
+            } else {
+                [0., 0.]
+            };
+
+            let [uy, vy] = if dy > 0. {
+                diff(field.get(i, jl), field.get(i, jr), dy)
+            } else {
+                [0., 0.]
+            };
+
+            grad[w * j + i] = [ux, uy, vx, vy];
+        }
+    }
+
+    grad
+}
+
+fn map_gradient<F: Fn([f32; 4]) -> f32>(field: &VectorField, f: F) -> ScalarField {
+    let mut out = ScalarField::new(field.width, field.height, field.spacing);
+
+    out.data = gradient(field).into_iter().map(f).collect();
+
+    out
+}
+
+pub fn speed(field: &VectorField) -> ScalarField {
+    let mut out = ScalarField::new(field.width, field.height, field.spacing);
+
+    out.data = field
+        .data
+        .iter()
+        .map(|[u, v]| (u * u + v * v).sqrt())
+        .collect();
+
+    out
+}
+
+pub fn vorticity(field: &VectorField) -> ScalarField {
+    map_gradient(field, |[_, uy, vx, _]| vx - uy)
+}
+
+pub fn divergence(field: &VectorField) -> ScalarField {
+    map_gradient(field, |[ux, _, _, vy]| ux + vy)
+}
+
+/// `Q = (|Omega|^2 - |S|^2) / 2`, positive where rotation dominates strain.
+pub fn q_criterion(field: &VectorField) -> ScalarField {
+    map_gradient(field, |[ux, uy, vx, vy]| {
+        -0.5 * (ux * ux + vy * vy) - uy * vx
+    })
+}
+
+/// Strain-rate magnitude `sqrt(2 S:S)`.
+pub fn strain_rate(field: &VectorField) -> ScalarField {
+    map_gradient(field, |[ux, uy, vx, vy]| {
+        let s = 0.5 * (uy + vx);
+
+        (2. * (ux * ux + vy * vy + 2. * s * s)).sqrt()
+    })
+}
+
+/// Stream function with `u = d(psi)/dy` and `v = -d(psi)/dx`, integrated with
+/// the trapezoidal rule down the first column and then along each row, taking
+/// `psi = 0` at the first sample. Only meaningful for divergence-free fields.
+pub fn stream_function(field: &VectorField) -> ScalarField {
+    let mut psi = ScalarField::new(field.width, field.height, field.spacing);
+    let [dx, dy] = field.spacing;
+
+    for j in 1..field.height {
+        let u = 0.5 * (field.get(0, j - 1)[0] + field.get(0, j)[0]);
+        psi.set(0, j, psi.get(0, j - 1) + dy * u);
+    }
+
+    for j in 0..field.height {
+        for i in 1..field.width {
+            let v = 0.5 * (field.get(i - 1, j)[1] + field.get(i, j)[1]);
+            psi.set(i, j, psi.get(i - 1, j) - dx * v);
+        }
+    }
+
+    psi
+}
+
+/// Colour for `value` scaled by `scale`: blue-white-red for signed quantities,
+/// black-to-yellow for magnitudes. Returns RGBA in `[0, 1]`.
+pub fn colormap(value: f32, scale: f32, signed: bool) -> [f32; 4] {
+    let t = if scale > 0. { value / scale } else { 0. };
+
+    if signed {
+        let t = t.clamp(-1., 1.);
+
+        if t >= 0. {
+            [1., 1. - t, 1. - t, 1.]
+        } else {
+            [1. + t, 1. + t, 1., 1.]
+        }
+    } else {
+        let t = t.clamp(0., 1.);
+
+        [t.sqrt(), t, 0.25 * t * t, 1.]
+    }
+}
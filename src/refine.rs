@@ -0,0 +1,356 @@
+//! Block-structured grid refinement with a 2:1 ratio between levels.
+//!
+//! A fine block covers a rectangle of parent nodes, with every other fine node
+//! coinciding with a parent node. Under acoustic scaling the fine lattice has
+//! half the spacing and half the time step of its parent, so its relaxation
+//! time is `tau_f = 1/2 + 2 (tau_c - 1/2)` and it runs two steps per parent
+//! step. The outermost ring of fine nodes is driven by the parent, with
+//! density, velocity and non-equilibrium populations interpolated in space and
+//! time, and the parent nodes strictly inside the block are overwritten by the
+//! fine solution after every parent step. Non-equilibrium populations scale as
+//! `tau / spacing` between the levels (Dupuis and Chopard).
+
+use crate::d2q9::{equilibrium, Lattice, NodeType, E, Q};
+
+pub struct Block {
+    pub lattice: Lattice,
+    /// Parent node that coincides with fine node `(0, 0)`.
+    pub origin: [usize; 2],
+    /// Root-lattice coordinates of fine node `(0, 0)`.
+    pub position: [f32; 2],
+    /// Node spacing in root-lattice units.
+    pub spacing: f32,
+    pub children: Vec<Block>,
+}
+
+/// Fine relaxation time giving the same physical viscosity as `tau` on the parent.
+pub fn fine_tau(tau: f32) -> f32 {
+    0.5 + 2. * (tau - 0.5)
+}
+
+/// Density, velocity and non-equilibrium part of a population set.
+fn decompose(f: &[f32; Q]) -> (f32, [f32; 2], [f32; Q]) {
+    let mut rho = 0.;
+    let mut m = [0., 0.];
+
+    for k in 0..Q {
+        rho += f[k];
+        m[0] += f[k] * E[k][0] as f32;
+        m[1] += f[k] * E[k][1] as f32;
+    }
+
+    let u = [m[0] / rho, m[1] / rho];
+    let feq = equilibrium(rho, u);
+
+    let mut neq = [0.; Q];
+
+    for k in 0..Q {
+        neq[k] = f[k] - feq[k];
+    }
+
+    (rho, u, neq)
+}
+
+fn compose(rho: f32, u: [f32; 2], neq: &[f32; Q], scale: f32) -> [f32; Q] {
+    let mut f = equilibrium(rho, u);
+
+    for k in 0..Q {
+        f[k] += scale * neq[k];
+    }
+
+    f
+}
+
+impl Block {
+    /// Fine block over parent nodes `i0..=i1` by `j0..=j1`, initialized from
+    /// the parent populations. `position` and `spacing` locate the parent in
+    /// root-lattice units. Body forces are copied at half strength.
+    fn new(parent: &Lattice, position: [f32; 2], spacing: f32, region: [usize; 4]) -> Self {
+        let [i0, j0, i1, j1] = region;
+
+        assert!(i0 < i1 && j0 < j1 && i1 < parent.width && j1 < parent.height);
+
+        let mut lattice = Lattice::new(2 * (i1 - i0) + 1, 2 * (j1 - j0) + 1, fine_tau(parent.tau));
+
+        for q in 0..lattice.height {
+            for p in 0..lattice.width {
+                let n = lattice.index(p, q);
+                let m = parent.index(i0 + p / 2, j0 + q / 2);
+
+                lattice.force[n] = [0.5 * parent.force[m][0], 0.5 * parent.force[m][1]];
+            }
+        }
+
+        let mut block = Block {
+            lattice,
+            origin: [i0, j0],
+            position: [
+                position[0] + i0 as f32 * spacing,
+                position[1] + j0 as f32 * spacing,
+            ],
+            spacing: 0.5 * spacing,
+            children: vec![],
+        };
+
+        let nodes: Vec<_> = (0..block.lattice.height)
+            .flat_map(|q| (0..block.lattice.width).map(move |p| (p, q)))
+            .collect();
+
+        block.prolong(&parent.f, &parent.f, parent, 0., &nodes);
+        block.lattice.compute_macroscopic();
+
+        block
+    }
+
+    /// Adds a finer block over nodes `i0..=i1` by `j0..=j1` of this block.
+    pub fn refine(&mut self, region: [usize; 4]) -> &mut Block {
+        let child = Block::new(&self.lattice, self.position, self.spacing, region);
+        self.children.push(child);
+
+        self.children.last_mut().unwrap()
+    }
+
+    /// Parent extent `[i0, j0, i1, j1]` of this block.
+    pub fn region(&self) -> [usize; 4] {
+        let [i0, j0] = self.origin;
+
+        [
+            i0,
+            j0,
+            i0 + (self.lattice.width - 1) / 2,
+            j0 + (self.lattice.height - 1) / 2,
+        ]
+    }
+
+    fn ring(&self) -> Vec<(usize, usize)> {
+        let (w, h) = (self.lattice.width, self.lattice.height);
+
+        (0..h)
+            .flat_map(|q| (0..w).map(move |p| (p, q)))
+            .filter(|&(p, q)| p == 0 || q == 0 || p == w - 1 || q == h - 1)
+            .collect()
+    }
+
+    /// Sets fine `nodes` from the parent populations `old` and `new`, blended
+    /// with weight `alpha` on `new` and bilinearly interpolated in space.
+    fn prolong(
+        &mut self,
+        old: &[[f32; Q]],
+        new: &[[f32; Q]],
+        parent: &Lattice,
+        alpha: f32,
+        nodes: &[(usize, usize)],
+    ) {
+        let [i0, j0] = self.origin;
+        let scale = self.lattice.tau / (2. * parent.tau);
+
+        for &(p, q) in nodes {
+            let n = self.lattice.index(p, q);
+
+            if self.lattice.node_type[n].is_solid() {
+                continue;
+            }
+
+            let mut weight = 0.;
+            let mut rho = 0.;
+            let mut u = [0., 0.];
+            let mut neq = [0.; Q];
+
+            for (di, wi) in [(0, 1. - 0.5 * (p % 2) as f32), (1, 0.5 * (p % 2) as f32)] {
+                for (dj, wj) in [(0, 1. - 0.5 * (q % 2) as f32), (1, 0.5 * (q % 2) as f32)] {
+                    let w = wi * wj;
+
+                    if w == 0. {
+                        continue;
+                    }
+
+                    let m = parent.index(i0 + p / 2 + di, j0 + q / 2 + dj);
+
+                    if parent.node_type[m].is_solid() {
+                        continue;
+                    }
+
+                    for (f, a) in [(&old[m], 1. - alpha), (&new[m], alpha)] {
+                        if a == 0. {
+                            continue;
+                        }
+
+                        let (r, v, d) = decompose(f);
+
+                        weight += w * a;
+                        rho += w * a * r;
+                        u[0] += w * a * v[0];
+                        u[1] += w * a * v[1];
+
+                        for k in 0..Q {
+                            neq[k] += w * a * d[k];
+                        }
+                    }
+                }
+            }
+
+            if weight > 0. {
+                rho /= weight;
+                u = [u[0] / weight, u[1] / weight];
+                neq.iter_mut().for_each(|d| *d /= weight);
+
+                self.lattice.f[n] = compose(rho, u, &neq, scale);
+            }
+        }
+    }
+
+    /// Copies the fine solution onto the parent nodes strictly inside the block.
+    fn restrict(&self, parent: &mut Lattice) {
+        let [i0, j0, i1, j1] = self.region();
+        let scale = 2. * parent.tau / self.lattice.tau;
+
+        for j in j0 + 1..j1 {
+            for i in i0 + 1..i1 {
+                let m = parent.index(i, j);
+                let n = self.lattice.index(2 * (i - i0), 2 * (j - j0));
+
+                if parent.node_type[m] != NodeType::Fluid
+                    || self.lattice.node_type[n] != NodeType::Fluid
+                {
+                    continue;
+                }
+
+                let (rho, u, neq) = decompose(&self.lattice.f[n]);
+                parent.f[m] = compose(rho, u, &neq, scale);
+            }
+        }
+    }
+
+    /// Marks nodes of this block and its children whose root-lattice
+    /// coordinates satisfy `solid` as boundary nodes.
+    pub fn mark_solid<F: Fn(f32, f32) -> bool>(&mut self, solid: &F) {
+        for q in 0..self.lattice.height {
+            for p in 0..self.lattice.width {
+                let x = self.position[0] + p as f32 * self.spacing;
+                let y = self.position[1] + q as f32 * self.spacing;
+
+                if solid(x, y) {
+                    let n = self.lattice.index(p, q);
+                    self.lattice.node_type[n] = NodeType::Boundary;
+                }
+            }
+        }
+
+        for child in self.children.iter_mut() {
+            child.mark_solid(solid);
+        }
+    }
+}
+
+/// Total mass of `lattice` in its own units, with the regions strictly inside
+/// `children` taken from the children. A `nested` lattice leaves out what its
+/// parent's edge nodes already cover.
+fn level_mass(lattice: &Lattice, children: &[Block], nested: bool) -> f32 {
+    let (w, h) = (lattice.width, lattice.height);
+
+    // fraction of the node's cell not covered by the parent's edge nodes
+    let edge = |p: usize, n: usize| match (nested, p.min(n - 1 - p)) {
+        (true, 0) => 0.,
+        (true, 1) => 0.5,
+        _ => 1.,
+    };
+
+    let covered = |p: usize, q: usize| {
+        children.iter().any(|c| {
+            let [i0, j0, i1, j1] = c.region();
+            p > i0 && p < i1 && q > j0 && q < j1
+        })
+    };
+
+    let mut mass = 0.;
+
+    for q in 0..h {
+        for p in 0..w {
+            let n = lattice.index(p, q);
+
+            if lattice.node_type[n].is_solid() || covered(p, q) {
+                continue;
+            }
+
+            mass += edge(p, w) * edge(q, h) * lattice.f[n].iter().sum::<f32>();
+        }
+    }
+
+    let fine: f32 = children
+        .iter()
+        .map(|c| level_mass(&c.lattice, &c.children, true))
+        .sum();
+
+    mass + 0.25 * fine
+}
+
+/// Advances `lattice` by one of its own time steps, sub-cycling `children`.
+fn level_step(lattice: &mut Lattice, children: &mut [Block]) {
+    let old = lattice.f.clone();
+
+    lattice.step();
+
+    for child in children.iter_mut() {
+        let ring = child.ring();
+
+        for alpha in [0., 0.5] {
+            child.prolong(&old, &lattice.f, lattice, alpha, &ring);
+            level_step(&mut child.lattice, &mut child.children);
+        }
+
+        child.prolong(&old, &lattice.f, lattice, 1., &ring);
+        child.restrict(lattice);
+    }
+}
+
+/// The root lattice together with its refined blocks.
+pub struct Refinement {
+    pub blocks: Vec<Block>,
+}
+
+impl Refinement {
+    pub fn new() -> Self {
+        Refinement { blocks: vec![] }
+    }
+
+    /// Adds a fine block over root nodes `i0..=i1` by `j0..=j1`. Blocks on the
+    /// same level must not overlap, and open boundaries stay on the root.
+    pub fn refine(&mut self, lattice: &Lattice, region: [usize; 4]) -> &mut Block {
+        self.blocks.push(Block::new(lattice, [0., 0.], 1., region));
+
+        self.blocks.last_mut().unwrap()
+    }
+
+    pub fn mark_solid<F: Fn(f32, f32) -> bool>(&mut self, lattice: &mut Lattice, solid: F) {
+        for j in 0..lattice.height {
+            for i in 0..lattice.width {
+                if solid(i as f32, j as f32) {
+                    let n = lattice.index(i, j);
+                    lattice.node_type[n] = NodeType::Boundary;
+                }
+            }
+        }
+
+        for block in self.blocks.iter_mut() {
+            block.mark_solid(&solid);
+        }
+    }
+
+    /// One root time step: the root lattice steps once, every block level
+    /// twice as often as its parent.
+    pub fn step(&mut self, lattice: &mut Lattice) {
+        level_step(lattice, &mut self.blocks);
+    }
+
+    /// Mass of the composite grid in root-lattice units, counting every
+    /// region once at its finest resolution.
+    pub fn mass(&self, lattice: &Lattice) -> f32 {
+        level_mass(lattice, &self.blocks, false)
+    }
+}
+
+impl Default for Refinement {
+    fn default() -> Self {
+        Self::new()
+    }
+}
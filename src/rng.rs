@@ -0,0 +1,78 @@
+//! Counter-based random numbers.
+//!
+//! Every draw is a pure function of the seed and a counter, typically the time
+//! step, a node or word index and a channel, so results do not depend on the
+//! order nodes are visited in or on how the work is split between threads.
+//! The counter is folded into the seed with the SplitMix64 finalizer.
+//!
+//! Every front-end takes its seed from `--seed` and prints it on startup, so
+//! a run can be replayed exactly.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CounterRng {
+    pub seed: u64,
+}
+
+#[inline]
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+impl CounterRng {
+    pub fn new(seed: u64) -> Self {
+        CounterRng { seed }
+    }
+
+    /// Seed from `--seed <n>` on the command line, or from the clock if there
+    /// is none. The seed is printed either way.
+    pub fn from_args() -> Self {
+        let args: Vec<_> = std::env::args().collect();
+
+        let seed = match args.iter().position(|a| a == "--seed") {
+            Some(k) => match args.get(k + 1).and_then(|s| s.parse().ok()) {
+                Some(seed) => seed,
+                None => {
+                    eprintln!("--seed takes an unsigned integer");
+                    std::process::exit(1);
+                }
+            },
+            None => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64),
+        };
+
+        println!("seed: {}", seed);
+
+        CounterRng::new(seed)
+    }
+
+    /// Independent generator for another purpose, e.g. collisions and
+    /// boundary conditions drawing on the same counters.
+    pub fn stream(&self, id: u64) -> Self {
+        CounterRng::new(mix(self.seed ^ mix(id.wrapping_add(0x9e37_79b9_7f4a_7c15))))
+    }
+
+    /// 64 random bits for `counter`.
+    #[inline]
+    pub fn bits(&self, counter: [u64; 3]) -> u64 {
+        counter.iter().fold(mix(self.seed), |h, &c| {
+            mix(h.wrapping_add(c).wrapping_add(0x9e37_79b9_7f4a_7c15))
+        })
+    }
+
+    /// Uniform in `0..n`.
+    #[inline]
+    pub fn below(&self, counter: [u64; 3], n: usize) -> usize {
+        ((self.bits(counter) as u128 * n as u128) >> 64) as usize
+    }
+
+    /// Uniform in `[0, 1)`.
+    #[inline]
+    pub fn uniform(&self, counter: [u64; 3]) -> f32 {
+        (self.bits(counter) >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
@@ -0,0 +1,245 @@
+//! Shallow-water lattice Boltzmann model on the D2Q9 `Lattice`.
+//!
+//! The zeroth moment of the populations is the water depth `h` (stored in
+//! `Lattice::rho`), and the equilibrium carries the hydrostatic pressure
+//! `g h^2 / 2` instead of `cs^2 rho`. Bed topography enters as a per-link
+//! source term evaluated at the link midpoint, which keeps a lake at rest
+//! exactly at rest over any bed. Solid nodes, inflows and sinks are the same
+//! `NodeType`s the Navier-Stokes solver uses.
+
+use rayon::prelude::*;
+
+use crate::d2q9::{forcing, Lattice, NodeType, E, Q, W};
+
+pub struct ShallowWater {
+    pub gravity: f32,
+    /// Bed elevation per node.
+    pub bed: Vec<f32>,
+    /// Nodes shallower than this are dry: their velocity is zeroed, and links
+    /// to a dry node whose bed rises above the local surface are walls.
+    pub dry_depth: f32,
+    /// Quadratic bed friction coefficient, the bed stress being `c_f |u| u`.
+    pub friction: f32,
+    bed_stress: Vec<[f32; 2]>,
+}
+
+/// Shallow-water equilibrium for depth `h` and velocity `u`.
+pub fn equilibrium(gravity: f32, h: f32, u: [f32; 2]) -> [f32; Q] {
+    let uu = u[0] * u[0] + u[1] * u[1];
+    let mut feq = [0.; Q];
+
+    for k in 1..Q {
+        let eu = E[k][0] as f32 * u[0] + E[k][1] as f32 * u[1];
+        feq[k] = W[k] * (1.5 * gravity * h * h + 3. * h * eu + 4.5 * h * eu * eu - 1.5 * h * uu);
+    }
+
+    feq[0] = h - feq[1..].iter().sum::<f32>();
+
+    feq
+}
+
+impl ShallowWater {
+    pub fn new(lattice: &Lattice, gravity: f32) -> Self {
+        ShallowWater {
+            gravity,
+            bed: vec![0.; lattice.width * lattice.height],
+            dry_depth: 1e-2,
+            friction: 0.,
+            bed_stress: vec![[0., 0.]; lattice.width * lattice.height],
+        }
+    }
+
+    /// Sets every node to rest with the free surface at `level`, leaving nodes
+    /// whose bed is above it dry.
+    pub fn fill(&self, lattice: &mut Lattice, level: f32) {
+        for n in 0..lattice.f.len() {
+            self.set(lattice, n, (level - self.bed[n]).max(0.), [0., 0.]);
+        }
+    }
+
+    /// Puts node `n` at equilibrium with depth `h` and velocity `u`.
+    pub fn set(&self, lattice: &mut Lattice, n: usize, h: f32, u: [f32; 2]) {
+        lattice.f[n] = equilibrium(self.gravity, h, u);
+        lattice.rho[n] = h;
+        lattice.u[n] = u;
+    }
+
+    #[inline]
+    fn is_dry(&self, lattice: &Lattice, n: usize) -> bool {
+        lattice.rho[n] < self.dry_depth
+    }
+
+    /// Whether the link between `a` and `b` is closed because one side is dry
+    /// and its bed sticks out of the water on the other side.
+    #[inline]
+    fn blocked(&self, lattice: &Lattice, a: usize, b: usize) -> bool {
+        let surface = |n: usize| self.bed[n] + lattice.rho[n].max(0.);
+
+        (self.is_dry(lattice, b) && self.bed[b] > surface(a))
+            || (self.is_dry(lattice, a) && self.bed[a] > surface(b))
+    }
+
+    /// Depth and velocity; dry nodes are held at rest. Bed friction enters
+    /// the half-force velocity like any other force, linearized around the
+    /// frictionless velocity so thin layers are damped rather than reversed.
+    pub fn compute_macroscopic(&mut self, lattice: &mut Lattice) {
+        lattice.compute_macroscopic();
+
+        let dry_depth = self.dry_depth;
+        let friction = self.friction;
+
+        lattice
+            .u
+            .par_iter_mut()
+            .zip(self.bed_stress.par_iter_mut())
+            .zip(lattice.rho.par_iter())
+            .for_each(|((u, stress), &h)| {
+                if h < dry_depth {
+                    *u = [0., 0.];
+                    *stress = [0., 0.];
+                    return;
+                }
+
+                let k = friction * (u[0] * u[0] + u[1] * u[1]).sqrt();
+
+                *u = [u[0] / (1. + 0.5 * k / h), u[1] / (1. + 0.5 * k / h)];
+                *stress = [-k * u[0], -k * u[1]];
+            });
+    }
+
+    /// BGK relaxation towards the shallow-water equilibrium, with Guo forcing
+    /// for `Lattice::force` plus bed friction, and the bed slope source on
+    /// every open link.
+    pub fn collide(&self, lattice: &mut Lattice) {
+        let lattice_ref = &*lattice;
+        let gravity = self.gravity;
+        let tau = lattice.tau;
+
+        let sources: Vec<[f32; Q]> = (0..lattice.f.len())
+            .into_par_iter()
+            .map(|n| {
+                let mut s = [0.; Q];
+
+                if lattice_ref.node_type[n] != NodeType::Fluid {
+                    return s;
+                }
+
+                let (i, j) = (n % lattice_ref.width, n / lattice_ref.width);
+                let h = lattice_ref.rho[n];
+
+                for (k, s) in s.iter_mut().enumerate().skip(1) {
+                    let m = lattice_ref.neighbor(i, j, E[k][0], E[k][1]);
+
+                    if lattice_ref.node_type[m].is_solid() || self.blocked(lattice_ref, n, m) {
+                        continue;
+                    }
+
+                    let depth = 0.5 * (h + lattice_ref.rho[m].max(0.));
+
+                    *s = -3. * W[k] * gravity * depth * (self.bed[m] - self.bed[n]);
+                }
+
+                s
+            })
+            .collect();
+
+        let rho = &lattice.rho;
+        let u = &lattice.u;
+        let force = &lattice.force;
+        let stress = &self.bed_stress;
+        let node_type = &lattice.node_type;
+
+        lattice
+            .f
+            .par_iter_mut()
+            .zip(sources.par_iter())
+            .enumerate()
+            .for_each(|(n, (f, bed))| {
+                if node_type[n] != NodeType::Fluid {
+                    return;
+                }
+
+                let feq = equilibrium(gravity, rho[n], u[n]);
+                let total = [force[n][0] + stress[n][0], force[n][1] + stress[n][1]];
+                let s = forcing(tau, u[n], total);
+
+                for k in 0..Q {
+                    f[k] += (feq[k] - f[k]) / tau + s[k] + bed[k];
+                }
+            });
+    }
+
+    /// Streaming with bounce-back on solid nodes and closed wet/dry links,
+    /// then the open boundaries: inflows keep their velocity and take the
+    /// depth of their wet neighbours, sinks copy both.
+    pub fn stream(&self, lattice: &mut Lattice) {
+        let blocked: Vec<u16> = (0..lattice.f.len())
+            .into_par_iter()
+            .map(|n| {
+                let (i, j) = (n % lattice.width, n / lattice.width);
+                let mut mask = 0;
+
+                for (k, e) in E.iter().enumerate().skip(1) {
+                    let s = lattice.neighbor(i, j, -e[0], -e[1]);
+
+                    if self.blocked(lattice, n, s) {
+                        mask |= 1 << k;
+                    }
+                }
+
+                mask
+            })
+            .collect();
+
+        lattice.stream_with(|n, k| blocked[n] & (1 << k) != 0);
+
+        self.apply_open_boundaries(lattice);
+    }
+
+    fn apply_open_boundaries(&self, lattice: &mut Lattice) {
+        for j in 0..lattice.height {
+            for i in 0..lattice.width {
+                let n = lattice.index(i, j);
+
+                let inflow = match lattice.node_type[n] {
+                    NodeType::Inflow(u) => Some(u),
+                    NodeType::Sink => None,
+                    _ => continue,
+                };
+
+                let mut h = 0.;
+                let mut u = [0., 0.];
+                let mut count = 0;
+
+                for e in E.iter().skip(1) {
+                    let m = lattice.neighbor(i, j, e[0], e[1]);
+
+                    if lattice.node_type[m] == NodeType::Fluid {
+                        h += lattice.rho[m];
+                        u[0] += lattice.u[m][0];
+                        u[1] += lattice.u[m][1];
+                        count += 1;
+                    }
+                }
+
+                if count > 0 {
+                    h /= count as f32;
+                    u = [u[0] / count as f32, u[1] / count as f32];
+                }
+
+                self.set(lattice, n, h.max(0.), inflow.unwrap_or(u));
+            }
+        }
+    }
+
+    pub fn step(&mut self, lattice: &mut Lattice) {
+        self.compute_macroscopic(lattice);
+        self.collide(lattice);
+        self.stream(lattice);
+    }
+
+    /// Total water volume on wet and dry nodes.
+    pub fn volume(&self, lattice: &Lattice) -> f32 {
+        lattice.total_mass()
+    }
+}
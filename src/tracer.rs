@@ -0,0 +1,190 @@
+//! Lagrangian tracer particles advected through a `VectorField`.
+//!
+//! Massless tracers follow the interpolated velocity exactly (midpoint rule);
+//! inertial tracers relax towards it with a Stokes response time.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::field::VectorField;
+
+#[derive(Clone, Debug)]
+pub struct Tracer {
+    pub id: usize,
+    pub pos: [f32; 2],
+    pub vel: [f32; 2],
+    /// Most recent `(t, pos, vel)` samples, one per `advance`.
+    pub trajectory: VecDeque<(f32, [f32; 2], [f32; 2])>,
+}
+
+/// Line segment that releases `count` evenly spaced tracers every `interval` time units.
+#[derive(Clone, Copy, Debug)]
+pub struct Emitter {
+    pub start: [f32; 2],
+    pub end: [f32; 2],
+    pub count: usize,
+    pub interval: f32,
+}
+
+pub struct Tracers {
+    pub tracers: Vec<Tracer>,
+    /// Tracers that left the domain, kept for export.
+    pub finished: Vec<Tracer>,
+    pub emitters: Vec<Emitter>,
+    /// Stokes response time of inertial tracers, zero for massless ones.
+    pub response_time: f32,
+    /// Number of trajectory samples drawn as a trail.
+    pub trail_length: usize,
+    /// Number of trajectory samples kept per tracer, older ones are dropped.
+    pub history: usize,
+    pub time: f32,
+    next_id: usize,
+    /// Time of the last batch of each emitter.
+    last_emission: Vec<f32>,
+}
+
+impl Tracers {
+    pub fn new() -> Self {
+        Tracers {
+            tracers: vec![],
+            finished: vec![],
+            emitters: vec![],
+            response_time: 0.,
+            trail_length: 50,
+            history: 5000,
+            time: 0.,
+            next_id: 0,
+            last_emission: vec![],
+        }
+    }
+
+    pub fn seed(&mut self, pos: [f32; 2], vel: [f32; 2]) {
+        self.tracers.push(Tracer {
+            id: self.next_id,
+            pos,
+            vel,
+            trajectory: VecDeque::from(vec![(self.time, pos, vel)]),
+        });
+
+        self.next_id += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.tracers.clear();
+        self.finished.clear();
+        self.emitters.clear();
+        self.last_emission.clear();
+    }
+
+    fn emit(&mut self, e: &Emitter, field: &VectorField) {
+        for k in 0..e.count {
+            let t = (k as f32 + 0.5) / e.count as f32;
+            let pos = [
+                e.start[0] + t * (e.end[0] - e.start[0]),
+                e.start[1] + t * (e.end[1] - e.start[1]),
+            ];
+
+            if field.contains(pos) {
+                self.seed(pos, field.sample(pos));
+            }
+        }
+    }
+
+    /// Moves every tracer by `dt` through `field` and releases due emitter batches.
+    pub fn advance(&mut self, field: &VectorField, dt: f32) {
+        // emitters added since the last step are due at once
+        self.last_emission
+            .resize(self.emitters.len(), f32::NEG_INFINITY);
+
+        let time = self.time;
+        let due: Vec<Emitter> = self
+            .emitters
+            .iter()
+            .zip(self.last_emission.iter_mut())
+            .filter(|(e, last)| time - **last >= e.interval)
+            .map(|(e, last)| {
+                *last = time;
+                *e
+            })
+            .collect();
+
+        for e in due.iter() {
+            self.emit(e, field);
+        }
+
+        self.time += dt;
+
+        let tau = self.response_time;
+        let history = self.history.max(1);
+
+        for tracer in self.tracers.iter_mut() {
+            if tau > 0. {
+                // implicit Stokes drag, stable for any dt / tau
+                let u = field.sample(tracer.pos);
+
+                for ((v, x), u) in tracer.vel.iter_mut().zip(tracer.pos.iter_mut()).zip(u) {
+                    *v = (*v + dt / tau * u) / (1. + dt / tau);
+                    *x += dt * *v;
+                }
+            } else {
+                let u0 = field.sample(tracer.pos);
+                let mid = [
+                    tracer.pos[0] + 0.5 * dt * u0[0],
+                    tracer.pos[1] + 0.5 * dt * u0[1],
+                ];
+                let u = field.sample(mid);
+
+                tracer.vel = u;
+                tracer.pos = [tracer.pos[0] + dt * u[0], tracer.pos[1] + dt * u[1]];
+            }
+
+            if tracer.trajectory.len() >= history {
+                tracer.trajectory.pop_front();
+            }
+
+            tracer
+                .trajectory
+                .push_back((self.time, tracer.pos, tracer.vel));
+        }
+
+        let (inside, outside): (Vec<_>, Vec<_>) =
+            self.tracers.drain(..).partition(|t| field.contains(t.pos));
+
+        self.tracers = inside;
+        self.finished.extend(outside);
+    }
+
+    /// Last `trail_length` positions of a tracer, oldest first.
+    pub fn trail<'a>(&self, tracer: &'a Tracer) -> impl Iterator<Item = [f32; 2]> + 'a {
+        let skip = tracer.trajectory.len().saturating_sub(self.trail_length);
+
+        tracer.trajectory.iter().skip(skip).map(|(_, pos, _)| *pos)
+    }
+
+    /// Writes every sample still kept as `id,t,x,y,u,v`.
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        writeln!(out, "id,t,x,y,u,v")?;
+
+        for tracer in self.finished.iter().chain(self.tracers.iter()) {
+            for (t, pos, vel) in tracer.trajectory.iter() {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    tracer.id, t, pos[0], pos[1], vel[0], vel[1]
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Tracers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,82 @@
+//! How lattice-gas walls reflect the particles that reach them.
+//!
+//! Bounce-back sends every particle back the way it came, which makes the
+//! wall no-slip. Specular reflection mirrors it about the wall, keeping its
+//! tangential velocity, which makes the wall free-slip. Diffuse reflection
+//! does one or the other at random, drawn afresh for every node and step, so
+//! the tangential momentum leaving the wall is zero on average whatever
+//! arrived: a rough no-slip wall.
+//!
+//! The mirrors are the reflections `d -> (m - d) mod n` of the `n` equally
+//! spaced lattice directions. The orientation of the wall at a node is the
+//! sum of the directions to its neighbours that are not walls, snapped to the
+//! nearest mirror axis; nodes without one, such as the middle of a wall one
+//! node thick, bounce back.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reflection {
+    BounceBack,
+    Specular,
+    Diffuse,
+}
+
+impl Reflection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Reflection::BounceBack => "bounce-back",
+            Reflection::Specular => "specular",
+            Reflection::Diffuse => "diffuse",
+        }
+    }
+
+    pub fn next(&self) -> Reflection {
+        match self {
+            Reflection::BounceBack => Reflection::Specular,
+            Reflection::Specular => Reflection::Diffuse,
+            Reflection::Diffuse => Reflection::BounceBack,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "bounce" | "bounce-back" => Some(Reflection::BounceBack),
+            "specular" => Some(Reflection::Specular),
+            "diffuse" => Some(Reflection::Diffuse),
+            _ => None,
+        }
+    }
+}
+
+/// Mirror `m` of a wall node whose neighbour in direction `d` is open to the
+/// fluid when `open(d)`, or `None` if the wall has no clear orientation there.
+pub fn mirror<F: Fn(usize) -> bool>(velocities: &[[f32; 2]], open: F) -> Option<usize> {
+    let normal = velocities
+        .iter()
+        .enumerate()
+        .filter(|&(d, _)| open(d))
+        .fold([0., 0.], |[x, y], (_, c)| [x + c[0], y + c[1]]);
+
+    if normal[0].hypot(normal[1]) < 1e-3 {
+        return None;
+    }
+
+    let step = std::f32::consts::PI / velocities.len() as f32;
+    let angle = (normal[1].atan2(normal[0]) / step).round() * step;
+    let n = [angle.cos(), angle.sin()];
+
+    // where the first direction goes
+    let c = velocities[0];
+    let dot = c[0] * n[0] + c[1] * n[1];
+    let r = [c[0] - 2. * dot * n[0], c[1] - 2. * dot * n[1]];
+
+    (0..velocities.len()).max_by(|&a, &b| {
+        let along = |d: usize| velocities[d][0] * r[0] + velocities[d][1] * r[1];
+        along(a).partial_cmp(&along(b)).unwrap()
+    })
+}
+
+/// Direction a particle moving along `d` leaves in after mirror `m`.
+#[inline]
+pub fn mirrored(d: usize, m: usize, directions: usize) -> usize {
+    (m + directions - d) % directions
+}
@@ -0,0 +1,222 @@
+//! Stability watchdog for the lattice Boltzmann solvers.
+//!
+//! Every `interval` steps the lattice is scanned for non-finite values,
+//! negative populations and excessive Mach numbers. The first offending node
+//! (in storage order) is reported together with its 3x3 neighbourhood, and a
+//! full snapshot can be written for post-mortem analysis.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::d2q9::{Lattice, NodeType, CS2, Q};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Violation {
+    NonFinite,
+    NegativePopulation { k: usize, value: f32 },
+    Mach(f32),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::NonFinite => write!(f, "non-finite population"),
+            Violation::NegativePopulation { k, value } => {
+                write!(f, "negative population f{} = {:.3e}", k, value)
+            }
+            Violation::Mach(ma) => write!(f, "Mach number {:.3}", ma),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct NodeState {
+    pub i: usize,
+    pub j: usize,
+    pub node_type: NodeType,
+    pub f: [f32; Q],
+    pub rho: f32,
+    pub u: [f32; 2],
+}
+
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub step: usize,
+    pub i: usize,
+    pub j: usize,
+    pub violation: Violation,
+    pub neighborhood: Vec<NodeState>,
+    /// Where the snapshot went, if one was written.
+    pub snapshot: Option<PathBuf>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "step {}: {} at node ({}, {})",
+            self.step, self.violation, self.i, self.j
+        )?;
+
+        for node in self.neighborhood.iter() {
+            writeln!(
+                f,
+                "  ({:>4}, {:>4}) {:?} rho = {:.4} u = [{:.4}, {:.4}] f = {:.3?}",
+                node.i, node.j, node.node_type, node.rho, node.u[0], node.u[1], node.f
+            )?;
+        }
+
+        if let Some(path) = &self.snapshot {
+            writeln!(f, "  snapshot written to {}", path.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct Watchdog {
+    /// Steps between checks.
+    pub interval: usize,
+    pub max_mach: f32,
+    /// Populations below this count as negative.
+    pub min_population: f32,
+    pub snapshot: Option<PathBuf>,
+}
+
+impl Watchdog {
+    pub fn new(interval: usize) -> Self {
+        Watchdog {
+            interval,
+            max_mach: 0.3,
+            min_population: 0.,
+            snapshot: None,
+        }
+    }
+
+    fn inspect(&self, lattice: &Lattice, n: usize) -> Option<Violation> {
+        if lattice.node_type[n].is_solid() {
+            return None;
+        }
+
+        let f = &lattice.f[n];
+
+        if f.iter().any(|x| !x.is_finite()) {
+            return Some(Violation::NonFinite);
+        }
+
+        if let Some((k, &value)) = f.iter().enumerate().find(|(_, &x)| x < self.min_population) {
+            return Some(Violation::NegativePopulation { k, value });
+        }
+
+        let rho: f32 = f.iter().sum();
+        let [ux, uy] = lattice.u[n];
+        let ma = (ux * ux + uy * uy).sqrt() / CS2.sqrt();
+
+        if !rho.is_finite() || !ma.is_finite() {
+            Some(Violation::NonFinite)
+        } else if ma > self.max_mach {
+            Some(Violation::Mach(ma))
+        } else {
+            None
+        }
+    }
+
+    /// Checks the lattice if `step` is due. The Mach check uses the velocity
+    /// from the last `compute_macroscopic`.
+    pub fn check(&self, lattice: &Lattice, step: usize) -> Result<(), Report> {
+        if self.interval == 0 || !step.is_multiple_of(self.interval) {
+            return Ok(());
+        }
+
+        let first = (0..lattice.f.len())
+            .into_par_iter()
+            .find_first(|&n| self.inspect(lattice, n).is_some());
+
+        let n = match first {
+            Some(n) => n,
+            None => return Ok(()),
+        };
+
+        let (i, j) = (n % lattice.width, n / lattice.width);
+
+        let mut neighborhood = vec![];
+
+        for dj in -1..=1 {
+            for di in -1..=1 {
+                let m = lattice.neighbor(i, j, di, dj);
+
+                neighborhood.push(NodeState {
+                    i: m % lattice.width,
+                    j: m / lattice.width,
+                    node_type: lattice.node_type[m],
+                    f: lattice.f[m],
+                    rho: lattice.rho[m],
+                    u: lattice.u[m],
+                });
+            }
+        }
+
+        let snapshot = match &self.snapshot {
+            Some(path) => match write_snapshot(lattice, path) {
+                Ok(()) => Some(path.clone()),
+                Err(e) => {
+                    eprintln!("could not write snapshot {}: {}", path.display(), e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        Err(Report {
+            step,
+            i,
+            j,
+            violation: self.inspect(lattice, n).unwrap(),
+            neighborhood,
+            snapshot,
+        })
+    }
+}
+
+/// Writes every node as `i,j,type,rho,ux,uy,f0,...,f8`.
+pub fn write_snapshot<P: AsRef<Path>>(lattice: &Lattice, path: P) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    write!(out, "i,j,type,rho,ux,uy")?;
+    for k in 0..Q {
+        write!(out, ",f{}", k)?;
+    }
+    writeln!(out)?;
+
+    for j in 0..lattice.height {
+        for i in 0..lattice.width {
+            let n = lattice.index(i, j);
+
+            let node_type = match lattice.node_type[n] {
+                NodeType::Fluid => "fluid",
+                NodeType::Boundary => "boundary",
+                NodeType::Inflow(_) => "inflow",
+                NodeType::Sink => "sink",
+                NodeType::MovingWall(_) => "moving_wall",
+            };
+
+            write!(
+                out,
+                "{},{},{},{},{},{}",
+                i, j, node_type, lattice.rho[n], lattice.u[n][0], lattice.u[n][1]
+            )?;
+
+            for k in 0..Q {
+                write!(out, ",{}", lattice.f[n][k])?;
+            }
+
+            writeln!(out)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! Coarse-grained FHP fields: the Galilean factor and exact averages of
+//! uniform lattices.
+
+use lbm::coarse::{self, CoarseGraining, Kernel};
+use lbm::fhp::{self, Lattice, Model, NodeType, REST, VELOCITIES};
+use lbm::wall::Reflection;
+
+const MODELS: [Model; 3] = [Model::FhpI, Model::FhpII, Model::FhpIII];
+
+/// Lattice with every node in `state`, a wall across row 5.
+fn uniform(state: u8) -> Lattice {
+    let mut lattice = Lattice::new(30, 20);
+    lattice.nodes.iter_mut().for_each(|s| *s = state);
+
+    for i in 0..lattice.width {
+        let n = lattice.index(i, 5);
+        lattice.node_type[n] = NodeType::Boundary(Reflection::BounceBack);
+    }
+
+    lattice
+}
+
+fn momentum(state: u8) -> [f32; 2] {
+    (0..6)
+        .filter(|&d| state >> d & 1 != 0)
+        .fold([0., 0.], |[x, y], d| {
+            [x + VELOCITIES[d][0], y + VELOCITIES[d][1]]
+        })
+}
+
+fn assert_near(a: [f32; 2], b: [f32; 2], what: &str) {
+    assert!(
+        (a[0] - b[0]).abs() < 1e-5 && (a[1] - b[1]).abs() < 1e-5,
+        "{}: {:?} != {:?}",
+        what,
+        a,
+        b
+    );
+}
+
+#[test]
+fn galilean_factor_vanishes_at_half_filling() {
+    for &model in MODELS.iter() {
+        let channels = model.channels() as f32;
+
+        assert!(coarse::galilean_factor(model, 0.5 * channels).abs() < 1e-6);
+        assert!(coarse::galilean_factor(model, 0.6 * channels) < 0.);
+
+        // 1/2 for six channels and 7/12 with a rest channel, times the
+        // occupation dependence
+        let front = if model == Model::FhpI { 0.5 } else { 7. / 12. };
+
+        for &d in [0.1f32, 0.2, 0.3, 0.45].iter() {
+            let g = coarse::galilean_factor(model, d * channels);
+            let expected = front * (1. - 2. * d) / (1. - d);
+
+            assert!((g - expected).abs() < 1e-6, "{} at {}", model.name(), d);
+        }
+    }
+}
+
+#[test]
+fn uniform_lattice_averages_exactly_with_either_kernel() {
+    // three particles with momentum and a rest particle
+    let state = 0b0000011 | 1 << REST;
+    let lattice = uniform(state);
+    let (mass, m) = (fhp::mass(state) as f32, momentum(state));
+
+    for &kernel in [Kernel::Box([2., 1.5]), Kernel::Gaussian(1.5)].iter() {
+        let mut coarse = CoarseGraining::new(Model::FhpII, kernel, 4, 1);
+
+        assert!(coarse.fields().is_none());
+
+        coarse.add(&lattice);
+        let fields = coarse.fields().unwrap();
+
+        // every sample, edges and the wall row's neighbours included
+        assert_eq!((fields.density.width, fields.density.height), (8, 5));
+
+        let g = coarse::galilean_factor(Model::FhpII, mass);
+
+        for (k, &rho) in fields.density.data.iter().enumerate() {
+            let what = format!("{} sample {}", kernel.name(), k);
+
+            assert!((rho - mass).abs() < 1e-5, "{}: {}", what, rho);
+            assert_near(fields.momentum.data[k], m, &what);
+            assert_near(fields.velocity.data[k], [m[0] / mass, m[1] / mass], &what);
+            assert_near(
+                fields.corrected.data[k],
+                [g * m[0] / mass, g * m[1] / mass],
+                &what,
+            );
+        }
+    }
+}
+
+#[test]
+fn velocity_is_the_ratio_of_the_averages() {
+    let (a, b) = (0b000001, 0b111111 | 1 << REST);
+    let mut coarse = CoarseGraining::new(Model::FhpII, Kernel::Box([1., 1.]), 5, 2);
+
+    // the first snapshot drops out once two more are in
+    for &state in [0b001001, a, b].iter() {
+        coarse.add(&uniform(state));
+    }
+
+    let fields = coarse.fields().unwrap();
+    let mass = 0.5 * (fhp::mass(a) + fhp::mass(b)) as f32;
+    let m = [0.5 * momentum(a)[0], 0.5 * momentum(a)[1]];
+
+    assert!((fields.density.get(2, 2) - mass).abs() < 1e-5);
+    assert_near(fields.momentum.get(2, 2), m, "momentum");
+    assert_near(
+        fields.velocity.get(2, 2),
+        [m[0] / mass, m[1] / mass],
+        "velocity",
+    );
+}
+
+#[test]
+fn box_reaches_as_far_as_its_half_width_along_each_axis() {
+    // a row of rest particles between full rows
+    let mut lattice = uniform(0b111111 | 1 << REST);
+
+    for i in 0..lattice.width {
+        let n = lattice.index(i, 2);
+        lattice.nodes[n] = 1 << REST;
+    }
+
+    let density = |kernel| {
+        let mut coarse = CoarseGraining::new(Model::FhpII, kernel, 2, 1);
+        coarse.add(&lattice);
+        coarse.fields().unwrap().density.get(5, 1)
+    };
+
+    // short of the next row however wide, and over three once past it
+    assert!((density(Kernel::Box([6., 0.8])) - 1.).abs() < 1e-5);
+    assert!(density(Kernel::Box([6., 0.9])) > 4.);
+}
@@ -0,0 +1,473 @@
+//! Conservation laws of every collision operator, checked over all node states
+//! of the lattice gases and over randomly drawn populations for the lattice
+//! Boltzmann models.
+
+use lbm::d2q9::{self, forcing, Lattice, CS2, E, Q};
+use lbm::fhp::{self, CollisionTable, Model, MultiSpin, NodeType, RANDOM_BITS, REST};
+use lbm::hpp;
+use lbm::rng::CounterRng;
+use lbm::shallow;
+use lbm::wall::Reflection;
+
+const MODELS: [Model; 3] = [Model::FhpI, Model::FhpII, Model::FhpIII];
+
+/// Random populations drawn per node for the lattice Boltzmann checks.
+const SAMPLES: usize = 10_000;
+
+fn assert_close(a: f32, b: f32, tolerance: f32, what: &str) {
+    assert!(
+        (a - b).abs() <= tolerance * (1. + b.abs()),
+        "{}: {} != {}",
+        what,
+        a,
+        b
+    );
+}
+
+fn moments(f: &[f32; Q]) -> (f32, [f32; 2], [[f32; 2]; 2]) {
+    let mut rho = 0.;
+    let mut m = [0., 0.];
+    let mut p = [[0.; 2]; 2];
+
+    for (k, e) in E.iter().enumerate() {
+        let e = [e[0] as f32, e[1] as f32];
+
+        rho += f[k];
+
+        for a in 0..2 {
+            m[a] += f[k] * e[a];
+
+            for b in 0..2 {
+                p[a][b] += f[k] * e[a] * e[b];
+            }
+        }
+    }
+
+    (rho, m, p)
+}
+
+#[test]
+fn hpp_collision_conserves_mass_momentum_and_energy() {
+    for s in 0..1 << hpp::DIRECTIONS {
+        let node: hpp::Node = [s & 1 != 0, s & 2 != 0, s & 4 != 0, s & 8 != 0];
+        let out = hpp::collide(&node);
+
+        // every particle moves at unit speed, so energy is the mass
+        assert_eq!(hpp::mass(&out), hpp::mass(&node), "mass of {:?}", node);
+        assert_eq!(
+            hpp::momentum(&out),
+            hpp::momentum(&node),
+            "momentum of {:?}",
+            node
+        );
+    }
+}
+
+#[test]
+fn hpp_collision_is_a_permutation() {
+    let mut seen = [false; 1 << hpp::DIRECTIONS];
+
+    for s in 0..1 << hpp::DIRECTIONS {
+        let node: hpp::Node = [s & 1 != 0, s & 2 != 0, s & 4 != 0, s & 8 != 0];
+        let out = hpp::collide(&node);
+        let t = out
+            .iter()
+            .enumerate()
+            .fold(0, |t, (d, &b)| t | (b as usize) << d);
+
+        assert!(!seen[t], "two states collide into {:?}", out);
+        seen[t] = true;
+    }
+}
+
+#[test]
+fn hpp_streaming_loses_particles_only_through_open_edges() {
+    use hpp::{Edge, Edges};
+
+    let (width, height) = (7, 5);
+
+    for s in [
+        "periodic",
+        "reflecting",
+        "open",
+        "periodic,periodic,open,reflecting",
+    ]
+    .iter()
+    {
+        let edges = Edges::parse(s).unwrap();
+        let mut pulled = vec![0; width * height * hpp::DIRECTIONS];
+        let mut lost = 0;
+
+        for i in 0..width {
+            for j in 0..height {
+                for d in 0..hpp::DIRECTIONS {
+                    match edges.source(i, j, d, width, height) {
+                        Some((si, sj, sd)) => pulled[(width * sj + si) * hpp::DIRECTIONS + sd] += 1,
+                        None => lost += 1,
+                    }
+                }
+            }
+        }
+
+        // every channel is pulled at most once, and only an open edge leaves
+        // channels behind, one per node along it
+        assert!(
+            pulled.iter().all(|&p| p <= 1),
+            "{} pulls a channel twice",
+            s
+        );
+
+        let open = [
+            (edges.left, height),
+            (edges.right, height),
+            (edges.top, width),
+            (edges.bottom, width),
+        ]
+        .iter()
+        .filter(|(edge, _)| *edge == Edge::Open)
+        .map(|(_, n)| n)
+        .sum::<usize>();
+
+        assert_eq!(lost, open, "{}", s);
+        assert_eq!(pulled.iter().filter(|&&p| p == 0).count(), open, "{}", s);
+    }
+
+    assert_eq!(Edges::parse("periodic,open,reflecting,reflecting"), None);
+}
+
+#[test]
+fn fhp_collisions_conserve_mass_and_momentum() {
+    for &model in MODELS.iter() {
+        let table = CollisionTable::new(model);
+
+        for s in 0..1u32 << model.channels() {
+            let s = s as u8;
+
+            for r in 0..1 << RANDOM_BITS {
+                let out = table.collide(s, r);
+
+                assert_eq!(
+                    fhp::mass(out),
+                    fhp::mass(s),
+                    "{} mass {:07b}",
+                    model.name(),
+                    s
+                );
+                assert_eq!(
+                    fhp::momentum(out),
+                    fhp::momentum(s),
+                    "{} momentum {:07b}",
+                    model.name(),
+                    s
+                );
+                assert!(
+                    out >> model.channels() == 0,
+                    "{} leaves its channels",
+                    model.name()
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn fhp_i_conserves_energy() {
+    // every FHP-I particle moves at unit speed, so energy is conserved with
+    // mass as long as no rest particle appears; FHP-II and FHP-III trade
+    // kinetic energy with the rest particle
+    let table = CollisionTable::new(Model::FhpI);
+
+    for s in 0..1u8 << Model::FhpI.channels() {
+        for r in 0..1 << RANDOM_BITS {
+            let out = table.collide(s, r);
+
+            assert_eq!(out & 1 << REST, 0);
+            assert_eq!(fhp::mass(out), fhp::mass(s));
+        }
+    }
+}
+
+#[test]
+fn fhp_collisions_are_permutations() {
+    // with every random draw a bijection, the collision is doubly stochastic
+    // and satisfies semi-detailed balance
+    for &model in MODELS.iter() {
+        let table = CollisionTable::new(model);
+
+        for r in 0..1 << RANDOM_BITS {
+            let mut seen = vec![false; 1 << model.channels()];
+
+            for s in 0..1u32 << model.channels() {
+                let out = table.collide(s as u8, r) as usize;
+
+                assert!(!seen[out], "{} draw {} is not a bijection", model.name(), r);
+                seen[out] = true;
+            }
+        }
+    }
+}
+
+#[test]
+fn fhp_collisions_commute_with_reversal() {
+    for &model in MODELS.iter() {
+        let table = CollisionTable::new(model);
+
+        for s in 0..1u32 << model.channels() {
+            let s = s as u8;
+            let outputs: Vec<_> = (0..1 << RANDOM_BITS).map(|r| table.collide(s, r)).collect();
+
+            for r in 0..1 << RANDOM_BITS {
+                let reversed = table.collide(fhp::reverse(s), r);
+
+                assert!(
+                    outputs.iter().any(|&out| fhp::reverse(out) == reversed),
+                    "{} {:07b}",
+                    model.name(),
+                    s
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn fhp_lattices_conserve_particles_and_momentum() {
+    let rng = CounterRng::new(44);
+    let (width, height) = (100, 32);
+
+    for &model in MODELS.iter() {
+        let table = CollisionTable::new(model);
+        let mut lattice = fhp::Lattice::new(width, height);
+
+        for (n, node) in lattice.nodes.iter_mut().enumerate() {
+            *node = rng.below([0, n as u64, 0], 1 << model.channels()) as u8;
+        }
+
+        let totals = |nodes: &[u8]| {
+            nodes.iter().fold((0, [0, 0]), |(m, [x, y]), &s| {
+                let p = fhp::momentum(s);
+                (m + fhp::mass(s), [x + p[0], y + p[1]])
+            })
+        };
+
+        let before = totals(&lattice.nodes);
+        let mut spin = MultiSpin::new(&lattice, &table);
+
+        for t in 0..20 {
+            let random = fhp::random_bits(rng.stream(1), t);
+
+            lattice.step(&table, random, |_, _| 0.);
+            spin.step(random, |_, _| 0.);
+        }
+
+        assert_eq!(totals(&lattice.nodes), before, "{} scalar", model.name());
+
+        spin.store(&mut lattice);
+        assert_eq!(
+            totals(&lattice.nodes),
+            before,
+            "{} multi-spin",
+            model.name()
+        );
+    }
+}
+
+#[test]
+fn fhp_walls_conserve_particles_and_slip_walls_tangential_momentum() {
+    // a channel periodic along x between walls on the top and bottom rows,
+    // flowing along it
+    let rng = CounterRng::new(47);
+    let (width, height) = (100, 32);
+    let p = fhp::equilibrium(Model::FhpII, 2.1, [0.3, 0.]);
+
+    for &reflection in [
+        Reflection::BounceBack,
+        Reflection::Specular,
+        Reflection::Diffuse,
+    ]
+    .iter()
+    {
+        let table = CollisionTable::new(Model::FhpII);
+        let mut lattice = fhp::Lattice::new(width, height);
+
+        for i in 0..width {
+            for &j in [0, height - 1].iter() {
+                let n = lattice.index(i, j);
+                lattice.node_type[n] = NodeType::Boundary(reflection);
+            }
+        }
+
+        for j in 1..height - 1 {
+            for i in 0..width {
+                let n = lattice.index(i, j);
+
+                for (k, p) in p.iter().enumerate() {
+                    if rng.uniform([0, n as u64, k as u64]) < *p {
+                        lattice.nodes[n] |= 1 << k;
+                    }
+                }
+            }
+        }
+
+        let totals = |nodes: &[u8]| {
+            nodes.iter().fold((0, 0), |(m, x), &s| {
+                (m + fhp::mass(s), x + fhp::momentum(s)[0])
+            })
+        };
+
+        let before = totals(&lattice.nodes);
+        let mut spin = MultiSpin::new(&lattice, &table);
+
+        for t in 0..50 {
+            let random = fhp::random_bits(rng.stream(1), t);
+
+            lattice.step(&table, random, |_, _| 0.);
+            spin.step(random, |_, _| 0.);
+        }
+
+        let after = totals(&lattice.nodes);
+        let name = reflection.name();
+
+        assert_eq!(after.0, before.0, "{} mass", name);
+
+        if reflection == Reflection::Specular {
+            assert_eq!(after.1, before.1, "{} momentum", name);
+        } else {
+            assert!(after.1.abs() < before.1.abs(), "{} momentum", name);
+        }
+
+        let scalar = lattice.nodes.clone();
+        spin.store(&mut lattice);
+        assert_eq!(lattice.nodes, scalar, "{} engines differ", name);
+    }
+}
+
+#[test]
+fn bgk_collision_conserves_mass_and_momentum() {
+    let rng = CounterRng::new(44);
+    let mut lattice = Lattice::new(100, 100, 0.6);
+
+    for (n, f) in lattice.f.iter_mut().enumerate() {
+        let rho = 0.5 + rng.uniform([0, n as u64, 0]);
+        let u = [
+            0.2 * (rng.uniform([1, n as u64, 0]) - 0.5),
+            0.2 * (rng.uniform([1, n as u64, 1]) - 0.5),
+        ];
+
+        // equilibrium plus a non-equilibrium part of up to 20%
+        for (k, f) in d2q9::equilibrium(rho, u)
+            .iter()
+            .zip(f.iter_mut())
+            .enumerate()
+        {
+            *f.1 = f.0 * (0.8 + 0.4 * rng.uniform([2, n as u64, k as u64]));
+        }
+    }
+
+    assert_eq!(lattice.f.len(), SAMPLES);
+
+    let before: Vec<_> = lattice.f.iter().map(moments).collect();
+
+    lattice.compute_macroscopic();
+    lattice.collide();
+
+    for (f, (rho, m, _)) in lattice.f.iter().zip(before) {
+        let (rho_after, m_after, _) = moments(f);
+
+        assert_close(rho_after, rho, 1e-5, "mass");
+        assert_close(m_after[0], m[0], 1e-5, "x momentum");
+        assert_close(m_after[1], m[1], 1e-5, "y momentum");
+    }
+}
+
+#[test]
+fn guo_forcing_adds_the_force_and_no_mass() {
+    let rng = CounterRng::new(44);
+    let tau = 0.7;
+
+    for n in 0..SAMPLES as u64 {
+        let u = [
+            0.2 * (rng.uniform([0, n, 0]) - 0.5),
+            0.2 * (rng.uniform([0, n, 1]) - 0.5),
+        ];
+        let force = [rng.uniform([1, n, 0]) - 0.5, rng.uniform([1, n, 1]) - 0.5];
+
+        let (mass, m, _) = moments(&forcing(tau, u, force));
+
+        assert_close(mass, 0., 1e-6, "mass");
+        assert_close(m[0], (1. - 0.5 / tau) * force[0], 1e-5, "x momentum");
+        assert_close(m[1], (1. - 0.5 / tau) * force[1], 1e-5, "y momentum");
+    }
+}
+
+#[test]
+fn equilibria_have_the_right_moments() {
+    let rng = CounterRng::new(44);
+    let gravity = 0.1;
+
+    for n in 0..SAMPLES as u64 {
+        let rho = 0.5 + rng.uniform([0, n, 0]);
+        let u = [
+            0.2 * (rng.uniform([1, n, 0]) - 0.5),
+            0.2 * (rng.uniform([1, n, 1]) - 0.5),
+        ];
+
+        // Navier-Stokes: pressure cs^2 rho
+        let (mass, m, p) = moments(&d2q9::equilibrium(rho, u));
+
+        assert_close(mass, rho, 1e-5, "D2Q9 mass");
+
+        for a in 0..2 {
+            assert_close(m[a], rho * u[a], 1e-5, "D2Q9 momentum");
+
+            for b in 0..2 {
+                let delta = if a == b { CS2 * rho } else { 0. };
+                assert_close(
+                    p[a][b],
+                    delta + rho * u[a] * u[b],
+                    1e-5,
+                    "D2Q9 momentum flux",
+                );
+            }
+        }
+
+        // shallow water: hydrostatic pressure g h^2 / 2
+        let h = rho;
+        let (mass, m, p) = moments(&shallow::equilibrium(gravity, h, u));
+
+        assert_close(mass, h, 1e-5, "shallow-water depth");
+
+        for a in 0..2 {
+            assert_close(m[a], h * u[a], 1e-5, "shallow-water momentum");
+
+            for b in 0..2 {
+                let delta = if a == b { 0.5 * gravity * h * h } else { 0. };
+                assert_close(
+                    p[a][b],
+                    delta + h * u[a] * u[b],
+                    1e-5,
+                    "shallow-water momentum flux",
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn fhp_equilibria_have_the_right_moments() {
+    for &model in MODELS.iter() {
+        for &(rho, u) in [(1.5, [0.1, 0.]), (2.1, [0.1, -0.1]), (3., [0., 0.2])].iter() {
+            let n = fhp::equilibrium(model, rho, u);
+
+            let mass: f32 = n.iter().sum();
+            let m = fhp::VELOCITIES
+                .iter()
+                .zip(n.iter())
+                .fold([0., 0.], |[x, y], (c, n)| [x + n * c[0], y + n * c[1]]);
+
+            assert_close(mass, rho, 1e-4, "FHP mass");
+            assert_close(m[0], rho * u[0], 1e-4, "FHP x momentum");
+            assert_close(m[1], rho * u[1], 1e-4, "FHP y momentum");
+        }
+    }
+}
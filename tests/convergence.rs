@@ -0,0 +1,106 @@
+//! Steady and periodic state detection on synthetic signals.
+
+use lbm::convergence::{Convergence, State};
+use lbm::d2q9::Lattice;
+
+const INTERVAL: usize = 10;
+const WINDOW: usize = 200;
+
+/// Feeds `steps` steps of a flow with uniform velocity `u(t)` and force
+/// `force(t)`, returning the state after each sample.
+fn run<U, F>(convergence: &mut Convergence, steps: usize, u: U, force: F) -> Vec<State>
+where
+    U: Fn(f32) -> [f32; 2],
+    F: Fn(f32) -> [f32; 2],
+{
+    let mut lattice = Lattice::new(10, 5, 0.6);
+    let mut states = vec![];
+
+    for step in 1..=steps {
+        if step % INTERVAL != 0 {
+            continue;
+        }
+
+        lattice.fill(1., u(step as f32));
+        states.push(convergence.update(&lattice, step, force(step as f32)));
+    }
+
+    states
+}
+
+#[test]
+fn settled_flow_is_steady_only_after_a_full_window() {
+    let mut convergence = Convergence::new(INTERVAL, WINDOW);
+    let states = run(&mut convergence, 3000, |_| [0.05, 0.], |_| [1e-3, 0.]);
+
+    // the first sample has nothing to compare with
+    assert!(states[..WINDOW].iter().all(|s| *s == State::Running));
+    assert_eq!(states[WINDOW], State::Steady);
+    assert_eq!(*states.last().unwrap(), State::Steady);
+}
+
+#[test]
+fn slowly_drifting_flow_is_not_steady() {
+    let mut convergence = Convergence::new(INTERVAL, WINDOW);
+    let states = run(
+        &mut convergence,
+        3000,
+        |t| [0.05 * (1. + 1e-3 * t), 0.],
+        |_| [1e-3, 0.],
+    );
+
+    assert!(states.iter().all(|s| *s == State::Running));
+}
+
+#[test]
+fn shedding_force_is_periodic_with_its_period() {
+    let period = 370.;
+    let omega = 2. * std::f32::consts::PI / period;
+
+    let mut convergence = Convergence::new(INTERVAL, WINDOW);
+    let states = run(
+        &mut convergence,
+        4000,
+        |t| [0.05, 0.01 * (omega * t).sin()],
+        |t| {
+            [
+                2e-3 + 1e-4 * (2. * omega * t).cos(),
+                5e-4 * (omega * t).sin(),
+            ]
+        },
+    );
+
+    match *states.last().unwrap() {
+        State::Periodic {
+            period: p,
+            mean,
+            amplitude,
+        } => {
+            assert!((p - period).abs() < 0.02 * period, "period {}", p);
+            assert!((mean[0] - 2e-3).abs() < 2e-5, "mean {:?}", mean);
+            assert!(mean[1].abs() < 2e-5, "mean {:?}", mean);
+            assert!(
+                (amplitude[1] - 5e-4).abs() < 1e-5,
+                "amplitude {:?}",
+                amplitude
+            );
+        }
+        s => panic!("{}", s),
+    }
+}
+
+#[test]
+fn decaying_oscillation_is_not_periodic() {
+    let period = 370.;
+    let omega = 2. * std::f32::consts::PI / period;
+
+    let mut convergence = Convergence::new(INTERVAL, WINDOW);
+    let states = run(
+        &mut convergence,
+        4000,
+        |t| [0.05, 0.01 * (omega * t).sin()],
+        |t| [2e-3, 5e-4 * (-t / 2000.).exp() * (omega * t).sin()],
+    );
+
+    assert!(states.iter().all(|s| *s == State::Running));
+}
@@ -0,0 +1,304 @@
+//! The FHP models: the collisions each generated table performs, the
+//! multi-spin engine against the scalar one, chirality and the inflow
+//! equilibrium.
+
+use lbm::fhp::{self, CollisionTable, Model, MultiSpin, NodeType, RANDOM_BITS, REST, VELOCITIES};
+use lbm::rng::CounterRng;
+use lbm::wall::Reflection;
+
+/// The post-collision states of `s` over every random draw.
+fn outputs(table: &CollisionTable, s: u8) -> Vec<u8> {
+    (0..1 << RANDOM_BITS).map(|r| table.collide(s, r)).collect()
+}
+
+/// Particles moving along directions `a` and `b`, modulo six.
+fn pair(a: usize, b: usize) -> u8 {
+    1 << (a % 6) | 1 << (b % 6)
+}
+
+/// Number of times `state` occurs in `outputs`.
+fn count(outputs: &[u8], state: u8) -> usize {
+    outputs.iter().filter(|&&s| s == state).count()
+}
+
+#[test]
+fn model_names_parse_loosely() {
+    assert_eq!(Model::parse("1"), Some(Model::FhpI));
+    assert_eq!(Model::parse("II"), Some(Model::FhpII));
+    assert_eq!(Model::parse("fhp-iii"), Some(Model::FhpIII));
+    assert_eq!(Model::parse("FHP3"), Some(Model::FhpIII));
+    assert_eq!(Model::parse("4"), None);
+
+    for &model in [Model::FhpI, Model::FhpII, Model::FhpIII].iter() {
+        assert_eq!(Model::parse(model.name()), Some(model));
+    }
+}
+
+#[test]
+fn fhp_i_rotates_head_on_pairs_either_way_and_swaps_triples() {
+    let table = CollisionTable::new(Model::FhpI);
+
+    for d in 0..3 {
+        let head_on = pair(d, d + 3);
+        let out = outputs(&table, head_on);
+
+        // turned by +-60 degrees with equal chance
+        for &turn in [1, 5].iter() {
+            let rotated = pair(d + turn, d + turn + 3);
+            assert_eq!(count(&out, rotated), 2, "pair {:06b}", head_on);
+        }
+
+        // the dual four-particle state does the same with the holes
+        let holes = fhp::MOVING ^ head_on;
+        let out = outputs(&table, holes);
+
+        for &turn in [1, 5].iter() {
+            let rotated = fhp::MOVING ^ pair(d + turn, d + turn + 3);
+            assert_eq!(count(&out, rotated), 2, "holes {:06b}", holes);
+        }
+    }
+
+    assert_eq!(outputs(&table, 0b010101), vec![0b101010; 4]);
+    assert_eq!(outputs(&table, 0b101010), vec![0b010101; 4]);
+
+    // with momentum there is nothing else to go to
+    for s in 0..1 << 6 {
+        if fhp::momentum(s) != [0, 0] {
+            assert_eq!(outputs(&table, s), vec![s; 4], "{:06b}", s);
+        }
+    }
+}
+
+#[test]
+fn fhp_ii_trades_a_rest_particle_for_a_pair() {
+    let table = CollisionTable::new(Model::FhpII);
+    let rest = 1 << REST;
+
+    for d in 0..6 {
+        // a moving particle and a rest one become two at +-60 degrees
+        let single = rest | 1 << d;
+        let split = pair(d + 1, d + 5);
+
+        assert_eq!(outputs(&table, single), vec![split; 4], "{:07b}", single);
+        assert_eq!(outputs(&table, split), vec![single; 4], "{:07b}", split);
+    }
+
+    // next to a head-on pair the rest particle is a spectator
+    for &out in outputs(&table, rest | 0b001001).iter() {
+        assert!(out & rest != 0 && out != rest | 0b001001, "{:07b}", out);
+    }
+
+    // three particles with momentum do not collide
+    assert_eq!(outputs(&table, 0b000111), vec![0b000111; 4]);
+}
+
+#[test]
+fn fhp_iii_collides_every_state_that_has_a_partner() {
+    let table = CollisionTable::new(Model::FhpIII);
+
+    for s in 0..1u8 << 7 {
+        let partners: Vec<u8> = (0..1u8 << 7)
+            .filter(|&t| {
+                t != s && fhp::mass(t) == fhp::mass(s) && fhp::momentum(t) == fhp::momentum(s)
+            })
+            .collect();
+
+        let out = outputs(&table, s);
+
+        if partners.is_empty() {
+            assert_eq!(out, vec![s; 4], "{:07b}", s);
+            continue;
+        }
+
+        // uniform over the other states of equal mass and momentum
+        for &t in partners.iter() {
+            assert_eq!(count(&out, t), 4 / partners.len(), "{:07b} -> {:07b}", s, t);
+        }
+    }
+}
+
+#[test]
+fn multi_spin_engine_matches_the_scalar_one_bit_for_bit() {
+    let rng = CounterRng::new(37);
+    let height = 12;
+
+    // rows of one word, exactly one, and a word and a bit
+    for &width in [50, 64, 65, 130].iter() {
+        for &model in [Model::FhpI, Model::FhpII, Model::FhpIII].iter() {
+            let table = CollisionTable::new(model);
+            let mut lattice = fhp::Lattice::new(width, height);
+            let inflow = fhp::equilibrium(model, 2., [0.3, 0.]);
+
+            for j in 0..height {
+                for i in 0..width {
+                    let n = lattice.index(i, j);
+
+                    lattice.node_type[n] = match (i, j) {
+                        (_, 0) => NodeType::Boundary(Reflection::BounceBack),
+                        (_, j) if j == height - 1 => NodeType::Boundary(Reflection::Diffuse),
+                        (0, _) => NodeType::Inflow(inflow),
+                        (i, _) if i == width - 1 => NodeType::Sink,
+                        (i, j) if i % 17 == 5 && j % 4 == 2 => {
+                            NodeType::Boundary(Reflection::Specular)
+                        }
+                        _ => NodeType::Fluid,
+                    };
+
+                    lattice.nodes[n] = rng.below([0, n as u64, 0], 1 << model.channels()) as u8;
+                }
+            }
+
+            let mut spin = MultiSpin::new(&lattice, &table);
+            let mut unpacked = fhp::Lattice::new(width, height);
+
+            for t in 0..30 {
+                let random = fhp::random_bits(rng.stream(1), t);
+                let uniform = |n: usize, d: usize| rng.stream(2).uniform([t, n as u64, d as u64]);
+
+                lattice.step(&table, random, uniform);
+                spin.step(random, uniform);
+
+                spin.store(&mut unpacked);
+                assert_eq!(
+                    unpacked.nodes,
+                    lattice.nodes,
+                    "{} on {} nodes, step {}",
+                    model.name(),
+                    width,
+                    t
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn chirality_is_drawn_per_node_and_step() {
+    let table = CollisionTable::new(Model::FhpI);
+    let rng = CounterRng::new(38);
+    let (width, height) = (200, 50);
+
+    // every node a head-on pair along direction 0, turned one way or the other
+    let turned = |t: u64| {
+        let mut lattice = fhp::Lattice::new(width, height);
+        lattice.nodes.iter_mut().for_each(|s| *s = pair(0, 3));
+        lattice.collide(&table, fhp::random_bits(rng, t));
+
+        lattice
+            .nodes
+            .iter()
+            .map(|&s| s == pair(1, 4))
+            .collect::<Vec<_>>()
+    };
+
+    let (first, again, next) = (turned(0), turned(0), turned(1));
+    let fraction = |n: usize| n as f32 / (width * height) as f32;
+
+    assert_eq!(first, again);
+
+    // no handedness across the lattice, and none kept by a node
+    let one_way = fraction(first.iter().filter(|&&l| l).count());
+    let kept = fraction(
+        first
+            .iter()
+            .zip(next.iter())
+            .filter(|(a, b)| a == b)
+            .count(),
+    );
+
+    assert!((one_way - 0.5).abs() < 0.02, "{} turn one way", one_way);
+    assert!((kept - 0.5).abs() < 0.02, "{} keep their turn", kept);
+}
+
+#[test]
+fn equilibrium_has_the_target_moments_and_fermi_dirac_form() {
+    for &model in [Model::FhpI, Model::FhpII, Model::FhpIII].iter() {
+        for &(rho, u) in [(1.8, [0., 0.]), (2.1, [0.3, 0.]), (3., [-0.1, 0.2])].iter() {
+            let p = fhp::equilibrium(model, rho, u);
+
+            let mass: f32 = p.iter().sum();
+            let momentum = (0..6).fold([0., 0.], |[x, y], d| {
+                [x + p[d] * VELOCITIES[d][0], y + p[d] * VELOCITIES[d][1]]
+            });
+
+            assert!((mass - rho).abs() < 1e-5, "{} mass {}", model.name(), mass);
+
+            for a in 0..2 {
+                assert!(
+                    (momentum[a] - rho * u[a]).abs() < 1e-5,
+                    "{} momentum {:?}",
+                    model.name(),
+                    momentum
+                );
+            }
+
+            // ln(1/p - 1) is linear in the velocity, so opposite channels
+            // average to its value at rest, that of the rest channel
+            let x = |k: usize| (1. / p[k] as f64 - 1.).ln();
+            let origin = (x(0) + x(3)) / 2.;
+
+            for d in 1..3 {
+                assert!(
+                    ((x(d) + x(d + 3)) / 2. - origin).abs() < 1e-4,
+                    "{}",
+                    model.name()
+                );
+            }
+
+            if model != Model::FhpI {
+                assert!((x(REST) - origin).abs() < 1e-4, "{}", model.name());
+            }
+        }
+
+        if model == Model::FhpI {
+            assert_eq!(fhp::equilibrium(model, 2., [0.1, 0.])[REST], 0.);
+        }
+    }
+}
+
+#[test]
+fn inflow_injects_the_target_density_and_velocity_on_average() {
+    let rng = CounterRng::new(40);
+    let (rho, u) = (2.1, [0.25, -0.05]);
+    let p = fhp::equilibrium(Model::FhpII, rho, u);
+
+    let mut lattice = fhp::Lattice::new(100, 100);
+    lattice
+        .node_type
+        .iter_mut()
+        .for_each(|t| *t = NodeType::Inflow(p));
+
+    let mut mass = 0;
+    let mut momentum = [0, 0];
+
+    for t in 0..10 {
+        lattice.nodes.iter_mut().for_each(|s| *s = 0);
+        lattice.inject(|n, d| rng.uniform([t, n as u64, d as u64]));
+
+        for &s in lattice.nodes.iter() {
+            let m = fhp::momentum(s);
+            mass += fhp::mass(s);
+            momentum = [momentum[0] + m[0], momentum[1] + m[1]];
+        }
+    }
+
+    // momentum counts in units of (1/2, sqrt(3)/2)
+    let nodes = (10 * lattice.nodes.len()) as f32;
+    let density = mass as f32 / nodes;
+    let velocity = [
+        0.5 * momentum[0] as f32 / nodes / density,
+        0.866_025_4 * momentum[1] as f32 / nodes / density,
+    ];
+
+    assert!((density - rho).abs() < 0.015, "density {}", density);
+    assert!(
+        (velocity[0] - u[0]).abs() < 0.005,
+        "velocity {:?}",
+        velocity
+    );
+    assert!(
+        (velocity[1] - u[1]).abs() < 0.005,
+        "velocity {:?}",
+        velocity
+    );
+}
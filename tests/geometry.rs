@@ -0,0 +1,213 @@
+//! Analytic shapes: NACA sections, SVG paths, boolean combinations and where
+//! lattice links cross their surface.
+
+use lbm::geometry::{Geometry, Naca, PathError};
+
+#[test]
+fn naca_designations_parse_into_fractions_of_the_chord() {
+    assert_eq!(
+        Naca::parse("2412"),
+        Some(Naca {
+            camber: 0.02,
+            position: 0.4,
+            thickness: 0.12
+        })
+    );
+    assert_eq!(Naca::parse(" NACA 0012 "), Naca::parse("0012"));
+    assert_eq!(Naca::parse("naca4415").map(|n| n.thickness), Some(0.15));
+
+    for &bad in ["241", "23012", "24a2", "NACA", ""].iter() {
+        assert_eq!(Naca::parse(bad), None, "{:?}", bad);
+    }
+}
+
+#[test]
+fn naca_outline_has_its_thickness_and_camber_and_a_closed_trailing_edge() {
+    let points = 64;
+
+    // trailing edge, over the upper surface to the nose, back underneath
+    let symmetric = Naca::parse("0012").unwrap().outline(points);
+
+    assert_eq!(symmetric.len(), 2 * points);
+    assert!((symmetric[0][0] - 1.).abs() < 1e-6 && symmetric[0][1].abs() < 1e-4);
+    assert_eq!(symmetric[points], [0., 0.]);
+
+    // upper station k sits above lower station k
+    let thickness = (1..points)
+        .map(|k| symmetric[points - k][1] - symmetric[points + k][1])
+        .fold(0., f32::max);
+
+    assert!((thickness - 0.12).abs() < 1e-3, "thickness {}", thickness);
+
+    for k in 1..points {
+        assert!((symmetric[points - k][1] + symmetric[points + k][1]).abs() < 1e-6);
+    }
+
+    // the mean of both surfaces follows the camber line up to its maximum
+    let cambered = Naca::parse("2412").unwrap().outline(points);
+    let camber = (1..points)
+        .map(|k| 0.5 * (cambered[points - k][1] + cambered[points + k][1]))
+        .fold(0., f32::max);
+
+    assert!((camber - 0.02).abs() < 1e-3, "camber {}", camber);
+    assert!(cambered[0][1].abs() < 1e-4);
+}
+
+#[test]
+fn airfoil_is_pitched_nose_up_about_its_quarter_chord() {
+    let naca = Naca::parse("0012").unwrap();
+    let (leading_edge, chord, angle) = ([10., 20.], 40., 0.1f32);
+    let airfoil = Geometry::airfoil(naca, leading_edge, chord, angle);
+
+    // y points down, so the tail drops below the quarter chord
+    let along = |s: f32| {
+        let r = (s - 0.25) * chord;
+        [20. + r * angle.cos(), 20. + r * angle.sin()]
+    };
+
+    for &s in [0.05, 0.3, 0.6, 0.95].iter() {
+        assert!(airfoil.contains(along(s)), "chord at {}", s);
+    }
+
+    assert!(!airfoil.contains([along(0.95)[0], 20.]));
+}
+
+#[test]
+fn svg_lines_work_in_absolute_and_relative_form() {
+    let absolute = Geometry::svg_path("M 0 0 L 10 0 L 10 10 Z").unwrap();
+
+    assert!(absolute.contains([7., 3.]));
+    assert!(!absolute.contains([3., 7.]));
+
+    // implicit line-tos, horizontal and vertical lines, packed numbers
+    for d in [
+        "m 0 0 l 10 0 l 0 10 l -10 0 z",
+        "M0,0 h10 v10 H0 Z",
+        "m0 0 10 0 0 10-10 0z",
+    ]
+    .iter()
+    {
+        let square = Geometry::svg_path(d).unwrap();
+
+        assert_eq!(square, Geometry::rectangle([0., 0.], [10., 10.]), "{}", d);
+    }
+
+    // a second subpath inside the first is a hole, and a relative move after
+    // a close starts from the closed subpath's start
+    let frame = Geometry::svg_path("M0 0H10V10H0Z m3 3h4v4h-4z").unwrap();
+
+    assert!(frame.contains([1., 1.]));
+    assert!(!frame.contains([5., 5.]));
+}
+
+#[test]
+fn svg_smooth_curves_reflect_the_previous_control_point() {
+    // an S bulging up then down, closed along the x axis
+    let cubic = Geometry::svg_path("M0 0 C 0 10 10 10 10 0 S 20 -10 20 0 Z").unwrap();
+
+    assert!(cubic.contains([5., 5.]));
+    assert!(cubic.contains([13., -5.]));
+    assert!(!cubic.contains([5., -5.]));
+    assert!(!cubic.contains([15., 5.]));
+
+    let quadratic = Geometry::svg_path("M0 0 Q 5 10 10 0 T 20 0 Z").unwrap();
+
+    assert!(quadratic.contains([5., 3.]));
+    assert!(quadratic.contains([15., -3.]));
+    assert!(!quadratic.contains([5., 6.]));
+}
+
+#[test]
+fn svg_errors_say_what_went_wrong() {
+    assert_eq!(
+        Geometry::svg_path("M0 0 A 1 1 0 0 1 5 5"),
+        Err(PathError::Arc { offset: 5 })
+    );
+    assert_eq!(
+        Geometry::svg_path("M0 0 L 5"),
+        Err(PathError::MissingNumber { command: 'L' })
+    );
+    assert_eq!(
+        Geometry::svg_path("M0 0 X 1"),
+        Err(PathError::Unexpected {
+            offset: 5,
+            found: 'X'
+        })
+    );
+    assert_eq!(
+        Geometry::svg_path("5 5"),
+        Err(PathError::Unexpected {
+            offset: 0,
+            found: '5'
+        })
+    );
+}
+
+#[test]
+fn shapes_combine_and_move() {
+    let ring = Geometry::circle([0., 0.], 5.).difference(Geometry::circle([0., 0.], 2.));
+
+    assert!(ring.contains([3.5, 0.]));
+    assert!(ring.contains([0., -5.]));
+    assert!(!ring.contains([1., 1.]));
+    assert!(!ring.contains([4., 4.]));
+
+    let both = ring
+        .clone()
+        .union(Geometry::rectangle([-1., -1.], [1., 1.]));
+
+    assert!(both.contains([0.5, 0.5]));
+    assert!(both.contains([3.5, 0.]));
+
+    // a bar along x turned a quarter round one end lies along y
+    let bar = Geometry::rectangle([0., -1.], [10., 1.]);
+    let turned = bar.clone().rotate(std::f32::consts::FRAC_PI_2, [0., 0.]);
+
+    assert!(turned.contains([0., 8.]));
+    assert!(!turned.contains([8., 0.]));
+
+    let moved = bar.translate([0., 5.]).scale(2., [0., 5.]);
+
+    // twice as long and thick about its middle line
+    assert!(moved.contains([19., 6.5]));
+    assert!(moved.contains([1., 3.5]));
+    assert!(!moved.contains([21., 5.]));
+    assert!(!moved.contains([10., 7.5]));
+    assert!(Geometry::circle([1., 1.], 1.)
+        .scale(3., [0., 0.])
+        .contains([3., 5.9]));
+}
+
+#[test]
+fn distance_is_signed_and_links_find_the_surface() {
+    let circle = Geometry::circle([0., 0.], 2.);
+    let square = Geometry::rectangle([0., 0.], [4., 4.]);
+
+    assert!((circle.distance([3., 4.]) - 3.).abs() < 1e-6);
+    assert!((circle.distance([0., 0.5]) + 1.5).abs() < 1e-6);
+    assert!((square.distance([1., 2.]) + 1.).abs() < 1e-6);
+    assert!((square.distance([7., 8.]) - 5.).abs() < 1e-6);
+
+    let hole = square.difference(Geometry::circle([2., 2.], 1.));
+    assert!((hole.distance([2., 2.]) - 1.).abs() < 1e-6);
+
+    // a link from (-3, 0) two nodes long enters the circle half-way
+    let q = circle.link_fraction([-3., 0.], [2., 0.]).unwrap();
+    assert!((q - 0.5).abs() < 1e-5, "{}", q);
+
+    let q = circle.link_fraction([2., 2.], [-1., -1.]).unwrap();
+    assert!((q - (2. - 2f32.sqrt())).abs() < 1e-5, "{}", q);
+
+    assert_eq!(circle.link_fraction([0., 0.], [1., 0.]), None);
+    assert_eq!(circle.link_fraction([3., 0.], [1., 0.]), None);
+
+    // every node of a small lattice, along both axes
+    let directions = [[1., 0.], [0., 1.]];
+    let fractions = Geometry::circle([2., 2.], 1.5).link_fractions(5, 5, &directions);
+
+    assert_eq!(fractions.len(), 25);
+    assert!((fractions[5 * 2][0].unwrap() - 0.5).abs() < 1e-5);
+    assert!((fractions[2][1].unwrap() - 0.5).abs() < 1e-5);
+    assert_eq!(fractions[5 * 2 + 2], [None, None]);
+    assert_eq!(fractions[0], [None, None]);
+}
@@ -0,0 +1,287 @@
+//! The multi-spin HPP lattice against a direct node-by-node implementation,
+//! over every kind of edge and node.
+
+use lbm::hpp::{self, Edges, Lattice, Node, NodeType, Reversal, DIRECTIONS};
+use lbm::rng::CounterRng;
+use lbm::wall::Reflection;
+
+/// One step of the reference: collide or reflect every node, redraw the
+/// inflow, then pull every channel from its source.
+fn step<F, U>(nodes: &[Node], node_type: F, edges: &Edges, width: usize, uniform: U) -> Vec<Node>
+where
+    F: Fn(usize, usize) -> NodeType,
+    U: Fn(usize, usize) -> f32,
+{
+    let height = nodes.len() / width;
+
+    let collided: Vec<Node> = (0..nodes.len())
+        .map(|n| {
+            let (i, j) = (n % width, n / width);
+            let node = nodes[n];
+
+            match node_type(i, j) {
+                NodeType::Fluid => hpp::collide(&node),
+                NodeType::Wall(reflection) => {
+                    let specular = match reflection {
+                        Reflection::BounceBack => false,
+                        Reflection::Specular => true,
+                        Reflection::Diffuse => uniform(n, DIRECTIONS) < 0.5,
+                    };
+
+                    match hpp::wall_mirror(&node_type, edges, i, j, width, height) {
+                        Some(m) if specular => hpp::reflect(&node, m),
+                        _ => [node[2], node[3], node[0], node[1]],
+                    }
+                }
+                NodeType::Inflow(p) => [0, 1, 2, 3].map(|d| uniform(n, d) < p),
+                NodeType::Sink => [false; DIRECTIONS],
+            }
+        })
+        .collect();
+
+    (0..nodes.len())
+        .map(|n| {
+            let (i, j) = (n % width, n / width);
+
+            [0, 1, 2, 3].map(|d| match edges.source(i, j, d, width, height) {
+                Some((si, sj, sd)) => collided[width * sj + si][sd],
+                None => false,
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn multi_spin_lattice_matches_the_reference() {
+    let rng = CounterRng::new(49);
+
+    let cases = [
+        (100, 37, "periodic"),
+        (128, 20, "reflecting"),
+        (64, 16, "open"),
+        (131, 24, "periodic,periodic,open,reflecting"),
+        (70, 30, "reflecting,open,periodic,periodic"),
+    ];
+
+    for &(width, height, edges) in cases.iter() {
+        let edges = Edges::parse(edges).unwrap();
+
+        // a disc of each wall, a sink column and an inflow column
+        let node_type = |i: usize, j: usize| {
+            let disc = |x: f32, y: f32| {
+                let (dx, dy) = (i as f32 - x * width as f32, j as f32 - y * height as f32);
+                dx * dx + dy * dy < 16.
+            };
+
+            if disc(0.25, 0.3) {
+                NodeType::Wall(Reflection::BounceBack)
+            } else if disc(0.5, 0.6) {
+                NodeType::Wall(Reflection::Specular)
+            } else if disc(0.75, 0.4) {
+                NodeType::Wall(Reflection::Diffuse)
+            } else if i == 2 {
+                NodeType::Inflow(0.3)
+            } else if i == width - 3 {
+                NodeType::Sink
+            } else {
+                NodeType::Fluid
+            }
+        };
+
+        let mut lattice = Lattice::new(width, height, edges);
+        lattice.set_geometry(node_type);
+
+        let mut nodes: Vec<Node> = (0..width * height)
+            .map(|n| [0, 1, 2, 3].map(|d| rng.uniform([0, n as u64, d as u64]) < 0.4))
+            .collect();
+
+        for (n, &node) in nodes.iter().enumerate() {
+            lattice.set(n % width, n / width, node);
+        }
+
+        for t in 1..=40 {
+            let uniform = |n: usize, d: usize| rng.uniform([t, n as u64, d as u64]);
+
+            nodes = step(&nodes, node_type, &edges, width, uniform);
+            lattice.step(uniform);
+        }
+
+        for (n, node) in nodes.iter().enumerate() {
+            assert_eq!(
+                lattice.node(n % width, n / width),
+                *node,
+                "{}x{} {:?} node {}",
+                width,
+                height,
+                edges,
+                n
+            );
+        }
+
+        let mass = nodes.iter().map(hpp::mass).sum::<u32>() as u64;
+        assert_eq!(lattice.mass(), mass);
+    }
+}
+
+#[test]
+fn closed_lattice_conserves_mass_and_momentum() {
+    let rng = CounterRng::new(50);
+    let (width, height) = (200, 50);
+    let mut lattice = Lattice::new(width, height, Edges::parse("periodic").unwrap());
+
+    for j in 0..height {
+        for i in 0..width {
+            let n = (width * j + i) as u64;
+            lattice.set(i, j, [0, 1, 2, 3].map(|d| rng.uniform([n, d, 0]) < 0.3));
+        }
+    }
+
+    let (mass, momentum) = (lattice.mass(), lattice.momentum());
+
+    for _ in 0..100 {
+        lattice.step(|_, _| 0.);
+    }
+
+    assert_eq!(lattice.mass(), mass);
+    assert_eq!(lattice.momentum(), momentum);
+}
+
+/// A closed box of gas around walls of both reversible kinds, denser in the
+/// middle.
+fn closed_box(edges: &str) -> Lattice {
+    let rng = CounterRng::new(51);
+    let (width, height) = (150, 60);
+
+    let mut lattice = Lattice::new(width, height, Edges::parse(edges).unwrap());
+
+    lattice.set_geometry(|i, j| {
+        if (i as isize - 40).abs() + (j as isize - 20).abs() < 6 {
+            NodeType::Wall(Reflection::BounceBack)
+        } else if (i as isize - 100).pow(2) + (j as isize - 40).pow(2) < 36 {
+            NodeType::Wall(Reflection::Specular)
+        } else {
+            NodeType::Fluid
+        }
+    });
+
+    for j in 0..height {
+        for i in 0..width {
+            let p = if (60..90).contains(&i) { 0.6 } else { 0.2 };
+            let n = (width * j + i) as u64;
+
+            lattice.set(i, j, [0, 1, 2, 3].map(|d| rng.uniform([n, d, 0]) < p));
+        }
+    }
+
+    lattice
+}
+
+#[test]
+fn turning_back_twice_recovers_the_initial_state() {
+    for &edges in [
+        "reflecting",
+        "periodic",
+        "periodic,periodic,reflecting,reflecting",
+    ]
+    .iter()
+    {
+        let mut lattice = closed_box(edges);
+        let start = lattice.planes.clone();
+
+        assert!(lattice.reversible());
+
+        for _ in 0..300 {
+            lattice.step(|_, _| 0.);
+        }
+
+        assert!(lattice.distance(&start) > 0);
+
+        lattice.turn_back();
+
+        for _ in 0..300 {
+            lattice.step(|_, _| 0.);
+        }
+
+        lattice.turn_back();
+
+        assert_eq!(lattice.distance(&start), 0, "{}", edges);
+    }
+
+    assert!(!closed_box("open").reversible());
+}
+
+#[test]
+fn one_flipped_bit_spoils_the_reversal() {
+    let mut lattice = closed_box("reflecting");
+    let start = lattice.planes.clone();
+
+    for _ in 0..300 {
+        lattice.step(|_, _| 0.);
+    }
+
+    lattice.turn_back();
+
+    let mut node = lattice.node(75, 30);
+    node[0] = !node[0];
+    lattice.set(75, 30, node);
+
+    for _ in 0..300 {
+        lattice.step(|_, _| 0.);
+    }
+
+    lattice.turn_back();
+
+    // the error spreads through the collisions to thousands of bits
+    assert!(lattice.distance(&start) > 1000);
+}
+
+#[test]
+fn reversal_retraces_as_many_steps_as_it_took() {
+    for &length in [1, 40].iter() {
+        let mut lattice = closed_box("periodic");
+        let mut run = Reversal::new(&lattice, Some(length));
+        let mut turned = vec![];
+        let mut t = 0;
+
+        // the driver's order: step, count it, then see whether it is over
+        let bits = loop {
+            lattice.step(|_, _| 0.);
+
+            if run.advance(&mut lattice, false) {
+                turned.push(t);
+            }
+
+            t += 1;
+
+            if let Some(bits) = run.finish(&mut lattice) {
+                break bits;
+            }
+
+            assert!(t < 3 * length, "{} steps and still running", t);
+        };
+
+        assert_eq!(turned, vec![length - 1]);
+        assert_eq!((t, run.steps), (2 * length, length));
+        assert_eq!(bits, 0, "after {}", length);
+    }
+
+    // left to the caller, a run never turns back by itself
+    let mut lattice = closed_box("reflecting");
+    let mut run = Reversal::new(&lattice, None);
+
+    for _ in 0..25 {
+        lattice.step(|_, _| 0.);
+        assert!(!run.advance(&mut lattice, false));
+    }
+
+    run.turn_back(&mut lattice, true);
+    assert_eq!(run.remaining, Some(25));
+
+    for _ in 0..25 {
+        assert!(run.finish(&mut lattice).is_none());
+        lattice.step(|_, _| 0.);
+        run.advance(&mut lattice, false);
+    }
+
+    assert!(run.finish(&mut lattice).unwrap() > 0);
+}
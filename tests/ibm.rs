@@ -0,0 +1,125 @@
+//! Immersed-boundary kernels, forcing and the elastic filament.
+
+use lbm::d2q9::{Lattice, NodeType};
+use lbm::ibm::{Filament, ImmersedBoundary, Kernel, RigidBody};
+
+const KERNELS: [Kernel; 3] = [Kernel::TwoPoint, Kernel::ThreePoint, Kernel::FourPoint];
+
+/// Channel with a uniform inflow on the left, a sink on the right and walls
+/// along the top and bottom.
+fn channel(width: usize, height: usize, u: f32) -> Lattice {
+    let mut lattice = Lattice::new(width, height, 0.56);
+
+    for j in 0..height {
+        let n = lattice.index(0, j);
+        lattice.node_type[n] = NodeType::Inflow([u, 0.]);
+
+        let n = lattice.index(width - 1, j);
+        lattice.node_type[n] = NodeType::Sink;
+    }
+
+    for i in 0..width {
+        let n = lattice.index(i, 0);
+        lattice.node_type[n] = NodeType::Boundary;
+
+        let n = lattice.index(i, height - 1);
+        lattice.node_type[n] = NodeType::Boundary;
+    }
+
+    lattice.fill(1., [u, 0.]);
+
+    lattice
+}
+
+#[test]
+fn kernels_are_partitions_of_unity_with_no_first_moment() {
+    for kernel in KERNELS.iter() {
+        for s in 0..20 {
+            let x = s as f32 / 20.;
+            let r = kernel.radius().ceil() as isize + 1;
+
+            let (sum, first) = (-r..=r).fold((0., 0.), |(sum, first), i| {
+                let phi = kernel.phi(x - i as f32);
+                (sum + phi, first + (x - i as f32) * phi)
+            });
+
+            assert!((sum - 1.).abs() < 1e-5, "{:?} at {}: {}", kernel, x, sum);
+            assert!(first.abs() < 1e-5, "{:?} at {}: {}", kernel, x, first);
+        }
+    }
+}
+
+#[test]
+fn direct_forcing_holds_the_fluid_at_a_fixed_cylinder() {
+    let u = 0.05;
+    let mut lattice = channel(120, 50, u);
+    let ib = ImmersedBoundary::new(Kernel::ThreePoint, 3);
+    let mut cylinder = RigidBody::circle([30., 25.], 6.);
+
+    for _ in 0..1000 {
+        lattice.clear_force();
+        lattice.compute_macroscopic();
+        ib.apply(&mut lattice, &mut cylinder.markers);
+        lattice.collide();
+        lattice.stream();
+    }
+
+    lattice.clear_force();
+    lattice.compute_macroscopic();
+
+    let slip = cylinder
+        .markers
+        .iter()
+        .map(|m| {
+            let (v, _) = ib.interpolate(&lattice, m.pos);
+            v[0].hypot(v[1])
+        })
+        .fold(0., f32::max);
+
+    assert!(slip < 0.1 * u, "slip {}", slip);
+
+    // the flow pushes the cylinder downstream, and symmetrically
+    let [fx, fy] = cylinder.force();
+
+    assert!(fx > 0., "drag {}", fx);
+    assert!(fy.abs() < 0.05 * fx, "lift {}", fy);
+}
+
+#[test]
+fn default_filament_stays_stable_behind_a_cylinder() {
+    let mut lattice = channel(200, 80, 0.05);
+    let ib = ImmersedBoundary::new(Kernel::ThreePoint, 3);
+    let mut cylinder = RigidBody::circle([40., 40.], 8.);
+    let mut filament = Filament::new([48., 40.], 0., 40., 1.);
+
+    for t in 0..300 {
+        lattice.clear_force();
+        lattice.compute_macroscopic();
+
+        ib.apply(&mut lattice, &mut cylinder.markers);
+        ib.apply(&mut lattice, &mut filament.markers);
+
+        lattice.collide();
+        lattice.stream();
+
+        filament.anchor = Some(cylinder.markers[0].pos);
+        filament.advance();
+
+        let worst = lattice
+            .u
+            .iter()
+            .map(|u| u[0].hypot(u[1]))
+            .fold(0., f32::max);
+
+        assert!(worst < 0.15, "step {}: speed {}", t, worst);
+    }
+
+    // the springs keep it close to its rest length
+    let length: f32 = filament
+        .markers
+        .windows(2)
+        .map(|m| (m[1].pos[0] - m[0].pos[0]).hypot(m[1].pos[1] - m[0].pos[1]))
+        .sum();
+
+    assert!((length - 40.).abs() < 0.5, "length {}", length);
+}
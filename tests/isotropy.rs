@@ -0,0 +1,135 @@
+//! Isotropy of the velocity sets. Navier-Stokes behaviour needs the lattice
+//! tensors up to fourth order to be isotropic, which D2Q9 (with its weights)
+//! and FHP satisfy and HPP does not.
+
+use lbm::d2q9::{CS2, E, OPP, Q, W};
+use lbm::fhp::{self, DIRECTIONS, VELOCITIES};
+use lbm::hpp;
+
+fn delta(a: usize, b: usize) -> f64 {
+    if a == b {
+        1.
+    } else {
+        0.
+    }
+}
+
+/// `sum_k w_k c_k^a c_k^b ...` for every index tuple of the given order.
+fn tensor(weights: &[f64], velocities: &[[f64; 2]], order: usize) -> Vec<(Vec<usize>, f64)> {
+    (0..1 << order)
+        .map(|bits: usize| {
+            let indices: Vec<_> = (0..order).map(|o| bits >> o & 1).collect();
+            let sum = weights
+                .iter()
+                .zip(velocities.iter())
+                .map(|(w, c)| w * indices.iter().map(|&a| c[a]).product::<f64>())
+                .sum();
+
+            (indices, sum)
+        })
+        .collect()
+}
+
+/// Checks the tensors against `scale[order]` times the isotropic tensors: zero
+/// at odd orders, `delta_ab` at second and `delta_ab delta_cd + delta_ac
+/// delta_bd + delta_ad delta_bc` at fourth order.
+fn isotropic(weights: &[f64], velocities: &[[f64; 2]], scale: [f64; 5]) -> Result<(), String> {
+    for (order, scale) in scale.iter().enumerate() {
+        for (i, sum) in tensor(weights, velocities, order) {
+            let expected = scale
+                * match order {
+                    0 => 1.,
+                    2 => delta(i[0], i[1]),
+                    4 => {
+                        delta(i[0], i[1]) * delta(i[2], i[3])
+                            + delta(i[0], i[2]) * delta(i[1], i[3])
+                            + delta(i[0], i[3]) * delta(i[1], i[2])
+                    }
+                    _ => 0.,
+                };
+
+            if (sum - expected).abs() > 1e-6 {
+                return Err(format!("order {} {:?}: {} != {}", order, i, sum, expected));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn d2q9_weights_are_isotropic_to_fourth_order() {
+    let weights: Vec<_> = W.iter().map(|&w| w as f64).collect();
+    let velocities: Vec<_> = E.iter().map(|e| [e[0] as f64, e[1] as f64]).collect();
+    let cs2 = CS2 as f64;
+
+    isotropic(&weights, &velocities, [1., 0., cs2, 0., cs2 * cs2]).unwrap();
+}
+
+#[test]
+fn d2q9_opposites_reverse_the_velocities() {
+    for k in 0..Q {
+        assert_eq!(E[OPP[k]], [-E[k][0], -E[k][1]]);
+        assert_eq!(OPP[OPP[k]], k);
+        assert_eq!(W[OPP[k]], W[k]);
+    }
+}
+
+#[test]
+fn fhp_velocities_are_isotropic_to_fourth_order() {
+    // for b unit vectors in two dimensions the second-order tensor is b/2 and
+    // the fourth-order one b/8
+    let weights = [1.; DIRECTIONS];
+    let velocities: Vec<_> = VELOCITIES
+        .iter()
+        .map(|c| [c[0] as f64, c[1] as f64])
+        .collect();
+    let b = DIRECTIONS as f64;
+
+    isotropic(&weights, &velocities, [b, 0., b / 2., 0., b / 8.]).unwrap();
+}
+
+#[test]
+fn fhp_velocities_have_unit_length_and_opposites() {
+    for d in 0..DIRECTIONS {
+        let c = VELOCITIES[d];
+        let opposite = VELOCITIES[(d + DIRECTIONS / 2) % DIRECTIONS];
+
+        assert!((c[0] * c[0] + c[1] * c[1] - 1.).abs() < 1e-6);
+        assert!((c[0] + opposite[0]).abs() < 1e-6 && (c[1] + opposite[1]).abs() < 1e-6);
+        assert_eq!(
+            fhp::reverse(1 << d),
+            1 << ((d + DIRECTIONS / 2) % DIRECTIONS)
+        );
+    }
+}
+
+#[test]
+fn hpp_velocities_are_isotropic_to_second_order_only() {
+    let weights = [1.; hpp::DIRECTIONS];
+    let velocities: Vec<_> = hpp::VELOCITIES
+        .iter()
+        .map(|c| [c[0] as f64, c[1] as f64])
+        .collect();
+    let b = hpp::DIRECTIONS as f64;
+
+    // the isotropic part holds up to third order ...
+    isotropic(&weights, &velocities, [b, 0., b / 2., 0., b / 8.])
+        .expect_err("HPP is not isotropic at fourth order");
+
+    for (order, scale) in [(0, b), (1, 0.), (2, b / 2.), (3, 0.)].iter() {
+        for (i, sum) in tensor(&weights, &velocities, *order) {
+            let expected = match order {
+                0 => *scale,
+                2 => scale * delta(i[0], i[1]),
+                _ => 0.,
+            };
+
+            assert!((sum - expected).abs() < 1e-9, "order {} {:?}", order, i);
+        }
+    }
+
+    // ... but the fourth-order tensor has sum c_x^4 = 2 where isotropy needs 3 b / 8
+    let xxxx = tensor(&weights, &velocities, 4)[0].1;
+    assert!((xxxx - 2.).abs() < 1e-9);
+}
@@ -0,0 +1,156 @@
+//! Geometry masks: reading cells from pixel colours, saving them back, and
+//! the painting brush.
+
+use lbm::mask::{Brush, Cell, Mask, MaskError};
+
+const CELLS: [Cell; 5] = [
+    Cell::Fluid,
+    Cell::Wall,
+    Cell::Inflow,
+    Cell::Sink,
+    Cell::MovingWall,
+];
+
+/// Path in the temporary directory, unique to this process and `name`.
+fn temp(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("mask-{}-{}.png", std::process::id(), name))
+}
+
+/// Loads a mask from an image of the given pixels, one row of them.
+fn load(pixels: &[[u8; 4]], name: &str) -> Result<Mask, MaskError> {
+    let mut image = image::RgbaImage::new(pixels.len() as u32, 1);
+
+    for (x, &rgba) in pixels.iter().enumerate() {
+        image.put_pixel(x as u32, 0, image::Rgba(rgba));
+    }
+
+    let path = temp(name);
+    image.save(&path).unwrap();
+
+    let mask = Mask::load(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    mask
+}
+
+#[test]
+fn colours_are_read_at_half_intensity() {
+    let mask = load(
+        &[
+            [250, 240, 230, 255],
+            [20, 10, 100, 255],
+            [200, 40, 60, 255],
+            [0, 127, 128, 255],
+            [90, 180, 30, 255],
+            // transparent pixels are fluid whatever their colour
+            [0, 0, 0, 100],
+        ],
+        "colours",
+    )
+    .unwrap();
+
+    assert_eq!((mask.width, mask.height), (6, 1));
+    assert_eq!(
+        mask.cells,
+        vec![
+            Cell::Fluid,
+            Cell::Wall,
+            Cell::Inflow,
+            Cell::Sink,
+            Cell::MovingWall,
+            Cell::Fluid
+        ]
+    );
+}
+
+#[test]
+fn other_colours_are_rejected_with_their_pixel() {
+    match load(&[[255; 4], [255, 160, 0, 255], [0, 0, 0, 255]], "orange") {
+        Err(MaskError::Color { x, y, rgba }) => {
+            assert_eq!((x, y, rgba), (1, 0, [255, 160, 0, 255]));
+        }
+        other => panic!("{:?}", other),
+    }
+
+    assert!(matches!(
+        Mask::load(temp("missing")),
+        Err(MaskError::Image(_))
+    ));
+}
+
+#[test]
+fn cells_are_laid_out_like_the_pixels() {
+    // a wall at the end of the first row, an inflow and two sinks below
+    let image = image::RgbImage::from_fn(3, 2, |x, y| match (x, y) {
+        (2, 0) => image::Rgb([0, 0, 0]),
+        (_, 0) => image::Rgb([255; 3]),
+        (0, _) => image::Rgb([255, 0, 0]),
+        _ => image::Rgb([0, 0, 255]),
+    });
+
+    let path = temp("layout");
+    image.save(&path).unwrap();
+    let mask = Mask::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!((mask.width, mask.height), (3, 2));
+    assert_eq!(mask.get(2, 0), Cell::Wall);
+    assert_eq!(mask.get(0, 1), Cell::Inflow);
+    assert_eq!(mask.get(1, 1), Cell::Sink);
+
+    let solid = mask.map(|cell| cell != Cell::Fluid);
+    assert_eq!(solid, vec![false, false, true, true, true, true]);
+}
+
+#[test]
+fn saved_masks_load_back_the_same() {
+    let (width, height) = (7, 4);
+    let node_type: Vec<usize> = (0..width * height).map(|n| n * n % 5).collect();
+    let mask = Mask::from_nodes(width, height, &node_type, |&t| CELLS[t]);
+
+    assert_eq!(mask.get(3, 2), CELLS[(7 * 2 + 3) * (7 * 2 + 3) % 5]);
+
+    let path = temp("round-trip");
+    mask.save(&path).unwrap();
+    let loaded = Mask::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!((loaded.width, loaded.height), (width, height));
+    assert_eq!(loaded.cells, mask.cells);
+
+    let back = loaded.map(|cell| CELLS.iter().position(|&c| c == cell).unwrap());
+    assert_eq!(back, node_type);
+}
+
+#[test]
+fn brush_cycles_through_solid_cells_and_never_vanishes() {
+    let mut cell = Cell::Fluid;
+    let mut seen = vec![];
+
+    for _ in 0..4 {
+        cell = cell.next();
+        seen.push(cell);
+    }
+
+    assert_eq!(cell.next(), Cell::Wall);
+    assert_eq!(
+        seen,
+        vec![Cell::Wall, Cell::Inflow, Cell::Sink, Cell::MovingWall]
+    );
+
+    let mut brush = Brush::new();
+
+    for _ in 0..5 {
+        brush.shrink();
+    }
+
+    assert_eq!(brush.radius, 0.5);
+
+    brush.grow();
+    brush.grow();
+
+    let shape = brush.shape([10., 10.]);
+
+    assert!(shape.contains([11.4, 10.]));
+    assert!(!shape.contains([12.6, 10.]));
+}
@@ -0,0 +1,143 @@
+//! Open edges: the convective conditions on a wave leaving through the edge,
+//! and the sponge profile.
+
+use lbm::d2q9::{equilibrium, Lattice, E, Q};
+use lbm::outflow::{Convective, Edge, Outflow, Sponge};
+
+const WIDTH: usize = 30;
+const HEIGHT: usize = 3;
+
+/// Equilibrium of a velocity wave moving right at `SPEED` nodes per step.
+fn wave(x: f32, t: f32) -> [f32; Q] {
+    const SPEED: f32 = 0.3;
+    const LENGTH: f32 = 40.;
+
+    let phase = 2. * std::f32::consts::PI * (x - SPEED * t) / LENGTH;
+
+    equilibrium(1., [0.05 + 0.01 * phase.sin(), 0.])
+}
+
+/// Largest error of the populations entering through the right edge while
+/// the wave is imposed everywhere else, as streaming would leave it, and the
+/// edge is closed by `close`.
+fn edge_error<F: FnMut(&mut Lattice)>(mut close: F) -> f32 {
+    let mut lattice = Lattice::new(WIDTH, HEIGHT, 0.8);
+    let mut worst = 0f32;
+
+    for t in 0..400 {
+        for j in 0..HEIGHT {
+            for i in 0..WIDTH {
+                let n = lattice.index(i, j);
+                let f = wave(i as f32, t as f32);
+
+                for k in 0..Q {
+                    if i < WIDTH - 1 || E[k][0] >= 0 {
+                        lattice.f[n][k] = f[k];
+                    }
+                }
+            }
+        }
+
+        close(&mut lattice);
+
+        let exact = wave((WIDTH - 1) as f32, t as f32);
+        let f = lattice.f[lattice.index(WIDTH - 1, 1)];
+
+        if t > 100 {
+            for k in (0..Q).filter(|&k| E[k][0] < 0) {
+                worst = worst.max((f[k] - exact[k]).abs());
+            }
+        }
+    }
+
+    worst
+}
+
+#[test]
+fn convective_edges_let_a_wave_out_better_than_a_zero_gradient() {
+    let zero_gradient = edge_error(|lattice| {
+        for j in 0..HEIGHT {
+            let (b, m) = (lattice.index(WIDTH - 1, j), lattice.index(WIDTH - 2, j));
+            lattice.f[b] = lattice.f[m];
+        }
+    });
+
+    for &convective in [Convective::Fixed(0.3), Convective::Orlanski].iter() {
+        let mut outflow = Outflow::new();
+        outflow.set(Edge::Right, None, Some(convective));
+
+        let error = edge_error(|lattice| outflow.apply(lattice));
+
+        assert!(
+            error < 0.2 * zero_gradient,
+            "{:?}: {:e} against {:e}",
+            convective,
+            error,
+            zero_gradient
+        );
+    }
+}
+
+#[test]
+fn fixed_speed_update_is_implicit_upwind() {
+    let c = 0.5;
+    let mut lattice = Lattice::new(6, 4, 0.8);
+    let mut outflow = Outflow::new();
+    outflow.set(Edge::Top, None, Some(Convective::Fixed(c)));
+
+    // the first call copies the row inside
+    let (edge, inner) = (lattice.index(2, 0), lattice.index(2, 1));
+
+    lattice.fill(1.1, [0., -0.02]);
+    lattice.f[edge] = equilibrium(1., [0., 0.]);
+    outflow.apply(&mut lattice);
+
+    let before = lattice.f[edge];
+    assert_eq!(before, lattice.f[inner]);
+
+    lattice.fill(1., [0.01, -0.03]);
+    outflow.apply(&mut lattice);
+
+    let (after, inside) = (lattice.f[edge], lattice.f[inner]);
+
+    for k in 0..Q {
+        // only the populations coming in through the top are replaced
+        let expected = if E[k][1] > 0 {
+            (before[k] + c * inside[k]) / (1. + c)
+        } else {
+            inside[k]
+        };
+
+        assert!((after[k] - expected).abs() < 1e-7, "f{}", k);
+    }
+}
+
+#[test]
+fn sponge_relaxes_harder_towards_the_edge() {
+    let target = equilibrium(1., [0.05, 0.]);
+    let mut lattice = Lattice::new(20, 3, 0.8);
+    lattice.fill(1.02, [0., 0.01]);
+
+    let sponge = Sponge {
+        width: 5,
+        strength: 0.5,
+        rho: 1.,
+        u: [0.05, 0.],
+    };
+
+    let mut outflow = Outflow::new();
+    outflow.set(Edge::Left, Some(sponge), None);
+    outflow.apply(&mut lattice);
+
+    let distance = |i: usize| {
+        let f = lattice.f[lattice.index(i, 1)];
+        (0..Q).map(|k| (f[k] - target[k]).abs()).sum::<f32>()
+    };
+
+    for i in 0..5 {
+        assert!(distance(i) < distance(i + 1), "node {}", i);
+    }
+
+    let untouched = equilibrium(1.02, [0., 0.01]);
+    assert_eq!(lattice.f[lattice.index(5, 1)], untouched);
+}
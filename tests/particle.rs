@@ -0,0 +1,93 @@
+//! Rigid particles: shape moments and the momentum exchange with the fluid.
+
+use lbm::d2q9::Lattice;
+use lbm::particle::{Particle, Particles, Shape};
+
+#[test]
+fn shapes_have_the_right_area_and_polar_moment() {
+    let square = Shape::Polygon(vec![[-1., -1.], [1., -1.], [1., 1.], [-1., 1.]]);
+
+    assert!((square.area() - 4.).abs() < 1e-6);
+    assert!((square.polar_moment() - 16. / 6.).abs() < 1e-5);
+    assert!((square.bounding_radius() - 2f32.sqrt()).abs() < 1e-6);
+
+    // many-sided polygons approach the disc, whichever way they wind
+    let disc = Shape::Disc(3.);
+    let polygon = Shape::regular(400, 3.);
+
+    assert!((polygon.area() - disc.area()).abs() < 1e-3 * disc.area());
+    assert!((polygon.polar_moment() - disc.polar_moment()).abs() < 1e-3 * disc.polar_moment());
+
+    assert!(square.contains([0.9, -0.9]));
+    assert!(!square.contains([1.1, 0.]));
+    assert!(disc.contains([2., 2.]));
+    assert!(!disc.contains([2.2, 2.2]));
+}
+
+#[test]
+fn held_disc_takes_the_force_that_drives_the_fluid() {
+    let (size, g) = (30, 1e-5);
+    let mut lattice = Lattice::new(size, size, 1.);
+    let center = [size as f32 / 2. - 0.5, size as f32 / 2. - 0.5];
+
+    let mut particles = Particles::new(
+        &mut lattice,
+        vec![Particle::new(Shape::Disc(4.), 1., center)],
+    );
+
+    let fluid = (0..size * size)
+        .filter(|&n| particles.owner(n).is_none())
+        .count() as f32;
+
+    // periodic in both directions, so at steady state the body force on the
+    // fluid all ends up on the disc, which is never advanced
+    for _ in 0..5000 {
+        for (n, f) in lattice.force.iter_mut().enumerate() {
+            *f = if particles.owner(n).is_none() {
+                [g, 0.]
+            } else {
+                [0., 0.]
+            };
+        }
+
+        lattice.compute_macroscopic();
+        lattice.collide();
+        particles.exchange_momentum(&lattice);
+        lattice.stream();
+    }
+
+    let disc = &particles.particles[0];
+    let drive = g * fluid;
+
+    assert!(
+        (disc.force[0] - drive).abs() < 0.01 * drive,
+        "drag {} != {}",
+        disc.force[0],
+        drive
+    );
+    assert!(disc.force[1].abs() < 0.01 * drive, "lift {}", disc.force[1]);
+    assert!(disc.torque.abs() < 0.01 * drive, "torque {}", disc.torque);
+}
+
+#[test]
+fn neutrally_buoyant_disc_in_still_fluid_stays_put() {
+    let mut lattice = Lattice::new(50, 50, 0.8);
+    let mut particles = Particles::new(
+        &mut lattice,
+        vec![Particle::new(Shape::Disc(6.), 1., [24.3, 25.1])],
+    );
+
+    for _ in 0..500 {
+        lattice.compute_macroscopic();
+        lattice.collide();
+        particles.exchange_momentum(&lattice);
+        lattice.stream();
+        particles.advance(&mut lattice);
+    }
+
+    let disc = &particles.particles[0];
+
+    assert!((disc.center[0] - 24.3).abs() < 0.05, "{:?}", disc.center);
+    assert!((disc.center[1] - 25.1).abs() < 0.05, "{:?}", disc.center);
+    assert!(disc.angular_velocity.abs() < 1e-4);
+}
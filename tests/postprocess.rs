@@ -0,0 +1,120 @@
+//! Derived fields of flows whose gradients are known exactly. The flows are
+//! linear, so the one-sided differences on the edges are exact as well.
+
+use lbm::field::{ScalarField, VectorField};
+use lbm::postprocess::{self, Derived, DERIVED};
+
+/// Samples `u(x, y)` on a grid with unequal spacings along x and y.
+fn sampled<F: Fn(f32, f32) -> [f32; 2]>(u: F) -> VectorField {
+    let mut field = VectorField::new(12, 9, [0.5, 2.]);
+
+    for j in 0..field.height {
+        for i in 0..field.width {
+            field.set(i, j, u(0.5 * i as f32, 2. * j as f32));
+        }
+    }
+
+    field
+}
+
+fn assert_everywhere(field: &VectorField, derived: Derived, expected: f32) {
+    for (k, &value) in derived.compute(field).data.iter().enumerate() {
+        assert!(
+            (value - expected).abs() < 1e-4,
+            "{} at sample {}: {} != {}",
+            derived.name(),
+            k,
+            value,
+            expected
+        );
+    }
+}
+
+#[test]
+fn rigid_rotation_has_twice_its_rate_as_vorticity() {
+    let omega = 0.3;
+    let field = sampled(|x, y| [-omega * (y - 8.), omega * (x - 3.)]);
+
+    assert_everywhere(&field, Derived::Vorticity, 2. * omega);
+    assert_everywhere(&field, Derived::Divergence, 0.);
+    assert_everywhere(&field, Derived::StrainRate, 0.);
+    assert_everywhere(&field, Derived::QCriterion, omega * omega);
+}
+
+#[test]
+fn simple_shear_balances_rotation_and_strain() {
+    let rate = 0.2;
+    let field = sampled(|_, y| [rate * y, 0.]);
+
+    assert_everywhere(&field, Derived::Vorticity, -rate);
+    assert_everywhere(&field, Derived::StrainRate, rate);
+    assert_everywhere(&field, Derived::QCriterion, 0.);
+}
+
+#[test]
+fn stream_function_of_a_uniform_flow() {
+    let (u, v) = (0.4, -0.1);
+    let field = sampled(|_, _| [u, v]);
+    let psi = postprocess::stream_function(&field);
+
+    for j in 0..psi.height {
+        for i in 0..psi.width {
+            let (x, y) = (0.5 * i as f32, 2. * j as f32);
+
+            assert!((psi.get(i, j) - (u * y - v * x)).abs() < 1e-4);
+        }
+    }
+}
+
+#[test]
+fn bilinear_sampling_is_exact_for_linear_fields() {
+    let field = sampled(|x, y| [1. + 2. * x - y, 0.5 * x + 0.25 * y]);
+
+    for &[x, y] in [[0.3, 0.7], [2.75, 9.1], [5.5, 16.], [0., 15.9]].iter() {
+        let s = field.sample([x, y]);
+
+        assert!((s[0] - (1. + 2. * x - y)).abs() < 1e-4, "{:?}", s);
+        assert!((s[1] - (0.5 * x + 0.25 * y)).abs() < 1e-4, "{:?}", s);
+    }
+}
+
+#[test]
+fn degenerate_fields_have_no_extent() {
+    assert_eq!(VectorField::new(0, 0, [1., 1.]).size(), [0., 0.]);
+    assert_eq!(VectorField::new(0, 3, [1., 2.]).size(), [0., 4.]);
+
+    // a single sample is the value everywhere
+    let mut point = VectorField::new(1, 1, [1., 1.]);
+    point.set(0, 0, [0.5, -1.]);
+
+    assert_eq!(point.size(), [0., 0.]);
+    assert_eq!(point.sample([3., -2.]), [0.5, -1.]);
+}
+
+#[test]
+fn derived_quantities_cycle_through_all_of_them() {
+    let mut d = DERIVED[0];
+
+    for expected in DERIVED.iter().skip(1).chain(DERIVED.iter().take(1)) {
+        d = d.next();
+        assert_eq!(d, *expected);
+    }
+
+    assert!(Derived::Vorticity.signed() && !Derived::Speed.signed());
+}
+
+#[test]
+fn scales_ignore_samples_that_are_not_finite() {
+    let mut field = ScalarField::new(4, 3, [1., 1.]);
+    field.data.iter_mut().for_each(|x| *x = f32::NAN);
+
+    assert_eq!(field.range(), None);
+    assert_eq!(field.max_abs(), 0.);
+
+    field.set(1, 1, -3.);
+    field.set(2, 0, 0.5);
+    field.set(3, 2, f32::INFINITY);
+
+    assert_eq!(field.range(), Some((-3., 0.5)));
+    assert_eq!(field.max_abs(), 3.);
+}
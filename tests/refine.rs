@@ -0,0 +1,84 @@
+//! Block refinement: mass across the level interfaces and consistency of the
+//! levels with each other.
+
+use lbm::d2q9::{equilibrium, Lattice};
+use lbm::refine::{self, Refinement};
+
+/// Periodic root lattice with a density bump in the middle and a shear wave
+/// across it, so that mass and momentum both cross the block edges.
+fn disturbed(width: usize, height: usize) -> Lattice {
+    let mut lattice = Lattice::new(width, height, 0.8);
+
+    for j in 0..height {
+        for i in 0..width {
+            let (x, y) = (i as f32 - width as f32 / 2., j as f32 - height as f32 / 2.);
+            let rho = 1. + 0.01 * (-(x * x + y * y) / 20.).exp();
+            let u = 0.02 * (2. * std::f32::consts::PI * j as f32 / height as f32).sin();
+
+            let n = lattice.index(i, j);
+            lattice.f[n] = equilibrium(rho, [u, 0.]);
+        }
+    }
+
+    lattice.compute_macroscopic();
+
+    lattice
+}
+
+#[test]
+fn closed_composite_grid_keeps_its_mass() {
+    for &nested in [false, true].iter() {
+        let mut lattice = disturbed(40, 30);
+        let mut refinement = Refinement::new();
+
+        let block = refinement.refine(&lattice, [10, 8, 30, 22]);
+
+        if nested {
+            block.refine([8, 6, 24, 20]);
+        }
+
+        let mass = refinement.mass(&lattice);
+
+        for t in 1..=500 {
+            refinement.step(&mut lattice);
+
+            let drift = refinement.mass(&lattice) / mass - 1.;
+
+            assert!(
+                drift.abs() < 5e-5,
+                "nested {}, step {}: drift {:e}",
+                nested,
+                t,
+                drift
+            );
+        }
+    }
+}
+
+#[test]
+fn uniform_flow_passes_through_the_blocks_unchanged() {
+    let u = [0.04, -0.01];
+    let mut lattice = Lattice::new(40, 30, 0.7);
+    lattice.fill(1., u);
+
+    let mut refinement = Refinement::new();
+    refinement
+        .refine(&lattice, [10, 8, 30, 22])
+        .refine([8, 6, 24, 20]);
+
+    for _ in 0..100 {
+        refinement.step(&mut lattice);
+    }
+
+    let fine = &refinement.blocks[0].children[0].lattice;
+
+    for level in [&lattice, &refinement.blocks[0].lattice, fine].iter() {
+        for (n, f) in level.f.iter().enumerate() {
+            for (a, b) in f.iter().zip(equilibrium(1., u).iter()) {
+                assert!((a - b).abs() < 1e-5, "node {}", n);
+            }
+        }
+    }
+
+    assert!((refine::fine_tau(0.7) - 0.9).abs() < 1e-6);
+}
@@ -0,0 +1,105 @@
+//! The counter-based generator: reproducible, and independent across counters
+//! and streams.
+
+use lbm::rng::CounterRng;
+
+const DRAWS: u64 = 10_000;
+
+/// Fraction of bit positions where `a` and `b` differ, averaged over the
+/// pairs.
+fn differing_bits(pairs: impl Iterator<Item = (u64, u64)>) -> f32 {
+    let (mut bits, mut n) = (0, 0);
+
+    for (a, b) in pairs {
+        bits += (a ^ b).count_ones();
+        n += 1;
+    }
+
+    bits as f32 / (64 * n) as f32
+}
+
+#[test]
+fn same_seed_and_counter_give_the_same_bits() {
+    let (a, b) = (CounterRng::new(39), CounterRng::new(39));
+
+    for c in 0..100 {
+        assert_eq!(a.bits([c, 2 * c, 3]), b.bits([c, 2 * c, 3]));
+        assert_eq!(a.stream(c).bits([1, 2, 3]), b.stream(c).bits([1, 2, 3]));
+    }
+
+    // nothing depends on the order of the draws
+    let forward: Vec<_> = (0..100).map(|c| a.uniform([c, 0, 0])).collect();
+    let backward: Vec<_> = (0..100).rev().map(|c| a.uniform([c, 0, 0])).collect();
+
+    assert!(forward.iter().eq(backward.iter().rev()));
+}
+
+#[test]
+fn every_counter_component_changes_every_bit_with_even_odds() {
+    let rng = CounterRng::new(39);
+
+    for k in 0..3 {
+        let pairs = (0..DRAWS).map(|c| {
+            let mut next = [c, 7, 11];
+            next[k] += 1;
+
+            (rng.bits([c, 7, 11]), rng.bits(next))
+        });
+
+        let p = differing_bits(pairs);
+        assert!((p - 0.5).abs() < 0.01, "component {}: {}", k, p);
+    }
+
+    // components are not interchangeable
+    assert_ne!(rng.bits([1, 2, 3]), rng.bits([3, 2, 1]));
+    assert_ne!(rng.bits([0, 0, 1]), rng.bits([0, 1, 0]));
+}
+
+#[test]
+fn seeds_and_streams_are_independent() {
+    let rng = CounterRng::new(39);
+    let counter = |c: u64| [c, 0, 0];
+
+    let seeds = (0..DRAWS).map(|c| (rng.bits(counter(c)), CounterRng::new(40).bits(counter(c))));
+    let streams = (0..DRAWS).map(|c| {
+        (
+            rng.stream(1).bits(counter(c)),
+            rng.stream(2).bits(counter(c)),
+        )
+    });
+    let parent = (0..DRAWS).map(|c| (rng.bits(counter(c)), rng.stream(0).bits(counter(c))));
+
+    for (what, p) in [
+        ("seeds", differing_bits(seeds)),
+        ("streams", differing_bits(streams)),
+        ("stream and parent", differing_bits(parent)),
+    ]
+    .iter()
+    {
+        assert!((p - 0.5).abs() < 0.01, "{}: {}", what, p);
+    }
+}
+
+#[test]
+fn uniform_and_below_are_uniform() {
+    let rng = CounterRng::new(39);
+    let mut histogram = [0; 10];
+    let mut sum = 0.;
+
+    for c in 0..DRAWS {
+        let x = rng.uniform([c, 1, 2]);
+        assert!((0. ..1.).contains(&x), "{}", x);
+        sum += x;
+
+        let k = rng.below([c, 3, 4], 10);
+        histogram[k] += 1;
+    }
+
+    let mean = sum / DRAWS as f32;
+    assert!((mean - 0.5).abs() < 0.01, "mean {}", mean);
+
+    // within five standard deviations of the expected thousand
+    for (k, &n) in histogram.iter().enumerate() {
+        assert!((n as f32 - 1000.).abs() < 150., "{} drawn {} times", k, n);
+    }
+}
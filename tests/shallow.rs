@@ -0,0 +1,144 @@
+//! Shallow-water model: still water over uneven and emerging beds, and a
+//! one-dimensional dam break.
+
+use lbm::d2q9::{Lattice, NodeType};
+use lbm::shallow::ShallowWater;
+
+const GRAVITY: f32 = 0.1;
+
+/// Closed tank of `width` by `height` nodes, walls included.
+fn tank(width: usize, height: usize) -> Lattice {
+    let mut lattice = Lattice::new(width, height, 0.7);
+
+    for j in 0..height {
+        for i in 0..width {
+            if i == 0 || j == 0 || i == width - 1 || j == height - 1 {
+                let n = lattice.index(i, j);
+                lattice.node_type[n] = NodeType::Boundary;
+            }
+        }
+    }
+
+    lattice
+}
+
+fn max_speed(lattice: &Lattice) -> f32 {
+    lattice
+        .u
+        .iter()
+        .map(|u| u[0].hypot(u[1]))
+        .fold(0., f32::max)
+}
+
+/// Still water with its surface at `level` over a bed of two bumps, the
+/// second of which rises `emerged` above the surface.
+fn lake(level: f32, emerged: f32) -> (Lattice, ShallowWater) {
+    let mut lattice = tank(40, 30);
+    let mut water = ShallowWater::new(&lattice, GRAVITY);
+
+    for j in 0..lattice.height {
+        for i in 0..lattice.width {
+            let bump = |x: f32, y: f32, r: f32| {
+                let d2 = (i as f32 - x).powi(2) + (j as f32 - y).powi(2);
+                (1. - d2 / (r * r)).max(0.)
+            };
+
+            water.bed[lattice.index(i, j)] =
+                0.5 * level * bump(10., 12., 6.) + (level + emerged) * bump(27., 16., 5.);
+        }
+    }
+
+    water.fill(&mut lattice, level);
+
+    (lattice, water)
+}
+
+#[test]
+fn lake_at_rest_stays_at_rest_over_a_bumpy_bed() {
+    let (mut lattice, mut water) = lake(1., -0.5);
+    let volume = water.volume(&lattice);
+
+    for _ in 0..500 {
+        water.step(&mut lattice);
+    }
+
+    water.compute_macroscopic(&mut lattice);
+
+    assert!(max_speed(&lattice) < 1e-5, "{}", max_speed(&lattice));
+    assert!((water.volume(&lattice) - volume).abs() < 1e-4 * volume);
+
+    for n in 0..lattice.f.len() {
+        if !lattice.node_type[n].is_solid() {
+            let surface = water.bed[n] + lattice.rho[n];
+            assert!((surface - 1.).abs() < 1e-4, "node {}: {}", n, surface);
+        }
+    }
+}
+
+#[test]
+fn lake_at_rest_stays_at_rest_around_an_island() {
+    let (mut lattice, mut water) = lake(1., 0.3);
+    let volume = water.volume(&lattice);
+
+    let dry = (0..lattice.f.len())
+        .filter(|&n| lattice.rho[n] == 0. && !lattice.node_type[n].is_solid())
+        .count();
+
+    assert!(dry > 0);
+
+    for _ in 0..500 {
+        water.step(&mut lattice);
+    }
+
+    water.compute_macroscopic(&mut lattice);
+
+    assert!(max_speed(&lattice) < 1e-5, "{}", max_speed(&lattice));
+    assert!((water.volume(&lattice) - volume).abs() < 1e-4 * volume);
+}
+
+#[test]
+fn dam_break_follows_stokers_solution() {
+    // one-dimensional: walls at both ends, periodic across
+    let (width, dam) = (120, 40);
+    let mut lattice = Lattice::new(width, 3, 0.7);
+
+    for j in 0..lattice.height {
+        for &i in [0, width - 1].iter() {
+            let n = lattice.index(i, j);
+            lattice.node_type[n] = NodeType::Boundary;
+        }
+    }
+
+    let mut water = ShallowWater::new(&lattice, GRAVITY);
+
+    for n in 0..lattice.f.len() {
+        let h = if n % width < dam { 1. } else { 0.3 };
+        water.set(&mut lattice, n, h, [0., 0.]);
+    }
+
+    let volume = water.volume(&lattice);
+    let steps = 150;
+
+    for _ in 0..steps {
+        water.step(&mut lattice);
+    }
+
+    assert!((water.volume(&lattice) - volume).abs() < 1e-4 * volume);
+
+    // between the rarefaction and the bore the depth is h_m = 0.5914, and
+    // the bore runs at 0.2964 into the tail water (Stoker 1957)
+    let depth = |i: usize| lattice.rho[lattice.index(i, 1)];
+    let bore = dam as f32 + 0.2964 * steps as f32;
+
+    for i in 50..75 {
+        assert!(
+            (depth(i) - 0.5914).abs() < 0.01,
+            "plateau at {}: {}",
+            i,
+            depth(i)
+        );
+    }
+
+    assert!(depth(bore as usize - 5) > 0.55);
+    assert!(depth(bore as usize + 5) < 0.31);
+}
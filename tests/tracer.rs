@@ -0,0 +1,109 @@
+//! Tracer advection, emitter timing and the trajectory history.
+
+use lbm::field::VectorField;
+use lbm::tracer::{Emitter, Tracers};
+
+fn uniform(u: [f32; 2]) -> VectorField {
+    let mut field = VectorField::new(11, 11, [1., 1.]);
+    field.data.iter_mut().for_each(|v| *v = u);
+
+    field
+}
+
+#[test]
+fn massless_tracers_follow_the_flow_and_leave_with_it() {
+    let field = uniform([0.5, -0.25]);
+    let mut tracers = Tracers::new();
+
+    tracers.seed([1., 8.], [0., 0.]);
+
+    for _ in 0..10 {
+        tracers.advance(&field, 1.);
+    }
+
+    let pos = tracers.tracers[0].pos;
+
+    assert!((pos[0] - 6.).abs() < 1e-5 && (pos[1] - 5.5).abs() < 1e-5);
+
+    for _ in 0..10 {
+        tracers.advance(&field, 1.);
+    }
+
+    assert!(tracers.tracers.is_empty());
+    assert_eq!(tracers.finished.len(), 1);
+}
+
+#[test]
+fn inertial_tracers_relax_to_the_flow() {
+    let field = uniform([0.1, 0.]);
+    let mut tracers = Tracers::new();
+
+    tracers.response_time = 2.;
+    tracers.seed([1., 5.], [0., 0.]);
+
+    tracers.advance(&field, 1.);
+    let early = tracers.tracers[0].vel[0];
+
+    for _ in 0..20 {
+        tracers.advance(&field, 1.);
+    }
+
+    assert!(early > 0. && early < 0.05, "{}", early);
+    assert!((tracers.tracers[0].vel[0] - 0.1).abs() < 1e-4);
+}
+
+/// Number of tracers released at height `y`, which none of them has left.
+fn released(tracers: &Tracers, y: f32) -> usize {
+    tracers.tracers.iter().filter(|t| t.pos[1] == y).count()
+}
+
+#[test]
+fn every_emitter_keeps_its_own_interval() {
+    let field = uniform([0., 0.]);
+    let mut tracers = Tracers::new();
+
+    let line = |y: f32, count: usize, interval: f32| Emitter {
+        start: [2., y],
+        end: [8., y],
+        count,
+        interval,
+    };
+
+    tracers.emitters.push(line(2., 1, 1.));
+    tracers.emitters.push(line(5., 2, 5.));
+
+    for _ in 0..10 {
+        tracers.advance(&field, 1.);
+    }
+
+    // the fast one at t = 0..=9, the slow one only at t = 0 and 5
+    assert_eq!(released(&tracers, 2.), 10);
+    assert_eq!(released(&tracers, 5.), 4);
+
+    // a new emitter releases its first batch straight away
+    tracers.emitters.push(line(8., 3, 100.));
+    tracers.advance(&field, 1.);
+
+    assert_eq!(released(&tracers, 8.), 3);
+}
+
+#[test]
+fn trajectories_keep_only_the_latest_samples() {
+    let field = uniform([0.01, 0.]);
+    let mut tracers = Tracers::new();
+
+    tracers.history = 5;
+    tracers.trail_length = 3;
+    tracers.seed([1., 1.], [0., 0.]);
+
+    for _ in 0..20 {
+        tracers.advance(&field, 1.);
+    }
+
+    let tracer = &tracers.tracers[0];
+    let times: Vec<f32> = tracer.trajectory.iter().map(|s| s.0).collect();
+
+    assert_eq!(times, vec![16., 17., 18., 19., 20.]);
+    assert_eq!(tracers.trail(tracer).count(), 3);
+    assert_eq!(tracers.trail(tracer).last(), Some(tracer.pos));
+}
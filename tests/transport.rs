@@ -0,0 +1,71 @@
+//! Transport coefficients of the FHP models: the Boltzmann predictions and a
+//! sound pulse small enough to run with the tests.
+
+use lbm::fhp::Model;
+use lbm::rng::CounterRng;
+use lbm::transport::{self, Experiment};
+
+#[test]
+fn fhp_i_viscosity_is_the_textbook_one_when_dilute() {
+    // the dual collisions only matter once holes are as rare as particles
+    for &d in [0.005f32, 0.01, 0.02].iter() {
+        let textbook = 1. / (12. * d * (1. - d).powi(3)) - 0.125;
+        let nu = transport::viscosity(Model::FhpI, d);
+
+        assert!(
+            (nu / textbook - 1.).abs() < 1e-3,
+            "{}: {} != {}",
+            d,
+            nu,
+            textbook
+        );
+    }
+
+    // and make FHP-I symmetric between particles and holes
+    for &d in [0.1f32, 0.2, 0.35].iter() {
+        let (nu, dual) = (
+            transport::viscosity(Model::FhpI, d),
+            transport::viscosity(Model::FhpI, 1. - d),
+        );
+
+        assert!((nu / dual - 1.).abs() < 1e-4, "{}: {} != {}", d, nu, dual);
+    }
+}
+
+#[test]
+fn more_collisions_mean_less_viscosity() {
+    for &d in [0.1f32, 0.2, 0.3].iter() {
+        let nu = [Model::FhpI, Model::FhpII, Model::FhpIII]
+            .iter()
+            .map(|&model| transport::viscosity(model, d))
+            .collect::<Vec<_>>();
+
+        assert!(nu[0] > nu[1] && nu[1] > nu[2], "{}: {:?}", d, nu);
+        assert!(nu[2] > 0.);
+    }
+}
+
+#[test]
+fn rest_particles_slow_sound_down() {
+    assert!((transport::sound_speed(Model::FhpI) - 0.5f32.sqrt()).abs() < 1e-6);
+
+    for &model in [Model::FhpII, Model::FhpIII].iter() {
+        assert!((transport::sound_speed(model) - (3f32 / 7.).sqrt()).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn sound_pulse_runs_at_the_predicted_speed() {
+    let mut experiment = Experiment::new(Model::FhpI, 0.2, CounterRng::new(45));
+    experiment.width = 512;
+
+    let sound = experiment.sound_pulse();
+
+    assert!(
+        sound.error().abs() < 0.15,
+        "{} against {}",
+        sound.measured,
+        sound.theory
+    );
+    assert_eq!(sound.samples.len(), 512 / 4 / experiment.interval + 1);
+}
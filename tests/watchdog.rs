@@ -0,0 +1,110 @@
+//! The stability watchdog on lattices spoiled on purpose.
+
+use lbm::d2q9::{Lattice, NodeType};
+use lbm::watchdog::{Violation, Watchdog};
+
+#[test]
+fn healthy_lattices_and_steps_in_between_pass() {
+    let mut lattice = Lattice::new(20, 10, 0.6);
+    lattice.fill(1., [0.05, 0.]);
+
+    let watchdog = Watchdog::new(10);
+
+    assert!(watchdog.check(&lattice, 10).is_ok());
+
+    lattice.f[7][3] = f32::NAN;
+
+    assert!(watchdog.check(&lattice, 15).is_ok());
+    assert!(watchdog.check(&lattice, 20).is_err());
+}
+
+#[test]
+fn report_names_the_first_offending_node_and_its_neighbours() {
+    let mut lattice = Lattice::new(20, 10, 0.6);
+
+    let (a, b) = (lattice.index(3, 4), lattice.index(15, 6));
+    lattice.f[b][2] = f32::INFINITY;
+    lattice.f[a][5] = -0.01;
+
+    // walls are never inspected
+    let wall = lattice.index(1, 1);
+    lattice.node_type[wall] = NodeType::Boundary;
+    lattice.f[wall][0] = f32::NAN;
+
+    let report = Watchdog::new(1).check(&lattice, 7).unwrap_err();
+
+    assert_eq!((report.step, report.i, report.j), (7, 3, 4));
+    assert_eq!(
+        report.violation,
+        Violation::NegativePopulation { k: 5, value: -0.01 }
+    );
+
+    let mut around: Vec<_> = report.neighborhood.iter().map(|s| (s.i, s.j)).collect();
+    around.sort_unstable();
+
+    assert_eq!(
+        around,
+        vec![
+            (2, 3),
+            (2, 4),
+            (2, 5),
+            (3, 3),
+            (3, 4),
+            (3, 5),
+            (4, 3),
+            (4, 4),
+            (4, 5)
+        ]
+    );
+
+    lattice.f[a][5] = 0.01;
+
+    let report = Watchdog::new(1).check(&lattice, 8).unwrap_err();
+
+    assert_eq!((report.i, report.j), (15, 6));
+    assert_eq!(report.violation, Violation::NonFinite);
+}
+
+#[test]
+fn fast_flow_trips_the_mach_limit() {
+    let mut lattice = Lattice::new(8, 8, 0.6);
+    lattice.fill(1., [0.2, 0.]);
+    lattice.compute_macroscopic();
+
+    let mut watchdog = Watchdog::new(1);
+
+    match watchdog.check(&lattice, 1) {
+        Err(report) => match report.violation {
+            Violation::Mach(ma) => assert!((ma - 0.2 * 3f32.sqrt()).abs() < 1e-4),
+            v => panic!("{:?}", v),
+        },
+        Ok(()) => panic!("no report"),
+    }
+
+    watchdog.max_mach = 0.4;
+
+    assert!(watchdog.check(&lattice, 1).is_ok());
+}
+
+#[test]
+fn snapshot_holds_every_node() {
+    let mut lattice = Lattice::new(6, 5, 0.6);
+    lattice.f[12][0] = f32::NAN;
+
+    let path = std::env::temp_dir().join(format!("watchdog-{}.csv", std::process::id()));
+    let mut watchdog = Watchdog::new(1);
+    watchdog.snapshot = Some(path.clone());
+
+    let report = watchdog.check(&lattice, 1).unwrap_err();
+
+    assert_eq!(report.snapshot.as_ref(), Some(&path));
+
+    let csv = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let lines: Vec<_> = csv.lines().collect();
+
+    assert_eq!(lines.len(), 1 + 6 * 5);
+    assert_eq!(lines[0].split(',').count(), 6 + 9);
+    assert!(lines[1 + 12].starts_with("0,2,fluid,"));
+}